@@ -30,6 +30,7 @@ let
       fileset = unions [
         ./spike_rs
         ./dpi_common
+        ./sim_schema
         ./dpi_t1emu
         ./dpi_t1rocketemu
         ./t1-sim-checker
@@ -0,0 +1,551 @@
+//! AXI slave ports driven by the `axi_push_*`/`axi_pop_*` DPI calls of `AXI4SlaveAgent`
+//!
+//! The agent pushes address and data beats, and pops responses. `AxiSlave` keeps track of the
+//! transactions in between, the driver sends them to its memory and completes them on response.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::axi_monitor::AxiMonitor;
+
+// It has no payload,
+// code generates bus error shall log detailed error message.
+// TODO: may distinguish SLVERR and DECERR in the future?
+#[derive(Debug, Clone)]
+pub struct BusError;
+
+/// A request on the AXI AW or AR channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AxiAddr {
+  pub id: u64,
+  pub addr: u64,
+  pub size: u64,
+  pub len: u64,
+  pub user: u64,
+}
+
+/// A response on the AXI B channel
+#[derive(Debug, Serialize)]
+pub struct AxiB {
+  pub id: u16,
+  pub user: u32,
+}
+
+/// A beat on the AXI R channel
+#[derive(Debug, Serialize)]
+pub struct AxiR {
+  pub last: bool,
+  pub id: u16,
+  pub user: u32,
+  /// a whole beat of the bus, only the bytes of the transfer are valid
+  #[serde(with = "hex::serde")]
+  pub data: Vec<u8>,
+}
+
+// Packed result buffer:
+// ret[0]: valid
+// ret[2..3]: BID
+// ret[4..8]: BUSER
+#[repr(C)]
+pub struct RetAxiPopB {
+  pub bvalid: u8,
+  pub _padding_0: u8,
+  pub bid: u16,
+  pub buser: u32,
+}
+
+// Packed result buffer:
+// ret[0]: valid
+// ret[1]: rlast
+// ret[2..3]: rid
+// ret[4..8]: ruser (in 32 bit)
+// ret[8..]: rdata
+#[repr(C)]
+pub struct RetAxiPopR {
+  pub rvalid: u8,
+  pub rlast: u8,
+  pub rid: u16,
+  pub ruser: u32,
+  pub rdata: [u8; 1024 / 8],
+}
+
+/// Wstrb iterator
+pub struct StrbIterator<'a> {
+  pub strb: &'a [u32],
+  pub total_width: usize,
+  pub current: usize,
+}
+
+impl Iterator for StrbIterator<'_> {
+  type Item = bool;
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.total_width == self.current {
+      return None;
+    }
+    assert!(self.total_width > self.current);
+
+    let slot = self.current / 32;
+    let bit = self.current % 32;
+    // The wstrb are transfered in small endian
+    let extracted = (self.strb[slot] >> bit & 1) != 0;
+    self.current += 1;
+    Some(extracted)
+  }
+}
+
+/// (start address, transfer size, bus size) of the INCR burst at `addr` served by the slave.
+///
+/// Protocol violations are reported by `AxiMonitor`, the slave serves what it can:
+/// - an unaligned burst starts at the aligned address, the bytes of the first beat below `addr`
+///   are masked by the strobes of a write, and ignored by the master on a read
+/// - a transfer wider than the bus is served as a full width one
+/// - a burst crossing a 4KiB boundary is served as a contiguous range, if the memory has it
+fn served_range(addr: u64, axsize: u64, data_width: u64) -> (u64, u64, u64) {
+  let bus_size = data_width / 8;
+  let size = (1 << axsize).min(bus_size);
+  (addr / size * size, size, bus_size)
+}
+
+/// An incomplete memory write
+/// Keeps track of both the data filling and the request into
+/// the memory itself
+#[derive(Debug)]
+pub struct IncompleteWrite {
+  id: u64,
+  addr: u64,
+  bursts: usize,
+  width: usize, // In bytes
+  user: u64,
+
+  /// Is this request already sent to the memory?
+  sent: bool,
+  /// Is this request processed by the memory?
+  done: Option<Result<(), BusError>>,
+
+  data: Vec<u8>,
+  strb: Vec<bool>,
+
+  // Used for transfers
+  bus_size: usize,
+}
+
+impl IncompleteWrite {
+  pub fn new(
+    awid: u64,
+    awaddr: u64,
+    awlen: u64,
+    awsize: u64,
+    awuser: u64,
+    data_width: u64,
+  ) -> IncompleteWrite {
+    let (addr, size, bus_size) = served_range(awaddr, awsize, data_width);
+
+    let tsize = (size * (awlen + 1)) as usize;
+    let data = Vec::with_capacity(tsize);
+    let strb = Vec::with_capacity(tsize);
+
+    IncompleteWrite {
+      id: awid,
+      addr,
+      bursts: awlen as usize + 1,
+      width: size as usize,
+      user: awuser,
+
+      sent: false,
+      done: None,
+      data,
+      strb,
+      bus_size: bus_size as usize,
+    }
+  }
+
+  /// Add an AXI W channel beat
+  pub fn push(&mut self, wdata: &[u8], wstrb: impl Iterator<Item = bool>) {
+    assert_eq!(
+      wdata.len(),
+      self.bus_size,
+      "Mismatch data width across DPI calls"
+    );
+
+    let next_addr = self.addr as usize + self.data.len();
+    let wire_offset = next_addr % self.bus_size;
+    assert!(
+      wire_offset + self.width <= self.bus_size,
+      "Sanity check for data width: IncompleteWrite::push"
+    );
+
+    self.data.extend(&wdata[wire_offset..wire_offset + self.width]);
+    self.strb.extend(wstrb.skip(wire_offset).take(self.width));
+
+    assert_eq!(self.data.len(), self.strb.len());
+    assert!(self.data.len() <= self.bursts * self.width);
+  }
+
+  /// Ready to send to mem
+  pub fn ready(&self) -> bool {
+    self.data.len() == self.bursts * self.width
+  }
+
+  pub fn complete_ok(&mut self) {
+    assert!(self.done.is_none(), "Write transaction is already done");
+    self.done = Some(Ok(()));
+  }
+
+  pub fn complete_with_error(&mut self, e: BusError) {
+    assert!(self.done.is_none(), "Write transaction is already done");
+    self.done = Some(Err(e));
+  }
+
+  pub fn done(&self) -> bool {
+    self.done.is_some()
+  }
+
+  pub fn resp(&self) -> Result<(), BusError> {
+    self.done.as_ref().expect("Write transanction is not done").clone()
+  }
+
+  pub fn id(&self) -> u64 {
+    self.id
+  }
+
+  pub fn user(&self) -> u64 {
+    self.user
+  }
+
+  pub fn addr(&self) -> u64 {
+    self.addr
+  }
+
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Bytes of the whole transaction
+  pub fn total_size(&self) -> usize {
+    self.bursts * self.width
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  pub fn strb(&self) -> &[bool] {
+    &self.strb
+  }
+}
+
+/// An incomplete memory read
+/// Keeps track of both the data draining and the request into
+/// the memory itself
+#[derive(Debug)]
+pub struct IncompleteRead {
+  addr: u64,
+  bursts: usize,
+  width: usize,
+  user: u64,
+
+  /// Is this request sent to memory?
+  sent: bool,
+  /// The number of bytes already returned to the RTL
+  returned: usize,
+  /// The fetched data. None if the response has not arrived yet
+  data: Option<Result<Vec<u8>, BusError>>,
+
+  // Used for transfers
+  bus_size: usize,
+}
+
+impl IncompleteRead {
+  pub fn new(araddr: u64, arlen: u64, arsize: u64, aruser: u64, data_width: u64) -> IncompleteRead {
+    let (addr, size, bus_size) = served_range(araddr, arsize, data_width);
+
+    IncompleteRead {
+      addr,
+      bursts: arlen as usize + 1,
+      width: size as usize,
+      user: aruser,
+
+      sent: false,
+      returned: 0,
+      data: None,
+
+      bus_size: bus_size as usize,
+    }
+  }
+
+  /// Drain one beat into the AXI R channel
+  ///
+  /// Returns true if this is the last beat in the response (rlast)
+  pub fn pop(&mut self, rdata_buf: &mut [u8]) -> (Result<(), BusError>, bool) {
+    assert_eq!(
+      rdata_buf.len(),
+      self.bus_size,
+      "Mismatch data width across DPI calls"
+    );
+
+    assert!(
+      self.returned < self.bursts * self.width,
+      "All data already taken by RTL"
+    );
+
+    let data = self
+      .data
+      .as_ref()
+      .expect("IncompleteRead::pop called on request that hasn't gotten its data!");
+
+    let resp = match data {
+      Err(e) => Err(e.clone()),
+      Ok(data) => {
+        // Find in-line offset
+        let result_offset = (self.addr as usize + self.returned) % self.bus_size;
+
+        let dst = &mut rdata_buf[result_offset..(result_offset + self.width)];
+        let src = &data[self.returned..(self.returned + self.width)];
+        dst.copy_from_slice(src);
+        Ok(())
+      }
+    };
+
+    self.returned += self.width;
+    assert!(self.returned <= self.bursts * self.width);
+
+    let last = self.returned == self.bursts * self.width;
+    (resp, last)
+  }
+
+  pub fn complete_with_data(&mut self, data: Vec<u8>) {
+    assert!(self.data.is_none(), "Read transaction is already done");
+    assert_eq!(
+      data.len(),
+      self.bursts * self.width,
+      "Transaction data width mismatch"
+    );
+
+    self.data = Some(Ok(data));
+  }
+
+  pub fn complete_with_error(&mut self, e: BusError) {
+    assert!(self.data.is_none(), "Read transaction is already done");
+    self.data = Some(Err(e));
+  }
+
+  pub fn done(&self) -> bool {
+    self.data.is_some()
+  }
+
+  pub fn user(&self) -> u64 {
+    self.user
+  }
+
+  pub fn addr(&self) -> u64 {
+    self.addr
+  }
+
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  /// Bytes of the whole transaction
+  pub fn total_size(&self) -> usize {
+    self.bursts * self.width
+  }
+}
+
+// [16 bit is_read][ 16 bit cid ][ 32 bit id ]
+fn mem_id(channel_id: u64, id: u64, is_write: bool) -> u64 {
+  (!is_write as u64) << 48 | channel_id << 32 | id
+}
+
+/// (channel_id, id, is_write) of the memory request id given to `send_writes` and `send_reads`
+pub fn split_mem_id(mem_id: u64) -> (u64, u64, bool) {
+  (
+    mem_id >> 32 & 0xFFFF,
+    mem_id & 0xFFFFFFFF,
+    mem_id >> 48 == 0,
+  )
+}
+
+/// Inflight transactions of the AXI slave ports
+pub struct AxiSlave {
+  /// channel_id -> writes
+  writes: BTreeMap<u64, VecDeque<IncompleteWrite>>,
+  /// (channel_id, id) -> reads
+  reads: BTreeMap<(u64, u64), VecDeque<IncompleteRead>>,
+
+  monitor: AxiMonitor,
+}
+
+impl AxiSlave {
+  pub fn new(monitor: AxiMonitor) -> Self {
+    AxiSlave {
+      writes: BTreeMap::new(),
+      reads: BTreeMap::new(),
+      monitor,
+    }
+  }
+
+  pub fn monitor(&self) -> &AxiMonitor {
+    &self.monitor
+  }
+
+  pub fn push_aw(&mut self, channel_id: u64, data_width: u64, aw: &AxiAddr) {
+    self.monitor.on_aw(channel_id, data_width, aw);
+    let w = IncompleteWrite::new(aw.id, aw.addr, aw.len, aw.size, aw.user, data_width);
+    let fifo = self.writes.entry(channel_id).or_default();
+    fifo.push_back(w);
+    debug!(
+      "Write initialized: channel_id={} id={} at=0x{:x}",
+      channel_id, aw.id, aw.addr
+    );
+  }
+
+  pub fn push_ar(&mut self, channel_id: u64, data_width: u64, ar: &AxiAddr) {
+    self.monitor.on_ar(channel_id, data_width, ar);
+    let r = IncompleteRead::new(ar.addr, ar.len, ar.size, ar.user, data_width);
+    let fifo = self.reads.entry((channel_id, ar.id)).or_default();
+    fifo.push_back(r);
+    debug!(
+      "Read initialized: channel_id={} id={} at=0x{:x}",
+      channel_id, ar.id, ar.addr
+    );
+  }
+
+  pub fn push_w(&mut self, channel_id: u64, wdata: &[u8], wstrb: &[bool], wlast: bool) {
+    self.monitor.on_w(channel_id, wstrb, wlast);
+    // a beat without a pending AW is reported by the monitor, and dropped
+    // TODO: allow same-cycle AW/W (when W is sequenced before AW)
+    let Some(w) =
+      self.writes.get_mut(&channel_id).and_then(|channel| channel.iter_mut().find(|w| !w.ready()))
+    else {
+      debug!("W beat without a pending write dropped: channel_id={channel_id}");
+      return;
+    };
+    w.push(wdata, wstrb.iter().copied());
+    if w.ready() {
+      debug!(
+        "Write fully sequenced: channel_id={} id={}",
+        channel_id,
+        w.id()
+      );
+    }
+  }
+
+  pub fn pop_b(&mut self, channel_id: u64) -> Option<AxiB> {
+    let fifo = self.writes.get_mut(&channel_id)?;
+    // TODO: find later writes with different IDs
+    if fifo.front().is_none_or(|w| !w.done()) {
+      return None;
+    }
+    let w = fifo.pop_front().unwrap();
+    match w.resp() {
+      Ok(()) => {}
+      Err(BusError) => panic!(
+        "SIM ERROR: write bus error, cid={cid}, id={id}, addr=0x{addr:08x}, width={width}",
+        cid = channel_id,
+        id = w.id(),
+        addr = w.addr(),
+        width = w.width(),
+      ),
+    }
+    debug!("Write finalized: channel_id={} id={}", channel_id, w.id());
+
+    let b = AxiB { id: w.id() as u16, user: w.user() as u32 };
+    self.monitor.on_b(channel_id, &b);
+    Some(b)
+  }
+
+  pub fn pop_r(&mut self, channel_id: u64, data_width: u64) -> Option<AxiR> {
+    let beat = self.pop_r_beat(channel_id, data_width)?;
+    self.monitor.on_r(channel_id, &beat);
+    Some(beat)
+  }
+
+  fn pop_r_beat(&mut self, channel_id: u64, data_width: u64) -> Option<AxiR> {
+    for (&(cid, id), fifo) in self.reads.range_mut((channel_id, 0)..=(channel_id, u64::MAX)) {
+      if let Some(r) = fifo.front_mut()
+        && r.done()
+      {
+        let mut data = vec![0; data_width as usize / 8];
+        let (resp, last) = r.pop(&mut data);
+        match resp {
+          Ok(()) => {}
+          Err(BusError) => panic!(
+            "SIM ERROR: read bus error, cid={cid}, id={id}, addr=0x{addr:08x}, width={width}",
+            addr = r.addr(),
+            width = r.width()
+          ),
+        }
+        debug!(
+          "Read data: channel_id={} id={} content={:?}",
+          channel_id, id, data,
+        );
+
+        let beat = AxiR { last, id: id as u16, user: r.user() as u32, data };
+
+        if last {
+          debug!("Read finalized: channel_id={} id={}", channel_id, id);
+          fifo.pop_front();
+        }
+        return Some(beat);
+      }
+    }
+    None
+  }
+
+  /// Send the first unsent write of each channel to the memory, once all its data arrives.
+  /// `send` gets the id of the memory request, and returns whether the memory accepts it.
+  pub fn send_writes(&mut self, mut send: impl FnMut(u64, &IncompleteWrite) -> bool) {
+    for (&cid, fifo) in self.writes.iter_mut() {
+      // Always handled in-order, find first pending
+      let Some(w) = fifo.iter_mut().find(|w| !w.sent) else {
+        continue;
+      };
+      if w.ready() && send(mem_id(cid, w.id, true), w) {
+        w.sent = true;
+      }
+    }
+  }
+
+  /// Send the first unsent read of each channel and ID to the memory, see `send_writes`
+  pub fn send_reads(&mut self, mut send: impl FnMut(u64, &IncompleteRead) -> bool) {
+    for (&(cid, id), fifo) in self.reads.iter_mut() {
+      let Some(r) = fifo.iter_mut().find(|r| !r.sent) else {
+        continue;
+      };
+      if send(mem_id(cid, id, false), r) {
+        r.sent = true;
+      }
+    }
+  }
+
+  /// Complete the write of the memory request `mem_id`
+  pub fn complete_write(&mut self, mem_id: u64, resp: Result<(), BusError>) {
+    let (cid, id, _) = split_mem_id(mem_id);
+    let w = self
+      .writes
+      .get_mut(&cid)
+      .and_then(|f| f.iter_mut().find(|w| w.id == id && !w.done()))
+      .expect("Returned write has no corresponding pending data");
+    match resp {
+      Ok(()) => w.complete_ok(),
+      Err(e) => w.complete_with_error(e),
+    }
+  }
+
+  /// Complete the read of the memory request `mem_id`
+  pub fn complete_read(&mut self, mem_id: u64, resp: Result<Vec<u8>, BusError>) {
+    let (cid, id, _) = split_mem_id(mem_id);
+    let r = self
+      .reads
+      .get_mut(&(cid, id))
+      .and_then(|f| f.iter_mut().find(|r| !r.done()))
+      .expect("Returned read has no corresponding pending data");
+    assert!(r.sent);
+    match resp {
+      Ok(data) => r.complete_with_data(data),
+      Err(e) => r.complete_with_error(e),
+    }
+  }
+}
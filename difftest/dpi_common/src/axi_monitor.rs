@@ -0,0 +1,380 @@
+//! AXI4 protocol monitor of the `AxiSlave` ports
+//!
+//! The monitor follows the transactions of each channel in the order the agent pushes them,
+//! independently of the slave serving them, and reports protocol violations of both sides:
+//! 4KiB boundary, burst length, transfer size, outstanding transactions, WLAST and RLAST
+//! placement, write strobes outside the byte lanes of the beat, and responses out of order.
+//! All bursts are INCR, their start address may be unaligned.
+//! Depending on `+t1_axi_monitor`, a violation is logged as a warning or aborts the simulation,
+//! with `warn` and `off` the slave serves the violating transactions as it can.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::axi::{AxiAddr, AxiB, AxiR};
+use crate::plusarg::{PlusArgDef, PlusArgs};
+
+/// Plusargs read by `AxiMonitorConfig::from_plusargs`, to be declared by emulators with AXI ports
+pub const AXI_MONITOR_PLUSARGS: [PlusArgDef; 2] = [
+  PlusArgDef::enumeration(
+    "t1_axi_monitor",
+    &["off", "warn", "fatal"],
+    "action on AXI protocol violations",
+  )
+  .default("fatal"),
+  PlusArgDef::int(
+    "t1_axi_max_outstanding",
+    "max outstanding reads or writes of an AXI port, unlimited if absent",
+  ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitorMode {
+  /// don't check
+  Off,
+  /// log violations and continue
+  Warn,
+  /// panic on the first violation
+  Fatal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AxiMonitorConfig {
+  pub mode: MonitorMode,
+  pub max_outstanding: Option<u64>,
+}
+
+impl Default for AxiMonitorConfig {
+  fn default() -> Self {
+    AxiMonitorConfig { mode: MonitorMode::Fatal, max_outstanding: None }
+  }
+}
+
+impl AxiMonitorConfig {
+  pub fn from_plusargs(plusargs: &PlusArgs) -> Self {
+    let mode = match plusargs.get_str("t1_axi_monitor") {
+      Some("off") => MonitorMode::Off,
+      Some("warn") => MonitorMode::Warn,
+      _ => MonitorMode::Fatal,
+    };
+    AxiMonitorConfig {
+      mode,
+      max_outstanding: plusargs.get_int("t1_axi_max_outstanding"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AxiChannel {
+  AW,
+  W,
+  B,
+  AR,
+  R,
+}
+
+/// A transaction followed by the monitor
+struct Transaction {
+  addr: AxiAddr,
+  /// in bytes
+  bus_size: u64,
+  /// cycle of the AW or AR request
+  cycle: u64,
+  /// W beats received, or R beats returned
+  beats: u64,
+}
+
+impl Transaction {
+  fn size(&self) -> u64 {
+    1 << self.addr.size
+  }
+
+  fn bursts(&self) -> u64 {
+    self.addr.len + 1
+  }
+
+  // start address aligned to the transfer size
+  fn aligned(&self) -> u64 {
+    self.addr.addr / self.size() * self.size()
+  }
+
+  // the byte lanes of the beat `n` of an INCR burst
+  fn lanes(&self, n: u64) -> (u64, u64) {
+    let size = self.size();
+    let start = if n == 0 {
+      self.addr.addr
+    } else {
+      self.aligned() + n * size
+    };
+    let lower = start % self.bus_size;
+    (lower, lower - start % size + size)
+  }
+}
+
+impl fmt::Display for Transaction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "addr={:#x} size={}B len={} user={:#x} beats={}/{} issued at cycle {}",
+      self.addr.addr,
+      self.size(),
+      self.addr.len,
+      self.addr.user,
+      self.beats,
+      self.bursts(),
+      self.cycle
+    )
+  }
+}
+
+pub struct AxiMonitor {
+  config: AxiMonitorConfig,
+  get_t: fn() -> u64,
+  /// channel_id -> writes in AW order
+  writes: BTreeMap<u64, VecDeque<Transaction>>,
+  /// channel_id -> reads in AR order
+  reads: BTreeMap<u64, VecDeque<Transaction>>,
+  violations: u64,
+}
+
+impl AxiMonitor {
+  /// `get_t` gives the cycle reported with violations
+  pub fn new(config: AxiMonitorConfig, get_t: fn() -> u64) -> Self {
+    AxiMonitor {
+      config,
+      get_t,
+      writes: BTreeMap::new(),
+      reads: BTreeMap::new(),
+      violations: 0,
+    }
+  }
+
+  /// Number of violations reported so far
+  pub fn violations(&self) -> u64 {
+    self.violations
+  }
+
+  pub fn on_aw(&mut self, channel_id: u64, data_width: u64, aw: &AxiAddr) {
+    if self.config.mode == MonitorMode::Off {
+      return;
+    }
+    let txn = Transaction {
+      addr: aw.clone(),
+      bus_size: data_width / 8,
+      cycle: (self.get_t)(),
+      beats: 0,
+    };
+    let outstanding = self.writes.get(&channel_id).map_or(0, VecDeque::len);
+    self.check_request(AxiChannel::AW, channel_id, &txn, outstanding);
+    self.writes.entry(channel_id).or_default().push_back(txn);
+  }
+
+  pub fn on_ar(&mut self, channel_id: u64, data_width: u64, ar: &AxiAddr) {
+    if self.config.mode == MonitorMode::Off {
+      return;
+    }
+    let txn = Transaction {
+      addr: ar.clone(),
+      bus_size: data_width / 8,
+      cycle: (self.get_t)(),
+      beats: 0,
+    };
+    let outstanding = self.reads.get(&channel_id).map_or(0, VecDeque::len);
+    self.check_request(AxiChannel::AR, channel_id, &txn, outstanding);
+    self.reads.entry(channel_id).or_default().push_back(txn);
+  }
+
+  pub fn on_w(&mut self, channel_id: u64, wstrb: &[bool], wlast: bool) {
+    if self.config.mode == MonitorMode::Off {
+      return;
+    }
+    // write data are in the order of AW, without interleaving
+    let Some(txn) = self
+      .writes
+      .get_mut(&channel_id)
+      .and_then(|fifo| fifo.iter_mut().find(|txn| txn.beats < txn.bursts()))
+    else {
+      self.report(
+        AxiChannel::W,
+        channel_id,
+        None,
+        "W beat without a pending AW",
+      );
+      return;
+    };
+
+    let beat = txn.beats;
+    txn.beats += 1;
+    let mut errors = vec![];
+    if wstrb.len() as u64 != txn.bus_size {
+      errors.push(format!(
+        "{} strobe bits on a {}B bus",
+        wstrb.len(),
+        txn.bus_size
+      ));
+    } else {
+      let (lower, upper) = txn.lanes(beat);
+      let outside: Vec<u64> = (0..txn.bus_size)
+        .filter(|&lane| wstrb[lane as usize] && !(lower..upper).contains(&lane))
+        .collect();
+      if !outside.is_empty() {
+        errors.push(format!(
+          "strobe of the byte lanes {outside:?} outside the lanes {lower}..{upper} of beat {beat}"
+        ));
+      }
+    }
+    let last = beat + 1 == txn.bursts();
+    if wlast != last {
+      errors.push(format!(
+        "WLAST={} on beat {beat} of a {}-beat burst",
+        wlast as u8,
+        txn.bursts()
+      ));
+    }
+
+    let desc = format!("id={} {txn}", txn.addr.id);
+    for error in errors {
+      self.report(AxiChannel::W, channel_id, Some(&desc), &error);
+    }
+  }
+
+  pub fn on_b(&mut self, channel_id: u64, b: &AxiB) {
+    if self.config.mode == MonitorMode::Off {
+      return;
+    }
+    let id = b.id;
+    let fifo = self.writes.entry(channel_id).or_default();
+    let Some(index) = fifo.iter().position(|txn| txn.addr.id as u16 == id) else {
+      self.report(
+        AxiChannel::B,
+        channel_id,
+        None,
+        &format!("B of id={id} without an outstanding write"),
+      );
+      return;
+    };
+    let txn = fifo.remove(index).unwrap();
+
+    let desc = format!("id={id} {txn}");
+    if txn.beats < txn.bursts() {
+      self.report(
+        AxiChannel::B,
+        channel_id,
+        Some(&desc),
+        "B before the last W beat",
+      );
+    }
+    if b.user != txn.addr.user as u32 {
+      self.report(
+        AxiChannel::B,
+        channel_id,
+        Some(&desc),
+        &format!(
+          "BUSER={:#x} of another write, responses of the same ID must be in order",
+          b.user
+        ),
+      );
+    }
+  }
+
+  pub fn on_r(&mut self, channel_id: u64, r: &AxiR) {
+    if self.config.mode == MonitorMode::Off {
+      return;
+    }
+    let id = r.id;
+    let fifo = self.reads.entry(channel_id).or_default();
+    let Some(index) = fifo.iter().position(|txn| txn.addr.id as u16 == id) else {
+      self.report(
+        AxiChannel::R,
+        channel_id,
+        None,
+        &format!("R of id={id} without an outstanding read"),
+      );
+      return;
+    };
+    let txn = &mut fifo[index];
+    let beat = txn.beats;
+    txn.beats += 1;
+    let last = txn.beats == txn.bursts();
+
+    let desc = format!("id={id} {txn}");
+    let user_mismatch = r.user != txn.addr.user as u32;
+    if last {
+      fifo.remove(index);
+    }
+
+    if r.last != last {
+      self.report(
+        AxiChannel::R,
+        channel_id,
+        Some(&desc),
+        &format!("RLAST={} on beat {beat}", r.last as u8),
+      );
+    }
+    if user_mismatch {
+      self.report(
+        AxiChannel::R,
+        channel_id,
+        Some(&desc),
+        &format!(
+          "RUSER={:#x} of another read, responses of the same ID must be in order",
+          r.user
+        ),
+      );
+    }
+  }
+
+  fn check_request(
+    &mut self,
+    channel: AxiChannel,
+    channel_id: u64,
+    txn: &Transaction,
+    outstanding: usize,
+  ) {
+    let mut errors = vec![];
+    let size = txn.size();
+    if size > txn.bus_size {
+      errors.push(format!(
+        "transfer size wider than the {}B bus",
+        txn.bus_size
+      ));
+    }
+    if txn.addr.len > 255 {
+      errors.push("burst longer than 256 beats".to_string());
+    }
+    if txn.aligned() % 4096 + size * txn.bursts() > 4096 {
+      errors.push("burst crossing a 4KiB boundary".to_string());
+    }
+    if let Some(max) = self.config.max_outstanding
+      && outstanding as u64 >= max
+    {
+      errors.push(format!("more than {max} outstanding transactions"));
+    }
+
+    let desc = format!("id={} {txn}", txn.addr.id);
+    for error in errors {
+      self.report(channel, channel_id, Some(&desc), &error);
+    }
+  }
+
+  #[track_caller]
+  fn report(&mut self, channel: AxiChannel, channel_id: u64, txn: Option<&str>, error: &str) {
+    self.violations += 1;
+    let cycle = (self.get_t)();
+    let txn = txn.map(|txn| format!(" ({txn})")).unwrap_or_default();
+    let message =
+      format!("[{cycle}] AXI violation on channel {channel_id} {channel:?}: {error}{txn}");
+    match self.config.mode {
+      MonitorMode::Off => {}
+      MonitorMode::Warn => warn!("{message}"),
+      MonitorMode::Fatal => panic!("{message}"),
+    }
+  }
+}
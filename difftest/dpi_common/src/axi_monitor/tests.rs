@@ -0,0 +1,247 @@
+//! Violations reported by the AXI monitor, and its modes
+
+use std::panic::{self, AssertUnwindSafe};
+
+use super::{AxiMonitor, AxiMonitorConfig, MonitorMode};
+use crate::axi::{AxiAddr, AxiB, AxiR};
+
+// a 32B bus
+const DATA_WIDTH: u64 = 256;
+const BUS_SIZE: usize = DATA_WIDTH as usize / 8;
+
+fn monitor(mode: MonitorMode) -> AxiMonitor {
+  AxiMonitor::new(AxiMonitorConfig { mode, max_outstanding: Some(2) }, || 42)
+}
+
+fn request(id: u64, addr: u64, size: u64, len: u64) -> AxiAddr {
+  AxiAddr { id, addr, size, len, user: id }
+}
+
+// strobes of the byte lanes `lanes`
+fn strobe(lanes: std::ops::Range<usize>) -> Vec<bool> {
+  (0..BUS_SIZE).map(|lane| lanes.contains(&lane)).collect()
+}
+
+fn beat(id: u16, last: bool) -> AxiR {
+  AxiR { last, id, user: id as u32, data: vec![0; BUS_SIZE] }
+}
+
+// the number of violations reported by `f` in warn mode
+#[track_caller]
+fn violations(f: impl FnOnce(&mut AxiMonitor)) -> u64 {
+  let mut monitor = monitor(MonitorMode::Warn);
+  f(&mut monitor);
+  monitor.violations()
+}
+
+// the message of the first violation reported by `f` in fatal mode
+#[track_caller]
+fn fatal(f: impl FnOnce(&mut AxiMonitor)) -> String {
+  let mut monitor = monitor(MonitorMode::Fatal);
+  let err = panic::catch_unwind(AssertUnwindSafe(|| f(&mut monitor)))
+    .expect_err("a violation should be reported");
+  match err.downcast::<String>() {
+    Ok(message) => *message,
+    Err(err) => err.downcast_ref::<&str>().unwrap().to_string(),
+  }
+}
+
+#[track_caller]
+fn assert_fatal(f: impl FnOnce(&mut AxiMonitor), expected: &str) {
+  let message = fatal(f);
+  assert!(
+    message.contains(expected),
+    "unexpected violation: {message}"
+  );
+}
+
+#[test]
+fn accepts_a_write_burst() {
+  let count = violations(|monitor| {
+    monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1000, 5, 1));
+    monitor.on_w(0, &strobe(0..32), false);
+    monitor.on_w(0, &strobe(0..32), true);
+    monitor.on_b(0, &AxiB { id: 1, user: 1 });
+  });
+  assert_eq!(count, 0);
+}
+
+#[test]
+fn accepts_an_unaligned_narrow_burst() {
+  // beat 0 covers 0x1006..0x1008, then 0x1008..0x100c
+  let count = violations(|monitor| {
+    monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1006, 2, 1));
+    monitor.on_w(0, &strobe(6..8), false);
+    monitor.on_w(0, &strobe(8..12), true);
+    monitor.on_ar(0, DATA_WIDTH, &request(2, 0x1006, 2, 1));
+    monitor.on_r(0, &beat(2, false));
+    monitor.on_r(0, &beat(2, true));
+  });
+  assert_eq!(count, 0);
+}
+
+#[test]
+fn reports_bursts_crossing_4kib() {
+  assert_fatal(
+    |monitor| monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1ff8, 3, 1)),
+    "AXI violation on channel 0 AW: burst crossing a 4KiB boundary",
+  );
+  assert_fatal(
+    |monitor| monitor.on_ar(3, DATA_WIDTH, &request(1, 0xfe0, 5, 1)),
+    "AXI violation on channel 3 AR: burst crossing a 4KiB boundary",
+  );
+
+  // the unaligned start is in the aligned transfer that ends at the boundary
+  let count = violations(|monitor| monitor.on_ar(0, DATA_WIDTH, &request(1, 0xffc, 3, 0)));
+  assert_eq!(count, 0);
+}
+
+#[test]
+fn reports_long_bursts() {
+  assert_fatal(
+    |monitor| monitor.on_ar(0, DATA_WIDTH, &request(1, 0, 0, 256)),
+    "burst longer than 256 beats",
+  );
+}
+
+#[test]
+fn reports_transfers_wider_than_the_bus() {
+  assert_fatal(
+    |monitor| monitor.on_aw(0, DATA_WIDTH, &request(1, 0, 6, 0)),
+    "transfer size wider than the 32B bus",
+  );
+}
+
+#[test]
+fn reports_too_many_outstanding_transactions() {
+  assert_fatal(
+    |monitor| {
+      for id in 0..3 {
+        monitor.on_ar(0, DATA_WIDTH, &request(id, 0x1000, 5, 0));
+      }
+    },
+    "more than 2 outstanding transactions",
+  );
+  // each channel has its own limit
+  let count = violations(|monitor| {
+    for channel in 0..3 {
+      monitor.on_ar(channel, DATA_WIDTH, &request(0, 0x1000, 5, 0));
+    }
+  });
+  assert_eq!(count, 0);
+}
+
+#[test]
+fn reports_w_without_aw() {
+  assert_fatal(
+    |monitor| monitor.on_w(0, &strobe(0..32), true),
+    "W beat without a pending AW",
+  );
+}
+
+#[test]
+fn reports_strobes_outside_the_lanes() {
+  assert_fatal(
+    |monitor| {
+      monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1004, 2, 0));
+      monitor.on_w(0, &strobe(4..9), true);
+    },
+    "strobe of the byte lanes [8] outside the lanes 4..8 of beat 0",
+  );
+}
+
+#[test]
+fn reports_misplaced_wlast() {
+  assert_fatal(
+    |monitor| {
+      monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1000, 5, 1));
+      monitor.on_w(0, &strobe(0..32), true);
+    },
+    "WLAST=1 on beat 0 of a 2-beat burst",
+  );
+  assert_fatal(
+    |monitor| {
+      monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1000, 5, 0));
+      monitor.on_w(0, &strobe(0..32), false);
+    },
+    "WLAST=0 on beat 0 of a 1-beat burst",
+  );
+}
+
+#[test]
+fn reports_b_violations() {
+  assert_fatal(
+    |monitor| monitor.on_b(0, &AxiB { id: 1, user: 1 }),
+    "B of id=1 without an outstanding write",
+  );
+  assert_fatal(
+    |monitor| {
+      monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1000, 5, 1));
+      monitor.on_w(0, &strobe(0..32), false);
+      monitor.on_b(0, &AxiB { id: 1, user: 1 });
+    },
+    "B before the last W beat",
+  );
+  assert_fatal(
+    |monitor| {
+      monitor.on_aw(0, DATA_WIDTH, &request(1, 0x1000, 5, 0));
+      monitor.on_w(0, &strobe(0..32), true);
+      monitor.on_b(0, &AxiB { id: 1, user: 7 });
+    },
+    "BUSER=0x7 of another write",
+  );
+}
+
+#[test]
+fn reports_r_violations() {
+  assert_fatal(
+    |monitor| monitor.on_r(0, &beat(1, true)),
+    "R of id=1 without an outstanding read",
+  );
+  assert_fatal(
+    |monitor| {
+      monitor.on_ar(0, DATA_WIDTH, &request(1, 0x1000, 5, 1));
+      monitor.on_r(0, &beat(1, true));
+    },
+    "RLAST=1 on beat 0",
+  );
+  assert_fatal(
+    |monitor| {
+      monitor.on_ar(0, DATA_WIDTH, &request(1, 0x1000, 5, 0));
+      monitor.on_r(0, &AxiR { user: 7, ..beat(1, true) });
+    },
+    "RUSER=0x7 of another read",
+  );
+}
+
+#[test]
+fn violations_include_the_transaction_and_cycle() {
+  assert_fatal(
+    |monitor| monitor.on_aw(2, DATA_WIDTH, &request(5, 0x1ff8, 3, 1)),
+    "[42] AXI violation on channel 2 AW: burst crossing a 4KiB boundary \
+     (id=5 addr=0x1ff8 size=8B len=1 user=0x5 beats=0/2 issued at cycle 42)",
+  );
+}
+
+#[test]
+fn warn_mode_counts_violations() {
+  let count = violations(|monitor| {
+    // burst crossing 4KiB, then too many strobes and an early WLAST
+    monitor.on_aw(0, DATA_WIDTH, &request(1, 0xff8, 3, 1));
+    monitor.on_w(0, &strobe(0..32), true);
+    // responses without requests
+    monitor.on_b(0, &AxiB { id: 9, user: 0 });
+    monitor.on_r(0, &beat(9, true));
+  });
+  assert_eq!(count, 5);
+}
+
+#[test]
+fn off_mode_checks_nothing() {
+  let mut monitor = monitor(MonitorMode::Off);
+  monitor.on_aw(0, DATA_WIDTH, &request(1, 0xff8, 6, 300));
+  monitor.on_w(0, &strobe(0..32), true);
+  monitor.on_b(0, &AxiB { id: 9, user: 0 });
+  monitor.on_r(0, &beat(9, true));
+  assert_eq!(monitor.violations(), 0);
+}
@@ -1,34 +1,53 @@
+//! Global state of the DPI library, one target per testbench instance
+//!
+//! A simulation may host several testbench instances (e.g. several T1 cores). Each one calls
+//! `*_cosim_init`, which returns the id of the new instance, and passes that id as the first
+//! argument of all its later DPI calls. An id is used rather than the calling scope, since
+//! the calls generated by the DPI intrinsics aren't `context` imports and have no scope.
+
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 
+use svdpi::SvScope;
+use tracing::info;
+
 pub struct DpiTarget<T> {
-  target: Mutex<Option<T>>,
+  // indexed by the instance id, disposed instances are `None`
+  instances: Mutex<Vec<Option<T>>>,
 }
 
 impl<T> DpiTarget<T> {
   pub const fn new() -> Self {
-    Self { target: Mutex::new(None) }
+    Self { instances: Mutex::new(Vec::new()) }
   }
 
+  /// Register the target of a new instance and return its id, `init_fn` gets that id.
+  /// Ids are given in the order of registration, from 0.
   #[track_caller]
-  pub fn init(&self, init_fn: impl FnOnce() -> T) {
-    let mut target = self.target.lock().unwrap();
-    if target.is_some() {
-      panic!("DpiTarget is already initialized");
+  pub fn init(&self, init_fn: impl FnOnce(usize) -> T) -> u32 {
+    let mut instances = self.instances.lock().unwrap();
+    let id = instances.len();
+    if id > 0 {
+      let scope = SvScope::get_current().and_then(|scope| scope.name().to_str().ok());
+      info!("DPI instance {id} at scope '{}'", scope.unwrap_or("<none>"));
     }
-    *target = Some(init_fn());
+    instances.push(Some(init_fn(id)));
+    id as u32
   }
 
   #[track_caller]
-  pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
-    let mut target = self.target.lock().unwrap();
-    let target = target.as_mut().expect("DpiTarget is not initialized");
-    f(target)
+  pub fn with<R>(&self, instance: u32, f: impl FnOnce(&mut T) -> R) -> R {
+    let mut instances = self.instances.lock().unwrap();
+    match instances.get_mut(instance as usize) {
+      Some(Some(target)) => f(target),
+      _ => panic!("DpiTarget is not initialized for instance {instance}"),
+    }
   }
 
   #[track_caller]
-  pub fn with_optional<R>(&self, f: impl FnOnce(Option<&mut T>) -> R) -> R {
-    match self.target.lock() {
-      Ok(mut target) => f(target.as_mut()),
+  pub fn with_optional<R>(&self, instance: u32, f: impl FnOnce(Option<&mut T>) -> R) -> R {
+    match self.instances.lock() {
+      Ok(mut instances) => f(instances.get_mut(instance as usize).and_then(Option::as_mut)),
 
       // treat poisoned mutex as non-initialized
       Err(_) => f(None),
@@ -36,11 +55,25 @@ impl<T> DpiTarget<T> {
   }
 
   #[track_caller]
-  pub fn dispose(&self) {
-    let mut target = self.target.lock().unwrap();
-    if target.is_none() {
-      panic!("DpiTarget is not initialized");
+  pub fn dispose(&self, instance: u32) {
+    let mut instances = self.instances.lock().unwrap();
+    match instances.get_mut(instance as usize) {
+      Some(target @ Some(_)) => *target = None,
+      _ => panic!("DpiTarget is not initialized for instance {instance}"),
     }
-    *target = None;
   }
 }
+
+/// Output path of the instance `index`: `path` itself for the first instance, so that
+/// single-instance simulations are unaffected, e.g. `sim_result.1.json` for the second one.
+pub fn instance_path(path: &Path, index: usize) -> PathBuf {
+  if index == 0 {
+    return path.to_path_buf();
+  }
+  let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+  let file_name = match path.extension() {
+    Some(ext) => format!("{stem}.{index}.{}", ext.to_string_lossy()),
+    None => format!("{stem}.{index}"),
+  };
+  path.with_file_name(file_name)
+}
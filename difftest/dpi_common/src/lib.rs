@@ -1,19 +1,89 @@
+pub mod axi;
+pub mod axi_monitor;
 pub mod dpi_target;
+pub mod mem_model;
 pub mod plusarg;
+pub mod record;
 pub mod util;
 
 pub use dpi_target::DpiTarget;
 
+use std::fmt;
+use std::fs::File;
+use std::sync::{Mutex, Once};
+
+use plusarg::{PlusArgDef, PlusArgs};
+use tracing::Subscriber;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
-pub fn setup_logger() {
-  let global_logger = FmtSubscriber::builder()
-    .with_env_filter(EnvFilter::from_default_env()) // default level: error
-    .without_time()
-    .with_target(false)
-    .with_ansi(true)
-    .compact()
-    .finish();
+/// Plusargs read by `setup_logger`, to be declared by each emulator
+pub const LOG_PLUSARGS: [PlusArgDef; 3] = [
+  PlusArgDef::path(
+    "t1_log_path",
+    "write DPI logs to this file instead of stderr",
+  ),
+  PlusArgDef::enumeration("t1_log_format", &["compact", "json"], "format of DPI logs")
+    .default("compact"),
+  PlusArgDef::str(
+    "t1_log_filter",
+    "level and filters of DPI logs in RUST_LOG syntax, e.g. 'info,dpi_t1emu::drive=trace', \
+    overriding RUST_LOG",
+  ),
+];
+
+/// Stamp each record with the cycle given by `get_t`
+struct CycleTime(fn() -> u64);
+
+impl FormatTime for CycleTime {
+  fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+    write!(w, "{}", (self.0)())
+  }
+}
+
+static LOGGER: Once = Once::new();
+
+/// Install the global logger configured by `LOG_PLUSARGS`, records are stamped by `get_t`.
+/// Only the first call takes effect, as every DPI instance calls it on init.
+pub fn setup_logger(plusargs: &PlusArgs, get_t: fn() -> u64) {
+  LOGGER.call_once(|| install_logger(plusargs, get_t));
+}
+
+fn install_logger(plusargs: &PlusArgs, get_t: fn() -> u64) {
+  let filter = match plusargs.get_str("t1_log_filter") {
+    Some(directives) => EnvFilter::try_new(directives)
+      .unwrap_or_else(|e| panic!("invalid plusarg '+t1_log_filter={directives}': {e}")),
+    None => EnvFilter::from_default_env(), // default level: error
+  };
+  let log_path = plusargs.get_path("t1_log_path");
+  let json = plusargs.get_str("t1_log_format") == Some("json");
+
+  let builder = FmtSubscriber::builder()
+    .with_env_filter(filter)
+    .with_timer(CycleTime(get_t))
+    .with_target(false);
+
+  let global_logger: Box<dyn Subscriber + Send + Sync> = match (&log_path, json) {
+    (None, false) => Box::new(builder.with_ansi(true).compact().finish()),
+    (None, true) => Box::new(builder.with_ansi(false).json().finish()),
+    (Some(path), json) => {
+      let file = File::create(path)
+        .unwrap_or_else(|e| panic!("failed to create log file '{}': {e}", path.display()));
+      let builder = builder.with_ansi(false).with_writer(Mutex::new(file));
+      if json {
+        Box::new(builder.json().finish())
+      } else {
+        Box::new(builder.compact().finish())
+      }
+    }
+  };
   tracing::subscriber::set_global_default(global_logger)
     .expect("internal error: fail to setup log subscriber");
+
+  if let Some(path) = log_path {
+    // still visible among the RTL output
+    eprintln!("DPI logs are written to '{}'", path.display());
+  }
+  plusargs.log_values();
 }
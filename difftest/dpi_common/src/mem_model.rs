@@ -0,0 +1,387 @@
+//! Memory models, simulating the latency of memory devices
+//!
+//! `MEM_MODEL_PLUSARGS` selects the model of an emulator whose memory latency is configurable.
+
+use std::cell::Cell;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::plusarg::{PlusArgDef, PlusArgs};
+
+/// Plusargs read by `MemModelConfig::from_plusargs`, to be declared by emulators selecting the model
+pub const MEM_MODEL_PLUSARGS: [PlusArgDef; 4] = [
+  PlusArgDef::enumeration(
+    "t1_mem_model",
+    &["trivial", "fixed", "dramsim3"],
+    "latency model of the memory behind the AXI ports",
+  )
+  .default("trivial"),
+  PlusArgDef::int(
+    "t1_mem_latency",
+    "latency in cycles of the 'fixed' memory model",
+  )
+  .default("32"),
+  PlusArgDef::path(
+    "t1_dramsim3_cfg",
+    "path to the dramsim3 configuration, required by the 'dramsim3' memory model",
+  ),
+  PlusArgDef::path(
+    "t1_dramsim3_path",
+    "path of the output of dramsim3, under temp if absent",
+  ),
+];
+
+/// An abstract memory request (identifier) for memory models
+#[derive(Debug)]
+pub struct MemIdent {
+  pub id: u64,
+  pub addr: u32,
+  pub len: u32,
+  pub is_write: bool,
+}
+
+impl From<InflightMem> for MemIdent {
+  fn from(inflight: InflightMem) -> Self {
+    MemIdent {
+      id: inflight.id,
+      addr: inflight.addr,
+      len: inflight.len,
+      is_write: inflight.is_write,
+    }
+  }
+}
+
+/// The memory model interface
+///
+/// A memory model simulates the latency of a memory device,
+/// but don't keep track of its content.
+pub trait MemoryModel {
+  fn push(&mut self, req: MemIdent);
+  fn pop(&mut self) -> Option<MemIdent>;
+  fn tick(&mut self);
+}
+
+/// Memory model selected by `MEM_MODEL_PLUSARGS`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum MemModelConfig {
+  Trivial,
+  Fixed { latency: u64 },
+  Dramsim3 { cfg: PathBuf, path: Option<PathBuf> },
+}
+
+impl MemModelConfig {
+  pub fn from_plusargs(plusargs: &PlusArgs) -> Self {
+    match plusargs.get_str("t1_mem_model") {
+      Some("fixed") => MemModelConfig::Fixed {
+        latency: plusargs.get_int("t1_mem_latency").unwrap(),
+      },
+      Some("dramsim3") => MemModelConfig::Dramsim3 {
+        cfg: plusargs
+          .get_path("t1_dramsim3_cfg")
+          .expect("plusarg '+t1_dramsim3_cfg' is required by '+t1_mem_model=dramsim3'"),
+        path: plusargs.get_path("t1_dramsim3_path"),
+      },
+      _ => MemModelConfig::Trivial,
+    }
+  }
+
+  /// Create the model, `sys_tck` is the clock period of the system in ns
+  pub fn build(&self, sys_tck: f64) -> Box<dyn MemoryModel + Send> {
+    match self {
+      MemModelConfig::Trivial => Box::new(TrivialModel::default()),
+      MemModelConfig::Fixed { latency } => Box::new(FixedLatencyModel::new(*latency)),
+      MemModelConfig::Dramsim3 { cfg, path } => {
+        let run_path = match path {
+          Some(path) => path.clone(),
+          None => {
+            let dir = tempfile::TempDir::new().expect("Failed to create dramsim3 runtime dir");
+            let path = dir.path().into();
+            std::mem::forget(dir);
+            path
+          }
+        };
+        debug!("DRAMsim3 enabled with config: {cfg:?}, result: {run_path:?}");
+        Box::new(DRAMModel::new(cfg, &run_path, sys_tck))
+      }
+    }
+  }
+}
+
+/// AddrSet repersents a subset of a specific memory slice, where
+/// each element (line) have uniform width
+///
+/// A memory request with arbitrary length may need to be segmented
+/// into multiple smaller memory requests. AddrSets are used to individually
+/// keep track of these requests' progress.
+#[derive(Clone, Debug)]
+pub struct AddrSet {
+  pub base: u32,
+  pub line_size: u16,
+  pub set: u16,
+}
+
+impl AddrSet {
+  /// Create an addr set based on the unaligned base, unaligned length, and the line size
+  ///
+  /// the returned set contains all elements initially.
+  pub fn new(unaligned_base: u32, len: u32, line_size: u32) -> AddrSet {
+    let base = (unaligned_base / line_size) * line_size;
+    let prepend = unaligned_base - base;
+    let line_num = (len + prepend).div_ceil(line_size);
+    assert!(
+      line_num <= 16,
+      "line number {} > 16 not supported!",
+      line_num
+    );
+    let set = if line_num == 16 {
+      u16::MAX
+    } else {
+      ((1 << line_num) - 1) as u16
+    };
+
+    AddrSet { base, line_size: line_size as u16, set }
+  }
+
+  /// Returns if this set is empty
+  pub fn empty(&self) -> bool {
+    self.set == 0
+  }
+
+  /// Remove a element based on its index
+  ///
+  /// Returns if the element is previously in the set
+  pub fn remove(&mut self, idx: u32) -> bool {
+    if idx >= 16 {
+      return false;
+    }
+    let currently_set = self.set & (1u16 << idx) != 0;
+    self.set &= !(1u16 << idx);
+    currently_set
+  }
+
+  /// Remove a element based on its address
+  /// The address has to reside within the range, and is aligned
+  /// to the line size.
+  ///
+  /// Returns if the element is previously in the set
+  pub fn remove_addr(&mut self, addr: u32) -> bool {
+    if addr < self.base {
+      return false;
+    }
+    let diff = addr - self.base;
+    if !diff.is_multiple_of(self.line_size as u32) {
+      return false;
+    }
+    let idx = diff / self.line_size as u32;
+    self.remove(idx)
+  }
+}
+
+impl Iterator for AddrSet {
+  type Item = (u32, u32); // (addr, offset)
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.set == 0 {
+      return None;
+    }
+    let last_one = self.set.trailing_zeros();
+    let addr = self.base + self.line_size as u32 * last_one;
+    self.remove(last_one);
+    Some((addr, last_one))
+  }
+}
+
+/// Book-keeping structure for DRAMsim models
+#[derive(Debug)]
+struct InflightMem {
+  id: u64,
+  addr: u32,
+  len: u32,
+  is_write: bool,
+
+  /// Un-sent requests
+  req_wait: AddrSet,
+  /// Un-acked responses
+  resp_wait: AddrSet,
+}
+
+impl InflightMem {
+  fn from_ident(ident: MemIdent, line_size: u32) -> Self {
+    let set = AddrSet::new(ident.addr, ident.len, line_size);
+    Self {
+      id: ident.id,
+      addr: ident.addr,
+      len: ident.len,
+      is_write: ident.is_write,
+
+      req_wait: set.clone(),
+      resp_wait: set,
+    }
+  }
+
+  /// Returns if all sub-requests are sent to the memory model
+  fn sent(&self) -> bool {
+    self.req_wait.empty()
+  }
+
+  /// Returns if all sub-responses are received
+  fn done(&self) -> bool {
+    self.resp_wait.empty()
+  }
+}
+
+/// The DRAM memory model based on DRAMsim3
+///
+/// The ticking speed of this model should be the system clock speed,
+/// as it keeps track of the DRAM clock tick internally and compensates
+/// for the clock speed difference.
+pub struct DRAMModel {
+  sys: dramsim3::MemorySystem,
+  inflights: Vec<InflightMem>,
+  // TODO: implement cache
+  _cached: BinaryHeap<u32>,
+  dram_tick: usize,
+  sys_tick: usize,
+  /// system tCK in ns
+  sys_tck: f64,
+}
+
+// FIXME: impl Send in upstream MemorySystem
+unsafe impl Send for DRAMModel {}
+
+impl DRAMModel {
+  pub fn new(ds_cfg: &Path, ds_path: &Path, sys_tck: f64) -> Self {
+    let chunk_size: Rc<Cell<u32>> = Rc::new(Cell::new(0));
+    let chunk_size_clone = chunk_size.clone();
+    let sys =
+      dramsim3::MemorySystem::new(ds_cfg, ds_path).expect("dramsim3 MemorySystem creation failed");
+    let ret = DRAMModel {
+      sys,
+      inflights: vec![],
+      _cached: BinaryHeap::new(),
+      dram_tick: 0,
+      sys_tick: 0,
+      sys_tck,
+    };
+
+    chunk_size_clone.set(ret.req_size());
+    ret
+  }
+
+  /// Size of each request to DRAM, in bytes
+  fn req_size(&self) -> u32 {
+    (self.sys.burst_length() * self.sys.bus_bits() / 8) as u32
+  }
+}
+
+impl MemoryModel for DRAMModel {
+  fn push(&mut self, req: MemIdent) {
+    // TODO: done if in cache
+    debug!("DRAM Pushing: {:x}, size = {}", req.addr, req.len);
+    self.inflights.push(InflightMem::from_ident(req, self.req_size()));
+  }
+
+  fn pop(&mut self) -> Option<MemIdent> {
+    // Take exact one of the inflight request that are fully done
+    // Also ensure no preceding requests that has conflicting ID
+    let mut blocked = HashSet::with_capacity(32);
+    let inf = &mut self.inflights;
+    for i in 0..inf.len() {
+      if inf[i].done() && !blocked.contains(&inf[i].id) {
+        return Some(inf.remove(i).into());
+      } else {
+        blocked.insert(inf[i].id);
+      }
+    }
+    None
+  }
+
+  fn tick(&mut self) {
+    for inflight in &mut self.inflights {
+      if inflight.sent() {
+        continue;
+      }
+      for (addr, idx) in inflight.req_wait.clone() {
+        if !self.sys.can_add(addr as u64, inflight.is_write) {
+          continue;
+        }
+        debug!(
+          "DRAM Memory request: {:x}, write={}, id=0x{:x}",
+          addr, inflight.is_write, inflight.id
+        );
+        self.sys.add(addr as u64, inflight.is_write);
+        inflight.req_wait.remove(idx);
+      }
+    }
+
+    self.sys_tick += 1;
+    let dram_tck = self.sys.tck(); // In ns
+    while self.sys_tick as f64 * self.sys_tck > self.dram_tick as f64 * dram_tck {
+      let inflights = &mut self.inflights;
+      self.sys.tick(|addr, is_write| {
+        debug!("DRAM Memory response: {:x}, write={}", addr, is_write);
+        for req in &mut *inflights {
+          if req.is_write == is_write && req.resp_wait.remove_addr(addr as u32) {
+            debug!("Found req: id={:x}", req.id);
+            return;
+          }
+        }
+        debug!("All requests: {:#?}", inflights);
+        panic!("Unexpected memory response!");
+      });
+      self.dram_tick += 1;
+    }
+  }
+}
+
+/// A trivial memory model, where all requests are immediately resolved
+/// and served in the FIFO order
+#[derive(Default)]
+pub struct TrivialModel {
+  holding: VecDeque<MemIdent>,
+}
+
+impl MemoryModel for TrivialModel {
+  fn push(&mut self, req: MemIdent) {
+    self.holding.push_back(req);
+  }
+  fn pop(&mut self) -> Option<MemIdent> {
+    self.holding.pop_front()
+  }
+  fn tick(&mut self) {}
+}
+
+/// A memory model resolving each request after a fixed number of ticks,
+/// served in the FIFO order, without limit on outstanding requests
+pub struct FixedLatencyModel {
+  latency: u64,
+  tick: u64,
+  /// requests with the tick they are resolved
+  holding: VecDeque<(u64, MemIdent)>,
+}
+
+impl FixedLatencyModel {
+  pub fn new(latency: u64) -> Self {
+    FixedLatencyModel { latency, tick: 0, holding: VecDeque::new() }
+  }
+}
+
+impl MemoryModel for FixedLatencyModel {
+  fn push(&mut self, req: MemIdent) {
+    self.holding.push_back((self.tick + self.latency, req));
+  }
+  fn pop(&mut self) -> Option<MemIdent> {
+    match self.holding.front() {
+      Some(&(resolved, _)) if resolved <= self.tick => self.holding.pop_front().map(|(_, req)| req),
+      _ => None,
+    }
+  }
+  fn tick(&mut self) {
+    self.tick += 1;
+  }
+}
@@ -1,29 +1,298 @@
-pub struct PlusArgMatcher {
-  plusargs: Vec<String>,
+//! Plusargs given to the emulator, e.g. `+t1_timeout=10000`
+//!
+//! Each emulator declares its plusargs as a list of `PlusArgDef`, including those parsed by the
+//! RTL testbench, so that values are checked in one place, a misspelled `+t1_*` plusarg is
+//! reported instead of silently ignored, and `+help` prints them all.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use anyhow::{Context as _, anyhow, bail};
+
+/// Plusargs with this prefix are checked against the declarations,
+/// others belong to the simulator and are ignored
+pub const PLUSARG_PREFIX: &str = "t1_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlusArgKind {
+  /// decimal, or hexadecimal with '0x' prefix
+  Int,
+  /// `+name` alone means true, otherwise `+name=1|0|true|false`
+  Bool,
+  Path,
+  Str,
+  /// one of the given names
+  Enum(&'static [&'static str]),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlusArgDef {
+  pub name: &'static str,
+  pub kind: PlusArgKind,
+  pub default: Option<&'static str>,
+  pub required: bool,
+  /// parsed by the RTL testbench with `$value$plusargs`, integers must be decimal
+  pub rtl: bool,
+  pub help: &'static str,
+}
+
+impl PlusArgDef {
+  pub const fn new(name: &'static str, kind: PlusArgKind, help: &'static str) -> Self {
+    PlusArgDef {
+      name,
+      kind,
+      default: None,
+      required: false,
+      rtl: false,
+      help,
+    }
+  }
+
+  pub const fn int(name: &'static str, help: &'static str) -> Self {
+    Self::new(name, PlusArgKind::Int, help)
+  }
+
+  pub const fn bool(name: &'static str, help: &'static str) -> Self {
+    Self::new(name, PlusArgKind::Bool, help)
+  }
+
+  pub const fn path(name: &'static str, help: &'static str) -> Self {
+    Self::new(name, PlusArgKind::Path, help)
+  }
+
+  pub const fn str(name: &'static str, help: &'static str) -> Self {
+    Self::new(name, PlusArgKind::Str, help)
+  }
+
+  pub const fn enumeration(
+    name: &'static str,
+    variants: &'static [&'static str],
+    help: &'static str,
+  ) -> Self {
+    Self::new(name, PlusArgKind::Enum(variants), help)
+  }
+
+  pub const fn default(mut self, default: &'static str) -> Self {
+    self.default = Some(default);
+    self
+  }
+
+  pub const fn required(mut self) -> Self {
+    self.required = true;
+    self
+  }
+
+  pub const fn rtl(mut self) -> Self {
+    self.rtl = true;
+    self
+  }
+
+  fn check(&self, value: &str) -> anyhow::Result<()> {
+    match self.kind {
+      PlusArgKind::Int if self.rtl => {
+        value.parse::<u64>().map(drop).map_err(|_| anyhow!("expect a decimal integer"))
+      }
+      PlusArgKind::Int => parse_int(value).map(drop),
+      PlusArgKind::Bool => parse_bool(value).map(drop),
+      PlusArgKind::Path | PlusArgKind::Str => Ok(()),
+      PlusArgKind::Enum(variants) => {
+        if variants.contains(&value) {
+          Ok(())
+        } else {
+          bail!("expect one of {}", variants.join(", "))
+        }
+      }
+    }
+  }
+
+  fn usage(&self) -> String {
+    let value = match self.kind {
+      PlusArgKind::Int => "<int>".to_string(),
+      PlusArgKind::Bool => return format!("+{}[=<bool>]", self.name),
+      PlusArgKind::Path => "<path>".to_string(),
+      PlusArgKind::Str => "<string>".to_string(),
+      PlusArgKind::Enum(variants) => format!("<{}>", variants.join("|")),
+    };
+    format!("+{}={value}", self.name)
+  }
+}
+
+fn parse_int(value: &str) -> anyhow::Result<u64> {
+  let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+    Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+    None => value.replace('_', "").parse(),
+  };
+  parsed.map_err(|_| anyhow!("expect a decimal or '0x' prefixed hexadecimal integer"))
+}
+
+fn parse_bool(value: &str) -> anyhow::Result<bool> {
+  match value {
+    "1" | "true" => Ok(true),
+    "0" | "false" => Ok(false),
+    _ => bail!("expect 1, 0, true or false"),
+  }
+}
+
+/// Plusargs checked against their declarations
+#[derive(Debug)]
+pub struct PlusArgs {
+  defs: Vec<&'static PlusArgDef>,
+  values: HashMap<&'static str, String>,
 }
 
-impl PlusArgMatcher {
-  pub fn from_args() -> Self {
-    let plusargs = std::env::args().filter(|arg| arg.starts_with('+')).collect();
+impl PlusArgs {
+  /// Parse the plusargs of the process against the declarations of all groups, e.g. the
+  /// emulator's own and the shared ones like `LOG_PLUSARGS`. Prints the help and exits on
+  /// `+help`, panics on invalid plusargs. It runs before the logger is set up,
+  /// so errors are printed to stderr directly.
+  pub fn from_args(groups: &[&'static [PlusArgDef]]) -> Self {
+    let args: Vec<String> = std::env::args().filter(|arg| arg.starts_with('+')).collect();
+    if args.iter().any(|arg| arg == "+help") {
+      eprint!("{}", Self::help(groups));
+      std::process::exit(0);
+    }
+
+    match Self::parse(groups, &args) {
+      Ok(plusargs) => plusargs,
+      Err(e) => {
+        eprintln!("{e:#}, see '+help' for available plusargs");
+        panic!("invalid plusargs: {e:#}");
+      }
+    }
+  }
 
-    Self { plusargs }
+  /// Log the given values in the order of declaration
+  pub fn log_values(&self) {
+    for def in &self.defs {
+      if let Some(value) = self.values.get(def.name) {
+        tracing::info!("plusarg +{}={value}", def.name);
+      }
+    }
   }
 
-  pub fn try_match(&self, arg_name: &str) -> Option<&str> {
-    let prefix = &format!("+{arg_name}=");
+  pub fn parse(groups: &[&'static [PlusArgDef]], args: &[String]) -> anyhow::Result<Self> {
+    let defs: Vec<&'static PlusArgDef> = groups.iter().flat_map(|group| group.iter()).collect();
+    let mut values = HashMap::new();
+    let mut errors = vec![];
 
-    for plusarg in &self.plusargs {
-      if plusarg.starts_with(prefix) {
-        return Some(&plusarg[prefix.len()..]);
+    for arg in args {
+      let Some(arg) = arg.strip_prefix('+').filter(|arg| arg.starts_with(PLUSARG_PREFIX)) else {
+        continue;
+      };
+      let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+
+      let Some(def) = defs.iter().find(|def| def.name == name) else {
+        errors.push(match closest(&defs, name) {
+          Some(similar) => format!("unknown plusarg '+{name}', did you mean '+{similar}'?"),
+          None => format!("unknown plusarg '+{name}'"),
+        });
+        continue;
+      };
+
+      let value = if value.is_empty() && def.kind == PlusArgKind::Bool {
+        "1"
+      } else {
+        value
+      };
+      if let Err(e) = def.check(value) {
+        errors.push(format!("invalid value '{value}' of '+{name}': {e}"));
+        continue;
       }
+      // the first one wins, as in `$value$plusargs`
+      values.entry(def.name).or_insert_with(|| value.to_string());
     }
-    None
+
+    for def in defs.iter().filter(|def| def.required && !values.contains_key(def.name)) {
+      errors.push(format!("required plusarg '{}' not found", def.usage()));
+    }
+
+    if !errors.is_empty() {
+      bail!("{}", errors.join("; "));
+    }
+    Ok(PlusArgs { defs, values })
   }
 
-  pub fn match_(&self, arg_name: &str) -> &str {
-    self.try_match(arg_name).unwrap_or_else(|| {
-      tracing::error!("required plusarg '+{arg_name}=' not found");
-      panic!("failed to match '+{arg_name}='");
+  pub fn help(groups: &[&[PlusArgDef]]) -> String {
+    let defs: Vec<&PlusArgDef> = groups.iter().flat_map(|group| group.iter()).collect();
+    let usages: Vec<String> = defs.iter().map(|def| def.usage()).collect();
+    let width = usages.iter().map(String::len).max().unwrap_or(0);
+
+    let mut help = String::from("plusargs:\n");
+    for (def, usage) in defs.iter().zip(&usages) {
+      let mut notes = vec![];
+      if def.required {
+        notes.push("required".to_string());
+      }
+      if let Some(default) = def.default {
+        notes.push(format!("default: {default}"));
+      }
+      if def.rtl {
+        notes.push("parsed by RTL".to_string());
+      }
+      let notes = if notes.is_empty() {
+        String::new()
+      } else {
+        format!(" ({})", notes.join(", "))
+      };
+      writeln!(help, "  {usage:width$}  {}{notes}", def.help).unwrap();
+    }
+    help
+  }
+
+  /// The given value, or the default
+  fn value(&self, name: &str, kind: fn(PlusArgKind) -> bool) -> Option<&str> {
+    let def =
+      self.defs.iter().find(|def| def.name == name && kind(def.kind)).unwrap_or_else(|| {
+        panic!("internal error: plusarg '+{name}' of this type is not declared")
+      });
+    self.values.get(name).map(String::as_str).or(def.default)
+  }
+
+  pub fn get_int(&self, name: &str) -> Option<u64> {
+    let value = self.value(name, |kind| kind == PlusArgKind::Int)?;
+    Some(parse_int(value).with_context(|| format!("in plusarg '+{name}'")).unwrap())
+  }
+
+  pub fn get_bool(&self, name: &str) -> bool {
+    self
+      .value(name, |kind| kind == PlusArgKind::Bool)
+      .is_some_and(|value| parse_bool(value).unwrap())
+  }
+
+  pub fn get_path(&self, name: &str) -> Option<PathBuf> {
+    self.value(name, |kind| kind == PlusArgKind::Path).map(PathBuf::from)
+  }
+
+  /// Value of a string or enum plusarg
+  pub fn get_str(&self, name: &str) -> Option<&str> {
+    self.value(name, |kind| {
+      matches!(kind, PlusArgKind::Str | PlusArgKind::Enum(_))
     })
   }
 }
+
+// the declared name within a small edit distance
+fn closest(defs: &[&PlusArgDef], name: &str) -> Option<&'static str> {
+  defs
+    .iter()
+    .map(|def| (edit_distance(def.name, name), def.name))
+    .filter(|&(distance, _)| distance <= 3)
+    .min()
+    .map(|(_, name)| name)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut row: Vec<usize> = (0..=b.len()).collect();
+  for (i, ca) in a.chars().enumerate() {
+    let mut prev = row[0];
+    row[0] = i + 1;
+    for (j, &cb) in b.iter().enumerate() {
+      let substitute = prev + (ca != cb) as usize;
+      prev = row[j + 1];
+      row[j + 1] = substitute.min(prev + 1).min(row[j] + 1);
+    }
+  }
+  row[b.len()]
+}
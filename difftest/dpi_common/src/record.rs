@@ -0,0 +1,206 @@
+//! Record of DPI calls, replayed into the driver without the simulator
+//!
+//! A record has one JSON line per DPI call, with its cycle and decoded arguments, e.g.
+//! `{"cycle":42,"call":"axi_pop_B","channel_id":0}`. The call is written before it is handled,
+//! so a call that panics is still recorded. A call returning a value is followed by the line
+//! `{"ret":...}`. The calls of each flavor are an enum tagged by "call".
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once, OnceLock, Weak};
+
+use anyhow::{Context as _, bail};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::plusarg::PlusArgDef;
+
+/// Plusarg enabling the record, to be declared by each emulator
+pub const RECORD_PLUSARG: PlusArgDef = PlusArgDef::path(
+  "t1_dpi_record_path",
+  "record DPI calls to this file for replaying",
+);
+
+const NOT_REPLAYING: u64 = u64::MAX;
+
+static REPLAY_CYCLE: AtomicU64 = AtomicU64::new(NOT_REPLAYING);
+
+static SIM_CYCLE: OnceLock<fn() -> u64> = OnceLock::new();
+
+/// The recorded cycle of the call being replayed, for `get_t` to use instead of the simulation time
+pub fn replay_cycle() -> Option<u64> {
+  let cycle = REPLAY_CYCLE.load(Ordering::Relaxed);
+  (cycle != NOT_REPLAYING).then_some(cycle)
+}
+
+/// Install the cycle of the simulator, by `*_cosim_init`. Only the DPI functions call svdpi,
+/// the driver gets the cycle from `cycle`, so that the replay binaries link without the simulator.
+pub fn set_sim_cycle(sim_cycle: fn() -> u64) {
+  SIM_CYCLE.get_or_init(|| sim_cycle);
+}
+
+/// The recorded cycle of the call being replayed, otherwise the cycle of the simulator,
+/// 0 before either is available
+pub fn cycle() -> u64 {
+  replay_cycle().or_else(|| SIM_CYCLE.get().map(|sim_cycle| sim_cycle())).unwrap_or(0)
+}
+
+#[derive(Serialize, Deserialize)]
+struct CallLine<C> {
+  cycle: u64,
+  #[serde(flatten)]
+  call: C,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RetLine<R> {
+  ret: R,
+}
+
+type RecordWriter = Arc<Mutex<BufWriter<File>>>;
+
+/// Writers of the recorders of all instances, flushed by the panic hook
+static RECORDERS: Mutex<Vec<Weak<Mutex<BufWriter<File>>>>> = Mutex::new(Vec::new());
+static PANIC_HOOK: Once = Once::new();
+
+fn flush_on_panic(writer: &RecordWriter) {
+  PANIC_HOOK.call_once(|| {
+    let prev_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+      // the panicking thread may hold any of the locks
+      if let Ok(recorders) = RECORDERS.try_lock() {
+        for writer in recorders.iter().filter_map(Weak::upgrade) {
+          if let Ok(mut writer) = writer.try_lock() {
+            let _ = writer.flush();
+          }
+        }
+      }
+      prev_hook(info);
+    }));
+  });
+
+  let mut recorders = RECORDERS.lock().unwrap();
+  recorders.retain(|writer| writer.strong_count() > 0);
+  recorders.push(Arc::downgrade(writer));
+}
+
+pub struct DpiRecorder {
+  writer: RecordWriter,
+}
+
+impl DpiRecorder {
+  /// Create the record file. The record is flushed on panic, so it ends with the call panicking.
+  pub fn create(path: &Path) -> anyhow::Result<Self> {
+    let file = File::create(path)
+      .with_context(|| format!("failed to create DPI record '{}'", path.display()))?;
+    let writer = Arc::new(Mutex::new(BufWriter::new(file)));
+    flush_on_panic(&writer);
+
+    Ok(DpiRecorder { writer })
+  }
+
+  pub fn call(&self, cycle: u64, call: &impl Serialize) {
+    self.write_line(&CallLine { cycle, call });
+  }
+
+  pub fn ret(&self, ret: &impl Serialize) {
+    self.write_line(&RetLine { ret });
+  }
+
+  pub fn flush(&self) {
+    self.writer.lock().unwrap().flush().expect("failed to flush DPI record");
+  }
+
+  fn write_line(&self, line: &impl Serialize) {
+    let mut writer = self.writer.lock().unwrap();
+    serde_json::to_writer(&mut *writer, line).expect("failed to write DPI record");
+    writer.write_all(b"\n").expect("failed to write DPI record");
+  }
+}
+
+/// Replay the calls of a record in order. `handle` returns what the call returns
+/// (`Value::Null` if it returns nothing), which is compared with the recorded one.
+/// Returns the number of calls replayed.
+pub fn replay<C: DeserializeOwned + std::fmt::Debug>(
+  path: &Path,
+  mut handle: impl FnMut(C) -> anyhow::Result<Value>,
+) -> anyhow::Result<u64> {
+  let file =
+    File::open(path).with_context(|| format!("failed to open DPI record '{}'", path.display()))?;
+  let mut lines = RecordLines { lines: BufReader::new(file).lines(), row: 0 };
+
+  let mut count = 0;
+  let mut next = lines.next_line()?;
+  while let Some((row, line)) = next {
+    let CallLine { cycle, call } = serde_json::from_str::<CallLine<C>>(&line)
+      .with_context(|| format!("invalid call at row {row} of DPI record"))?;
+
+    next = lines.next_line()?;
+    let expected = match &next {
+      Some((ret_row, ret_line)) if ret_line.starts_with("{\"ret\":") => {
+        let RetLine { ret } = serde_json::from_str::<RetLine<Value>>(ret_line)
+          .with_context(|| format!("invalid return value at row {ret_row} of DPI record"))?;
+        next = lines.next_line()?;
+        ret
+      }
+      _ => Value::Null,
+    };
+
+    let desc = format!("{call:?} at row {row} (cycle {cycle})");
+    REPLAY_CYCLE.store(cycle, Ordering::Relaxed);
+    let actual = handle(call).with_context(|| format!("failed to replay {desc}"))?;
+    if actual != expected {
+      bail!("{desc} returns {actual}, but {expected} is recorded");
+    }
+    count += 1;
+  }
+  REPLAY_CYCLE.store(NOT_REPLAYING, Ordering::Relaxed);
+
+  Ok(count)
+}
+
+struct RecordLines {
+  lines: Lines<BufReader<File>>,
+  row: usize,
+}
+
+impl RecordLines {
+  // the next non-empty line and its row, starting from 1
+  fn next_line(&mut self) -> anyhow::Result<Option<(usize, String)>> {
+    for line in self.lines.by_ref() {
+      self.row += 1;
+      let line = line.context("failed to read DPI record")?;
+      if !line.trim().is_empty() {
+        return Ok(Some((self.row, line)));
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// AXI write strobe as a string of '0' and '1', for `#[serde(with = "...")]`
+pub mod strobe {
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S: Serializer>(strobe: &[bool], serializer: S) -> Result<S::Ok, S::Error> {
+    let bits: String = strobe.iter().map(|&bit| if bit { '1' } else { '0' }).collect();
+    serializer.serialize_str(&bits)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<bool>, D::Error> {
+    let bits: String = Deserialize::deserialize(deserializer)?;
+    bits
+      .chars()
+      .map(|bit| match bit {
+        '0' => Ok(false),
+        '1' => Ok(true),
+        _ => Err(serde::de::Error::custom(format!(
+          "invalid strobe bit '{bit}'"
+        ))),
+      })
+      .collect()
+  }
+}
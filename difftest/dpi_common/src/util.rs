@@ -1,6 +1,10 @@
 //! utility functions
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use sim_schema::SimResult;
+use sim_schema::sim_result::{InstCounts, MemTraffic};
 
 pub struct MetaConfig {
   pub vlen: u32,
@@ -12,48 +16,94 @@ pub struct MetaConfig {
   pub dramsim3_enabled: bool,
 }
 
-pub fn write_perf_json(flavor: &str, cycle: u64, success: bool, meta: &MetaConfig) {
+/// Statistics collected by the driver, recorded in 'sim_result.json'
+pub struct SimStats {
+  start: Instant,
+  /// `None` if the driver does not run a reference model
+  pub insts: Option<InstCounts>,
+  /// `None` if the driver does not issue instructions
+  pub issue_stalls: Option<u64>,
+  pub mem_traffic: MemTraffic,
+}
+
+impl SimStats {
+  /// Start the wall clock
+  pub fn new() -> Self {
+    SimStats {
+      start: Instant::now(),
+      insts: None,
+      issue_stalls: None,
+      mem_traffic: MemTraffic::default(),
+    }
+  }
+
+  pub fn count_read(&mut self, bytes: usize) {
+    self.mem_traffic.reads += 1;
+    self.mem_traffic.read_bytes += bytes as u64;
+  }
+
+  pub fn count_write(&mut self, strobe: &[bool]) {
+    self.mem_traffic.writes += 1;
+    self.mem_traffic.write_bytes += strobe.iter().filter(|&&enabled| enabled).count() as u64;
+  }
+}
+
+impl Default for SimStats {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+pub fn write_perf_json(
+  path: &Path,
+  flavor: &str,
+  cycle: u64,
+  success: bool,
+  meta: &MetaConfig,
+  stats: &SimStats,
+) {
   // unsuccessful simulation will panic by default.
   // However, it could be suppressed by setting env T1_SUPPRESS_PANIC_IN_FINAL=1
   if !success && !suppress_panic_in_final() {
     panic!("simulation ends unsuccessfully [T={cycle}]");
   }
 
-  // we construct json manually,
-  // to avoid pull extra dependencies
-
   let memory_model = if meta.dramsim3_enabled {
     "dramsim3"
   } else {
     "trivial"
   };
 
-  let mut content = String::new();
-  content += "{\n";
-  content += &format!("    \"flavor\": \"{}\",\n", flavor.escape_default());
-  content += &format!("    \"meta_vlen\": {},\n", meta.vlen);
-  content += &format!("    \"meta_dlen\": {},\n", meta.dlen);
-  content += &format!("    \"meta_lane_width\": {},\n", meta.lane_width);
-  content += &format!("    \"meta_isa\": \"{}\",\n", meta.isa.escape_default());
-  if let Some(elf_file) = &meta.elf_file {
+  let wall_clock_secs = stats.start.elapsed().as_secs_f64();
+  let sim_result = SimResult {
+    flavor: flavor.to_string(),
+    meta_vlen: meta.vlen,
+    meta_dlen: meta.dlen,
+    meta_lane_width: meta.lane_width,
+    meta_isa: meta.isa.clone(),
     // we record it for offline use,
     // relative path is not very useful for offline
-    if Path::new(elf_file).is_absolute() {
-      content += &format!(
-        "    \"meta_elf_file\": \"{}\",\n",
-        elf_file.escape_default()
-      );
-    }
-  }
-  content += &format!("    \"meta_memory_model\": \"{}\",\n", memory_model);
-  content += &format!("    \"total_cycles\": {cycle},\n");
-  content += &format!("    \"success\": {success}\n");
-  content += "}\n";
+    meta_elf_file: meta.elf_file.as_ref().map(PathBuf::from).filter(|path| path.is_absolute()),
+    meta_memory_model: memory_model.to_string(),
+    total_cycles: cycle,
+    success,
+    insts: stats.insts.clone(),
+    issue_stalls: stats.issue_stalls,
+    mem_traffic: stats.mem_traffic.clone(),
+    wall_clock_secs,
+    sim_speed_hz: if wall_clock_secs > 0.0 {
+      cycle as f64 / wall_clock_secs
+    } else {
+      0.0
+    },
+    ..Default::default()
+  };
+  let content = sim_result.to_json();
 
-  match std::fs::write("sim_result.json", &content) {
+  match std::fs::write(path, &content) {
     Ok(()) => {}
     Err(e) => {
-      tracing::error!("failed to write 'sim_result.json': {e}");
+      tracing::error!("failed to write '{}': {e}", path.display());
     }
   }
 }
@@ -0,0 +1,29 @@
+//! Replay DPI calls recorded by `+t1_dpi_record_path` into the t1emu driver
+//!
+//! usage: t1emu-dpi-replay <record> [+t1_log_filter=...] [+t1_log_path=...] [+t1_log_format=...]
+//!   [+t1_timeline_path=...]
+
+use std::path::PathBuf;
+
+use dpi_common::plusarg::{PlusArgDef, PlusArgs};
+
+const PLUSARGS: &[&[PlusArgDef]] = &[
+  &dpi_common::LOG_PLUSARGS,
+  &[dpi_t1emu::timeline::TIMELINE_PLUSARG],
+];
+
+fn main() -> anyhow::Result<()> {
+  let (plusargs, args): (Vec<String>, Vec<String>) =
+    std::env::args().skip(1).partition(|arg| arg.starts_with('+'));
+  let [record] = args.as_slice() else {
+    anyhow::bail!("usage: t1emu-dpi-replay <record> [+t1_log_*=...] [+t1_timeline_path=...]");
+  };
+
+  let plusargs = PlusArgs::parse(PLUSARGS, &plusargs)?;
+  dpi_common::setup_logger(&plusargs, dpi_t1emu::get_t);
+
+  let timeline = plusargs.get_path("t1_timeline_path");
+  let count = dpi_t1emu::replay::replay(&PathBuf::from(record), timeline.as_deref())?;
+  println!("{count} DPI calls replayed, all return values match the record");
+  Ok(())
+}
@@ -2,11 +2,18 @@
 #![allow(unused_variables)]
 
 use dpi_common::DpiTarget;
-use std::ffi::c_longlong;
-use svdpi::dpi::param::InStr;
-use tracing::{debug, error};
+use dpi_common::axi::{AxiAddr, RetAxiPopB, RetAxiPopR, StrbIterator};
+use dpi_common::axi_monitor::AxiMonitorConfig;
+use dpi_common::dpi_target::instance_path;
+use dpi_common::mem_model::MemModelConfig;
+use svdpi::dpi::param::{InBV, InStr, Out};
+use tracing::error;
 
 use crate::drive::{Driver, OnlineArgs};
+use crate::replay::DpiCall;
+use crate::timeline::Timeline;
+use dpi_common::record::DpiRecorder;
+use serde::{Deserialize, Serialize};
 use svdpi::SvScope;
 
 pub type SvBitVecVal = u32;
@@ -17,59 +24,8 @@ pub type SvBitVecVal = u32;
 
 static TARGET: DpiTarget<Driver> = DpiTarget::new();
 
-pub(crate) struct AxiReadPayload {
-  pub(crate) data: Vec<u8>,
-}
-
-unsafe fn write_to_pointer(dst: *mut u8, data: &[u8]) {
-  let dst = std::slice::from_raw_parts_mut(dst, data.len());
-  dst.copy_from_slice(data);
-}
-
-unsafe fn fill_axi_read_payload(dst: *mut SvBitVecVal, dlen: u32, payload: &AxiReadPayload) {
-  let data_len = 256 * (dlen / 8) as usize;
-  assert!(payload.data.len() <= data_len);
-  write_to_pointer(dst as *mut u8, &payload.data);
-}
-
-// Return (strobe in bit, data in byte)
-unsafe fn load_from_payload<'a>(
-  payload: *const SvBitVecVal,
-  data_width: u32,
-) -> (Vec<bool>, &'a [u8]) {
-  let src = payload as *mut u8;
-  let data_width_in_byte = (data_width / 8) as usize;
-  let strb_width_in_byte = data_width_in_byte.div_ceil(8); // ceil divide by 8 to get byte width
-  let payload_size_in_byte = strb_width_in_byte + data_width_in_byte; // data width in byte
-  let byte_vec = std::slice::from_raw_parts(src, payload_size_in_byte);
-  let strobe = &byte_vec[0..strb_width_in_byte];
-  let data = &byte_vec[strb_width_in_byte..];
-
-  let strb_width_in_bit = std::cmp::min(8, data_width_in_byte);
-  let masks: Vec<bool> = strobe
-    .into_iter()
-    .flat_map(|strb| {
-      let mask: Vec<bool> = (0..strb_width_in_bit).map(|i| (strb & (1 << i)) != 0).collect();
-      mask
-    })
-    .collect();
-  assert!(
-    masks.len() == data.len(),
-    "strobe bit width is not aligned with data byte width"
-  );
-
-  debug!(
-    "load {payload_size_in_byte} byte from payload: raw_data={} strb={} data={}",
-    hex::encode(byte_vec),
-    hex::encode(strobe),
-    hex::encode(data),
-  );
-
-  (masks, data)
-}
-
 #[repr(C, packed)]
-#[derive(Default)]
+#[derive(Default, Serialize)]
 pub(crate) struct IssueData {
   pub meta: u32,
   pub vcsr: u32,
@@ -91,6 +47,7 @@ pub static WATCHDOG_TIMEOUT: u8 = 1;
 pub static WATCHDOG_QUIT: u8 = 255;
 
 #[repr(C, packed)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub(crate) struct Retire {
   pub vxsat: u32,
   pub write_rd: u32,
@@ -102,119 +59,167 @@ pub(crate) struct Retire {
 // dpi functions
 //----------------------
 
-/// evaluate after AW and W is finished at corresponding channel_id.
+/// evaluate at every cycle, by each AXI port
+#[unsafe(no_mangle)]
+unsafe extern "C" fn axi_tick(instance: u32, reset: u8) {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::AxiTick);
+    driver.tick();
+  })
+}
+
 #[unsafe(no_mangle)]
-unsafe extern "C" fn axi_write_highBandwidthPort(
-  channel_id: c_longlong,
-  awid: c_longlong,
-  awaddr: c_longlong,
-  awlen: c_longlong,
-  awsize: c_longlong,
-  awburst: c_longlong,
-  awlock: c_longlong,
-  awcache: c_longlong,
-  awprot: c_longlong,
-  awqos: c_longlong,
-  awregion: c_longlong,
-  // struct packed {bit [255:0][DLEN:0] data;
-  // bit [255:0][DLEN/8:0] strb; } payload
-  payload: *const SvBitVecVal,
+unsafe extern "C" fn axi_push_AW(
+  instance: u32,
+  reset: u8,
+  channel_id: u64,
+  data_width: u64,
+  awid: u64,
+  awaddr: u64,
+  awsize: u64,
+  awlen: u64,
+  awuser: u64,
+
+  mut ready: Out<'_, bool>,
 ) {
-  debug!(
-    "axi_write_highBandwidth (channel_id={channel_id}, awid={awid}, awaddr={awaddr:#x}, \
-  awlen={awlen}, awsize={awsize}, awburst={awburst}, awlock={awlock}, awcache={awcache}, \
-  awprot={awprot}, awqos={awqos}, awregion={awregion})"
-  );
-  TARGET.with(|driver| {
-    let (strobe, data) = load_from_payload(payload, driver.dlen);
-    driver.axi_write_high_bandwidth(awaddr as u32, awsize as u64, &strobe, data);
+  ready.set(false);
+  if reset != 0 {
+    return;
+  }
+  TARGET.with(instance, move |driver| {
+    let aw = AxiAddr {
+      id: awid,
+      addr: awaddr,
+      size: awsize,
+      len: awlen,
+      user: awuser,
+    };
+    driver.record_call(|| DpiCall::AxiPushAW { channel_id, data_width, aw: aw.clone() });
+    driver.axi_push_aw(channel_id, data_width, &aw);
   });
+  ready.set(true);
 }
 
-/// evaluate at AR fire at corresponding channel_id.
 #[unsafe(no_mangle)]
-unsafe extern "C" fn axi_read_highBandwidthPort(
-  channel_id: c_longlong,
-  arid: c_longlong,
-  araddr: c_longlong,
-  arlen: c_longlong,
-  arsize: c_longlong,
-  arburst: c_longlong,
-  arlock: c_longlong,
-  arcache: c_longlong,
-  arprot: c_longlong,
-  arqos: c_longlong,
-  arregion: c_longlong,
-  // struct packed {bit [255:0][DLEN:0] data; byte beats; } payload
-  payload: *mut SvBitVecVal,
+unsafe extern "C" fn axi_push_AR(
+  instance: u32,
+  reset: u8,
+  channel_id: u64,
+  data_width: u64,
+  arid: u64,
+  araddr: u64,
+  arsize: u64,
+  arlen: u64,
+  aruser: u64,
+
+  mut ready: Out<'_, bool>,
 ) {
-  debug!(
-    "axi_read_highBandwidth (channel_id={channel_id}, arid={arid}, araddr={araddr:#x}, \
-  arlen={arlen}, arsize={arsize}, arburst={arburst}, arlock={arlock}, arcache={arcache}, \
-  arprot={arprot}, arqos={arqos}, arregion={arregion})"
-  );
-  TARGET.with(|driver| {
-    let response = driver.axi_read_high_bandwidth(araddr as u32, arsize as u64);
-    fill_axi_read_payload(payload, driver.dlen, &response);
+  ready.set(false);
+  if reset != 0 {
+    return;
+  }
+  TARGET.with(instance, move |driver| {
+    let ar = AxiAddr {
+      id: arid,
+      addr: araddr,
+      size: arsize,
+      len: arlen,
+      user: aruser,
+    };
+    driver.record_call(|| DpiCall::AxiPushAR { channel_id, data_width, ar: ar.clone() });
+    driver.axi_push_ar(channel_id, data_width, &ar);
   });
+  ready.set(true);
 }
 
-/// evaluate at AR fire at corresponding channel_id.
 #[unsafe(no_mangle)]
-unsafe extern "C" fn axi_read_indexedAccessPort(
-  channel_id: c_longlong,
-  arid: c_longlong,
-  araddr: c_longlong,
-  arlen: c_longlong,
-  arsize: c_longlong,
-  arburst: c_longlong,
-  arlock: c_longlong,
-  arcache: c_longlong,
-  arprot: c_longlong,
-  arqos: c_longlong,
-  arregion: c_longlong,
-  // struct packed {bit [255:0][DLEN:0] data; byte beats; } payload
-  payload: *mut SvBitVecVal,
+unsafe extern "C" fn axi_push_W(
+  instance: u32,
+  reset: u8,
+  channel_id: u64,
+  data_width: u64,
+  wdata: InBV<'_, 1024>,
+  wstrb: InBV<'_, 128>,
+  wlast: u8,
+
+  mut ready: Out<'_, bool>,
 ) {
-  debug!(
-    "axi_read_indexed (channel_id={channel_id}, arid={arid}, araddr={araddr:#x}, \
-  arlen={arlen}, arsize={arsize}, arburst={arburst}, arlock={arlock}, arcache={arcache}, \
-  arprot={arprot}, arqos={arqos}, arregion={arregion})"
-  );
-  TARGET.with(|driver| {
-    let response = driver.axi_read_indexed(araddr as u32, arsize as u64);
-    fill_axi_read_payload(payload, driver.dlen, &response);
+  ready.set(false);
+  if reset != 0 {
+    return;
+  }
+  let wdata = &wdata.as_u8_slice()[..data_width as usize / 8];
+  let wstrb: Vec<bool> = StrbIterator {
+    strb: wstrb.as_slice(),
+    total_width: data_width as usize / 8,
+    current: 0,
+  }
+  .collect();
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::AxiPushW {
+      channel_id,
+      wdata: wdata.to_vec(),
+      wstrb: wstrb.clone(),
+      wlast: wlast != 0,
+    });
+    driver.axi_push_w(channel_id, wdata, &wstrb, wlast != 0);
   });
+  ready.set(true);
 }
 
-/// evaluate after AW and W is finished at corresponding channel_id.
 #[unsafe(no_mangle)]
-unsafe extern "C" fn axi_write_indexedAccessPort(
-  channel_id: c_longlong,
-  awid: c_longlong,
-  awaddr: c_longlong,
-  awlen: c_longlong,
-  awsize: c_longlong,
-  awburst: c_longlong,
-  awlock: c_longlong,
-  awcache: c_longlong,
-  awprot: c_longlong,
-  awqos: c_longlong,
-  awregion: c_longlong,
-  // struct packed {bit [255:0][32:0] data; bit [255:0][4:0] strb; } payload
-  payload: *const SvBitVecVal,
+unsafe extern "C" fn axi_pop_B(
+  instance: u32,
+  reset: u8,
+  channel_id: u64,
+  data_width: u64,
+  ret: *mut RetAxiPopB,
 ) {
-  debug!(
-    "axi_write_indexed (channel_id={channel_id}, awid={awid}, awaddr={awaddr:#x}, \
-  awlen={awlen}, awsize={awsize}, awburst={awburst}, awlock={awlock}, awcache={awcache}, \
-  awprot={awprot}, awqos={awqos}, awregion={awregion})"
-  );
-  let (strobe, data) = load_from_payload(payload, 32);
-  TARGET.with(|driver| {
-    driver.axi_write_indexed_access_port(awaddr as u32, awsize as u64, &strobe, data);
-  });
+  let ret = &mut *ret;
+  ret.bvalid = 0;
+  if reset != 0 {
+    return;
+  }
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::AxiPopB { channel_id });
+    let b = driver.axi_pop_b(channel_id);
+    driver.record_ret(&b);
+    if let Some(b) = b {
+      ret.bvalid = 1;
+      ret.bid = b.id;
+      ret.buser = b.user;
+    }
+  })
 }
 
+#[unsafe(no_mangle)]
+unsafe extern "C" fn axi_pop_R(
+  instance: u32,
+  reset: u8,
+  channel_id: u64,
+  data_width: u64,
+  ret: *mut RetAxiPopR,
+) {
+  let ret = &mut *ret;
+  ret.rvalid = 0;
+  if reset != 0 {
+    return;
+  }
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::AxiPopR { channel_id, data_width });
+    let r = driver.axi_pop_r(channel_id, data_width);
+    driver.record_ret(&r);
+    if let Some(r) = r {
+      ret.rvalid = 1;
+      ret.rlast = r.last as u8;
+      ret.rid = r.id;
+      ret.ruser = r.user;
+      ret.rdata[..r.data.len()].copy_from_slice(&r.data);
+    }
+  })
+}
+
+/// returns the id of the new instance, passed as the first argument of its other DPI calls
 #[unsafe(no_mangle)]
 unsafe extern "C" fn t1_cosim_init(
   elf_file: InStr<'_>,
@@ -222,32 +227,86 @@ unsafe extern "C" fn t1_cosim_init(
   lane_width: i32,
   vlen: i32,
   spike_isa: InStr<'_>,
-) {
-  dpi_common::setup_logger();
+) -> u32 {
+  let plusargs = dpi_common::plusarg::PlusArgs::from_args(crate::PLUSARGS);
+  dpi_common::record::set_sim_cycle(|| svdpi::get_time() / crate::CYCLE_PERIOD);
+  dpi_common::setup_logger(&plusargs, crate::get_t);
 
   let scope = SvScope::get_current().expect("failed to get scope in t1_cosim_init");
 
-  let args = OnlineArgs {
-    elf_file: elf_file.get().to_str().unwrap().into(),
-    rtl_event_file: None,
-    dlen: dlen as u32,
-    lane_width: lane_width as u32,
-    vlen: vlen as u32,
-    spike_isa: spike_isa.get().to_str().unwrap().into(),
-  };
-  TARGET.init(|| Driver::new(scope, &args));
+  TARGET.init(|instance| {
+    // other instances write their own files
+    let mem_model = match MemModelConfig::from_plusargs(&plusargs) {
+      MemModelConfig::Dramsim3 { cfg, path } => MemModelConfig::Dramsim3 {
+        cfg,
+        path: path.map(|path| instance_path(&path, instance)),
+      },
+      mem_model => mem_model,
+    };
+    let args = OnlineArgs {
+      elf_file: elf_file.get().to_str().unwrap().into(),
+      rtl_event_file: None,
+      dlen: dlen as u32,
+      lane_width: lane_width as u32,
+      vlen: vlen as u32,
+      spike_isa: spike_isa.get().to_str().unwrap().into(),
+      sim_result_path: instance_path(&plusargs.get_path("t1_sim_result_path").unwrap(), instance),
+      mem_model,
+      axi_monitor: AxiMonitorConfig::from_plusargs(&plusargs),
+    };
+
+    let recorder = plusargs.get_path("t1_dpi_record_path").map(|path| {
+      let recorder = DpiRecorder::create(&instance_path(&path, instance)).unwrap();
+      recorder.call(
+        crate::get_t(),
+        &DpiCall::Init {
+          elf_file: args.elf_file.clone(),
+          dlen: args.dlen,
+          lane_width: args.lane_width,
+          vlen: args.vlen,
+          spike_isa: args.spike_isa.clone(),
+          mem_model: args.mem_model.clone(),
+          axi_monitor: args.axi_monitor.clone(),
+        },
+      );
+      recorder
+    });
+
+    let mut driver = Driver::new(Some(scope), &args);
+    driver.recorder = recorder;
+    driver.timeline = plusargs
+      .get_path("t1_timeline_path")
+      .map(|path| Timeline::create(&instance_path(&path, instance)).unwrap());
+    driver
+  })
 }
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn t1_cosim_set_timeout(timeout: u64) {
-  TARGET.with(|driver| driver.set_timeout(timeout));
+unsafe extern "C" fn t1_cosim_set_timeout(instance: u32, timeout: u64) {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::SetTimeout { timeout });
+    driver.set_timeout(timeout)
+  });
 }
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn t1_cosim_final() {
-  TARGET.with_optional(|driver| {
+unsafe extern "C" fn t1_cosim_final(instance: u32) {
+  TARGET.with_optional(instance, |driver| {
     if let Some(driver) = driver {
-      dpi_common::util::write_perf_json("t1emu", crate::get_t(), driver.success, &driver.meta);
+      if let Some(recorder) = &driver.recorder {
+        recorder.flush();
+      }
+      if let Some(timeline) = &mut driver.timeline {
+        timeline.finish();
+      }
+      dpi_common::util::write_perf_json(
+        &driver.sim_result_path,
+        "t1emu",
+        crate::get_t(),
+        driver.success,
+        &driver.meta,
+        &driver.stats,
+      );
     } else {
       error!("'sim_result.json' generation skipped due to panic in DPI side");
     }
@@ -260,31 +319,49 @@ unsafe extern "C" fn t1_cosim_final() {
 ///   255 : quit successfully
 ///   otherwise : error
 #[unsafe(no_mangle)]
-unsafe extern "C" fn t1_cosim_watchdog() -> u8 {
-  TARGET.with(|driver| driver.watchdog())
+unsafe extern "C" fn t1_cosim_watchdog(instance: u32) -> u8 {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::Watchdog);
+    let status = driver.watchdog();
+    driver.record_ret(&status);
+    if status != WATCHDOG_CONTINUE {
+      // the simulation ends without t1_cosim_final on timeout
+      if let Some(recorder) = &driver.recorder {
+        recorder.flush();
+      }
+      if let Some(timeline) = &mut driver.timeline {
+        timeline.finish();
+      }
+    }
+    status
+  })
 }
 
 /// evaluate at instruction queue is not empty
 /// arg issue will be type cast from a struct to svBitVecVal*(uint32_t*)
 #[unsafe(no_mangle)]
-unsafe extern "C" fn issue_vector_instruction(issue_dst: *mut SvBitVecVal) {
-  TARGET.with(|driver| {
+unsafe extern "C" fn issue_vector_instruction(instance: u32, issue_dst: *mut SvBitVecVal) {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::IssueVectorInstruction);
     let issue = driver.issue_instruction();
+    driver.record_ret(&issue);
     *(issue_dst as *mut IssueData) = issue;
   });
 }
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn retire_vector_instruction(retire_src: *const SvBitVecVal) {
+unsafe extern "C" fn retire_vector_instruction(instance: u32, retire_src: *const SvBitVecVal) {
   let retire = &*(retire_src as *const Retire);
-  TARGET.with(|driver| {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::RetireVectorInstruction(*retire));
     driver.retire_instruction(retire);
   });
 }
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn retire_vector_mem(dummy: *const SvBitVecVal) {
-  TARGET.with(|driver| {
+unsafe extern "C" fn retire_vector_mem(instance: u32, dummy: *const SvBitVecVal) {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::RetireVectorMem);
     driver.retire_memory();
   });
 }
@@ -1,6 +1,10 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use dpi_common::util::MetaConfig;
+use dpi_common::axi::{AxiAddr, AxiB, AxiR, AxiSlave};
+use dpi_common::axi_monitor::{AxiMonitor, AxiMonitorConfig};
+use dpi_common::mem_model::{MemIdent, MemModelConfig, MemoryModel};
+use dpi_common::record::DpiRecorder;
+use dpi_common::util::{MetaConfig, SimStats};
 use spike_rs::runner::SpikeRunner;
 use spike_rs::runner::{MEM_SIZE, SpikeArgs};
 use spike_rs::spike_event::MemAccessRecord;
@@ -10,6 +14,8 @@ use tracing::{debug, error, info, trace};
 
 use crate::dpi::*;
 use crate::get_t;
+use crate::replay::DpiCall;
+use crate::timeline::Timeline;
 use svdpi::SvScope;
 
 struct ShadowMem {
@@ -34,64 +40,13 @@ impl ShadowMem {
     &self.mem[start..end]
   }
 
-  // size: 1 << arsize
-  // bus_size: AXI bus width in bytes
-  // return: Vec<u8> with len=bus_size
-  // if size < bus_size, the result is padded due to AXI narrow transfer rules
-  pub fn read_mem_axi(&self, addr: u32, size: u32, bus_size: u32) -> Vec<u8> {
-    assert!(
-      addr % size == 0 && bus_size % size == 0,
-      "unaligned access addr={addr:#x} size={size}B dlen={bus_size}B"
-    );
-
-    let data = self.read_mem(addr, size);
-    if size < bus_size {
-      // narrow
-      let mut data_padded = vec![0; bus_size as usize];
-      let start = (addr % bus_size) as usize;
-      let end = start + data.len();
-      data_padded[start..end].copy_from_slice(data);
-
-      data_padded
-    } else {
-      // normal
-      data.to_vec()
-    }
-  }
-
-  // size: 1 << awsize
-  // bus_size: AXI bus width in bytes
-  // masks: write strokes, len=bus_size
-  // data: write data, len=bus_size
-  pub fn write_mem_axi(
-    &mut self,
-    addr: u32,
-    size: u32,
-    bus_size: u32,
-    masks: &[bool],
-    data: &[u8],
-  ) {
-    assert!(
-      addr % size == 0 && bus_size % size == 0,
-      "unaligned write access addr={addr:#x} size={size}B dlen={bus_size}B"
-    );
-
-    // handle strb=0 AXI payload
-    if !masks.iter().any(|&x| x) {
-      trace!("Mask 0 write detect");
-      return;
-    }
-
-    // TODO: we do not check strobe is compatible with (addr, awsize)
-    let addr_align = addr & ((!bus_size) + 1);
-
-    let bus_size = bus_size as usize;
-    assert_eq!(bus_size, masks.len());
-    assert_eq!(bus_size, data.len());
-
-    for i in 0..bus_size {
-      if masks[i] {
-        self.mem[addr_align as usize + i] = data[i];
+  /// Write `data` to `addr`, only the bytes enabled by `strobe`
+  pub fn write_masked(&mut self, addr: u32, data: &[u8], strobe: &[bool]) {
+    assert_eq!(data.len(), strobe.len());
+    let start = addr as usize;
+    for (i, (&byte, &enabled)) in data.iter().zip(strobe).enumerate() {
+      if enabled {
+        self.mem[start + i] = byte;
       }
     }
   }
@@ -115,21 +70,33 @@ pub(crate) struct OnlineArgs {
 
   /// ISA config
   pub spike_isa: String,
+
+  /// Path to write the simulation result
+  pub sim_result_path: PathBuf,
+
+  /// Latency model of the memory behind the AXI ports
+  pub mem_model: MemModelConfig,
+
+  /// Checks of the AXI protocol
+  pub axi_monitor: AxiMonitorConfig,
 }
 
 pub(crate) struct Driver {
   spike_runner: SpikeRunner,
 
-  // SvScope from t1_cosim_init
+  // SvScope from t1_cosim_init, absent when replaying
   #[allow(unused)]
-  scope: SvScope,
+  scope: Option<SvScope>,
+
+  pub(crate) recorder: Option<DpiRecorder>,
+  pub(crate) timeline: Option<Timeline>,
 
   pub(crate) meta: MetaConfig,
+  pub(crate) stats: SimStats,
+  pub(crate) sim_result_path: PathBuf,
 
   pub(crate) success: bool,
 
-  pub(crate) dlen: u32,
-
   timeout: u64,
 
   // driver state
@@ -138,10 +105,15 @@ pub(crate) struct Driver {
   vector_lsu_count: u8,
 
   shadow_mem: ShadowMem,
+
+  // AXI transactions, served from `shadow_mem` with the latency of `mem_model`
+  axi: AxiSlave,
+  mem_model: Box<dyn MemoryModel + Send>,
+  next_tick: u64,
 }
 
 impl Driver {
-  pub(crate) fn new(scope: SvScope, args: &OnlineArgs) -> Self {
+  pub(crate) fn new(scope: Option<SvScope>, args: &OnlineArgs) -> Self {
     let elf_file = Path::new(&args.elf_file);
     let mut self_ = Self {
       spike_runner: SpikeRunner::new(
@@ -157,6 +129,8 @@ impl Driver {
       ),
 
       scope,
+      recorder: None,
+      timeline: None,
       success: false,
 
       meta: MetaConfig {
@@ -165,77 +139,123 @@ impl Driver {
         lane_width: args.lane_width,
         isa: args.spike_isa.clone(),
         elf_file: Some(args.elf_file.clone()),
-        dramsim3_enabled: false,
+        dramsim3_enabled: matches!(args.mem_model, MemModelConfig::Dramsim3 { .. }),
       },
+      stats: SimStats::new(),
+      sim_result_path: args.sim_result_path.clone(),
 
-      dlen: args.dlen,
       timeout: 0,
       last_commit_cycle: 0,
 
       issued: 0,
       vector_lsu_count: 0,
       shadow_mem: ShadowMem::new(),
+
+      axi: AxiSlave::new(AxiMonitor::new(args.axi_monitor.clone(), crate::get_t)),
+      mem_model: args.mem_model.build(crate::get_sys_tck()),
+      next_tick: 0,
     };
-    self_.spike_runner.load_elf(elf_file).unwrap();
+
+    // instructions are counted as stepped in spike
+    self_.stats.insts = Some(Default::default());
+    self_.stats.issue_stalls = Some(0);
 
     load_elf_to_buffer(&mut self_.shadow_mem.mem, elf_file).unwrap();
     self_
   }
 
-  pub(crate) fn axi_read_high_bandwidth(&mut self, addr: u32, arsize: u64) -> AxiReadPayload {
-    let size = 1 << arsize;
-    let data = self.shadow_mem.read_mem_axi(addr, size, self.dlen / 8);
-    let data_hex = hex::encode(&data);
-    trace!(
-      "[{}] axi_read_high_bandwidth (addr={addr:#x}, size={size}, data={data_hex})",
-      get_t()
-    );
-    AxiReadPayload { data }
+  pub(crate) fn record_call(&self, call: impl FnOnce() -> DpiCall) {
+    if let Some(recorder) = &self.recorder {
+      recorder.call(get_t(), &call());
+    }
   }
 
-  pub(crate) fn axi_write_high_bandwidth(
-    &mut self,
-    addr: u32,
-    awsize: u64,
-    strobe: &[bool],
-    data: &[u8],
-  ) {
-    let size = 1 << awsize;
-
-    self.shadow_mem.write_mem_axi(addr, size, self.dlen / 8, &strobe, data);
-    let data_hex = hex::encode(data);
-    trace!(
-      "[{}] axi_write_high_bandwidth (addr={addr:#x}, size={size}, data={data_hex})",
-      get_t()
-    );
+  pub(crate) fn record_ret(&self, ret: &impl serde::Serialize) {
+    if let Some(recorder) = &self.recorder {
+      recorder.ret(ret);
+    }
   }
 
-  pub(crate) fn axi_read_indexed(&mut self, addr: u32, arsize: u64) -> AxiReadPayload {
-    let size = 1 << arsize;
-    assert!(size <= 4);
-    let data = self.shadow_mem.read_mem_axi(addr, size, 4);
-    let data_hex = hex::encode(&data);
-    trace!(
-      "[{}] axi_read_indexed (addr={addr:#x}, size={size}, data={data_hex})",
-      get_t()
-    );
-    AxiReadPayload { data }
+  fn record_timeline(&mut self, f: impl FnOnce(&mut Timeline, u64)) {
+    if let Some(timeline) = &mut self.timeline {
+      f(timeline, get_t());
+    }
   }
 
-  pub(crate) fn axi_write_indexed_access_port(
-    &mut self,
-    addr: u32,
-    awsize: u64,
-    strobe: &[bool],
-    data: &[u8],
-  ) {
-    let size = 1 << awsize;
-    self.shadow_mem.write_mem_axi(addr, size, 4, strobe, data);
-    let data_hex = hex::encode(data);
-    trace!(
-      "[{}] axi_write_indexed_access_port (addr={addr:#x}, size={size}, data={data_hex})",
-      get_t()
-    );
+  /// Ticking the memory model
+  pub(crate) fn tick(&mut self) {
+    // It's called by each AXI port at every cycle,
+    // so here we check if we have already ticked this cycle.
+    let tick = get_t();
+    if self.next_tick != 0 && tick > self.next_tick {
+      panic!("Skipped a tick: {} -> {}", self.next_tick, tick);
+    }
+
+    if self.next_tick > tick {
+      assert!(self.next_tick == tick + 1);
+      return;
+    }
+    self.next_tick = tick + 1;
+
+    // The shadow memory is written once the request is sent, the model only delays responses.
+    // Reads take the content when they are responded.
+    let shadow_mem = &mut self.shadow_mem;
+    let mem_model = &mut self.mem_model;
+    let stats = &mut self.stats;
+    self.axi.send_writes(|mem_id, w| {
+      let (addr, len) = (w.addr() as u32, w.total_size() as u32);
+      shadow_mem.write_masked(addr, w.data(), w.strb());
+      stats.count_write(w.strb());
+      trace!(
+        "[{tick}] axi write (addr={addr:#x}, len={len}, data={})",
+        hex::encode(w.data())
+      );
+      mem_model.push(MemIdent { id: mem_id, addr, len, is_write: true });
+      true
+    });
+    self.axi.send_reads(|mem_id, r| {
+      let (addr, len) = (r.addr() as u32, r.total_size() as u32);
+      stats.count_read(len as usize);
+      mem_model.push(MemIdent { id: mem_id, addr, len, is_write: false });
+      true
+    });
+
+    self.mem_model.tick();
+
+    while let Some(resp) = self.mem_model.pop() {
+      if resp.is_write {
+        self.axi.complete_write(resp.id, Ok(()));
+      } else {
+        let data = self.shadow_mem.read_mem(resp.addr, resp.len);
+        trace!(
+          "[{tick}] axi read (addr={:#x}, len={}, data={})",
+          resp.addr,
+          resp.len,
+          hex::encode(data)
+        );
+        self.axi.complete_read(resp.id, Ok(data.to_vec()));
+      }
+    }
+  }
+
+  pub(crate) fn axi_push_aw(&mut self, channel_id: u64, data_width: u64, aw: &AxiAddr) {
+    self.axi.push_aw(channel_id, data_width, aw);
+  }
+
+  pub(crate) fn axi_push_ar(&mut self, channel_id: u64, data_width: u64, ar: &AxiAddr) {
+    self.axi.push_ar(channel_id, data_width, ar);
+  }
+
+  pub(crate) fn axi_push_w(&mut self, channel_id: u64, wdata: &[u8], wstrb: &[bool], wlast: bool) {
+    self.axi.push_w(channel_id, wdata, wstrb, wlast);
+  }
+
+  pub(crate) fn axi_pop_b(&mut self, channel_id: u64) -> Option<AxiB> {
+    self.axi.pop_b(channel_id)
+  }
+
+  pub(crate) fn axi_pop_r(&mut self, channel_id: u64, data_width: u64) -> Option<AxiR> {
+    self.axi.pop_r(channel_id, data_width)
   }
 
   pub(crate) fn set_timeout(&mut self, timeout: u64) {
@@ -274,6 +294,7 @@ impl Driver {
       // step until the instruction is a vector / exit / scalar load / scalar store
       // push into the commit queue and return
       let se = self.spike_runner.spike_step();
+      self.count_inst(&se);
       if se.is_v() || se.is_vfence() || se.is_load() || se.is_store() {
         self.spike_runner.commit_queue.push_front(se.clone());
         return se;
@@ -281,6 +302,25 @@ impl Driver {
     }
   }
 
+  fn count_inst(&mut self, se: &SpikeEvent) {
+    let insts = self.stats.insts.as_mut().unwrap();
+    if se.is_v() {
+      insts.vector += 1;
+    } else {
+      insts.scalar += 1;
+    }
+    if se.is_load() || se.is_vload() {
+      insts.loads += 1;
+    }
+    if se.is_store() || se.is_vstore() {
+      insts.stores += 1;
+    }
+  }
+
+  fn count_issue_stall(&mut self) {
+    *self.stats.issue_stalls.as_mut().unwrap() += 1;
+  }
+
   pub(crate) fn issue_instruction(&mut self) -> IssueData {
     loop {
       let se = self.step();
@@ -288,6 +328,7 @@ impl Driver {
       return if se.is_vfence() {
         if self.spike_runner.commit_queue.len() == 1 {
           // earlier instructions are committed
+          self.record_timeline(|timeline, cycle| timeline.fence_pass(cycle));
           if se.is_exit() {
             info!(
               "[{}] seeing an exit instruction on {:08x}, sending ISSUE_EXIT",
@@ -307,6 +348,8 @@ impl Driver {
             self.spike_runner.commit_queue.len()
           );
           // waiting for earlier instructions to be committed
+          self.count_issue_stall();
+          self.record_timeline(|timeline, cycle| timeline.fence_wait(cycle, &se));
           IssueData { meta: ISSUE_FENCE, ..Default::default() }
         }
       } else if se.is_load() || se.is_store() {
@@ -319,10 +362,13 @@ impl Driver {
         );
         if self.vector_lsu_count == 0 {
           // issue scalar load / store
+          self.record_timeline(|timeline, cycle| timeline.fence_pass(cycle));
           self.shadow_mem.apply_writes(&se.mem_access_record);
           self.spike_runner.commit_queue.pop_front();
           continue;
         } else {
+          self.count_issue_stall();
+          self.record_timeline(|timeline, cycle| timeline.fence_wait(cycle, &se));
           IssueData { meta: ISSUE_NOT_VALID, ..Default::default() }
         }
       } else {
@@ -338,6 +384,7 @@ impl Driver {
           self.vector_lsu_count,
         );
         self.issued += 1;
+        self.record_timeline(|timeline, cycle| timeline.issue(cycle, &se));
 
         IssueData {
           instruction_bits: se.inst_bits,
@@ -353,18 +400,31 @@ impl Driver {
     }
   }
 
-  pub(crate) fn retire_instruction(&mut self, _: &Retire) {
+  pub(crate) fn retire_instruction(&mut self, retire: &Retire) {
     let se = self.spike_runner.commit_queue.back().unwrap();
 
+    if let Err(e) = se
+      .check_retire_rd(retire.write_rd != 0, retire.rd, retire.data)
+      .and_then(|_| se.check_retire_vxsat(retire.vxsat != 0))
+    {
+      panic!(
+        "[{}] scalar result mismatch on retiring {}: {e}",
+        get_t(),
+        se.describe_insn()
+      );
+    }
+
     // todo: filter all vector instruction.
     self.shadow_mem.apply_writes(&se.mem_access_record);
 
     self.spike_runner.commit_queue.pop_back();
     self.last_commit_cycle = get_t();
+    self.record_timeline(|timeline, cycle| timeline.retire(cycle));
   }
 
   pub(crate) fn retire_memory(&mut self) {
     self.vector_lsu_count -= 1;
     info!("[{}] retire, count={}", get_t(), self.vector_lsu_count);
+    self.record_timeline(|timeline, cycle| timeline.retire_memory(cycle));
   }
 }
@@ -1,14 +1,73 @@
 // t1emu is in maintainance mode, don't bother to fix it
 #![allow(unsafe_op_in_unsafe_fn)]
 
+use dpi_common::plusarg::PlusArgDef;
+
 pub mod dpi;
 pub mod drive;
+pub mod replay;
+pub mod timeline;
+
+/// All plusargs of the emulator, its own and the shared ones
+pub const PLUSARGS: &[&[PlusArgDef]] = &[
+  EMU_PLUSARGS,
+  &dpi_common::LOG_PLUSARGS,
+  &[dpi_common::record::RECORD_PLUSARG],
+  &[timeline::TIMELINE_PLUSARG],
+  &dpi_common::axi_monitor::AXI_MONITOR_PLUSARGS,
+  &dpi_common::mem_model::MEM_MODEL_PLUSARGS,
+];
+
+// keep in sync with the plusargs parsed in TestBench.verbatimModule
+const EMU_PLUSARGS: &[PlusArgDef] = &[
+  PlusArgDef::path("t1_elf_file", "path to elf file").required().rtl(),
+  PlusArgDef::path(
+    "t1_wave_path",
+    "path to wave dump file, if trace is enabled",
+  )
+  .rtl(),
+  PlusArgDef::int("t1_timeout", "max cycle between two V inst retire").default("1000000").rtl(),
+  PlusArgDef::int(
+    "t1_debug_global_timeout",
+    "max cycle for whole simulation, for debug only",
+  )
+  .rtl(),
+  PlusArgDef::int(
+    "t1_debug_dump_start",
+    "cycle when wave dump starts, for debug only",
+  )
+  .rtl(),
+  PlusArgDef::int(
+    "t1_debug_dump_end",
+    "cycle when wave dump ends, for debug only",
+  )
+  .rtl(),
+  PlusArgDef::int(
+    "t1_dev_rtl_event_off",
+    "set to 1 to disable rtl event recording",
+  )
+  .rtl(),
+  PlusArgDef::path("t1_dev_rtl_event_path", "path to rtl event jsonl file").rtl(),
+  PlusArgDef::int(
+    "t1_dev_vrf_snapshot",
+    "set to 1 to log the VRF each time the vector unit becomes idle",
+  )
+  .rtl(),
+  PlusArgDef::int("t1_dev_final_state", "set to 1 to log the VRF at exit").rtl(),
+  PlusArgDef::path("t1_sim_result_path", "path to the simulation result")
+    .default("sim_result.json"),
+];
 
 // keep in sync with TestBench.verbatimModule
 // the value is measured in simulation time unit
 pub const CYCLE_PERIOD: u64 = 20000;
 
-/// get cycle
+/// Real system tCK in ns
+pub fn get_sys_tck() -> f64 {
+  0.8 // 1.25 GHz
+}
+
+/// get cycle, or the recorded one when replaying DPI calls
 pub fn get_t() -> u64 {
-  svdpi::get_time() / CYCLE_PERIOD
+  dpi_common::record::cycle()
 }
@@ -0,0 +1,166 @@
+//! DPI calls recorded with `+t1_dpi_record_path`, and their replay into a `Driver`
+//! without the simulator. See `dpi_common::record` for the format.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use dpi_common::axi::AxiAddr;
+use dpi_common::axi_monitor::AxiMonitorConfig;
+use dpi_common::mem_model::MemModelConfig;
+use dpi_common::record::strobe;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dpi::Retire;
+use crate::drive::{Driver, OnlineArgs};
+use crate::timeline::Timeline;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "call")]
+pub(crate) enum DpiCall {
+  #[serde(rename = "t1_cosim_init")]
+  Init {
+    elf_file: String,
+    dlen: u32,
+    lane_width: u32,
+    vlen: u32,
+    spike_isa: String,
+    mem_model: MemModelConfig,
+    axi_monitor: AxiMonitorConfig,
+  },
+  #[serde(rename = "t1_cosim_set_timeout")]
+  SetTimeout { timeout: u64 },
+  #[serde(rename = "t1_cosim_watchdog")]
+  Watchdog,
+  #[serde(rename = "issue_vector_instruction")]
+  IssueVectorInstruction,
+  #[serde(rename = "retire_vector_instruction")]
+  RetireVectorInstruction(Retire),
+  #[serde(rename = "retire_vector_mem")]
+  RetireVectorMem,
+  #[serde(rename = "axi_tick")]
+  AxiTick,
+  #[serde(rename = "axi_push_AW")]
+  AxiPushAW {
+    channel_id: u64,
+    data_width: u64,
+    #[serde(flatten)]
+    aw: AxiAddr,
+  },
+  #[serde(rename = "axi_push_AR")]
+  AxiPushAR {
+    channel_id: u64,
+    data_width: u64,
+    #[serde(flatten)]
+    ar: AxiAddr,
+  },
+  #[serde(rename = "axi_push_W")]
+  AxiPushW {
+    channel_id: u64,
+    #[serde(with = "hex::serde")]
+    wdata: Vec<u8>,
+    #[serde(with = "strobe")]
+    wstrb: Vec<bool>,
+    wlast: bool,
+  },
+  #[serde(rename = "axi_pop_B")]
+  AxiPopB { channel_id: u64 },
+  #[serde(rename = "axi_pop_R")]
+  AxiPopR { channel_id: u64, data_width: u64 },
+}
+
+impl DpiCall {
+  pub(crate) fn online_args(&self) -> Option<OnlineArgs> {
+    match self {
+      DpiCall::Init {
+        elf_file,
+        dlen,
+        lane_width,
+        vlen,
+        spike_isa,
+        mem_model,
+        axi_monitor,
+      } => Some(OnlineArgs {
+        elf_file: elf_file.clone(),
+        rtl_event_file: None,
+        dlen: *dlen,
+        lane_width: *lane_width,
+        vlen: *vlen,
+        spike_isa: spike_isa.clone(),
+        // not written when replaying
+        sim_result_path: PathBuf::new(),
+        mem_model: match mem_model {
+          // output of dramsim3 is not kept
+          MemModelConfig::Dramsim3 { cfg, .. } => {
+            MemModelConfig::Dramsim3 { cfg: cfg.clone(), path: None }
+          }
+          mem_model => mem_model.clone(),
+        },
+        axi_monitor: axi_monitor.clone(),
+      }),
+      _ => None,
+    }
+  }
+}
+
+/// Replay a record into a new driver, check that it returns the recorded values.
+/// The timeline of the replay is written to `timeline`, if given.
+/// Returns the number of calls replayed.
+pub fn replay(record: &Path, timeline: Option<&Path>) -> anyhow::Result<u64> {
+  let mut driver: Option<Driver> = None;
+
+  let count = dpi_common::record::replay(record, |call: DpiCall| {
+    if let Some(args) = call.online_args() {
+      anyhow::ensure!(driver.is_none(), "driver is already initialized");
+      let mut new_driver = Driver::new(None, &args);
+      new_driver.timeline = timeline.map(Timeline::create).transpose()?;
+      driver = Some(new_driver);
+      return Ok(Value::Null);
+    }
+    let driver = driver.as_mut().context("driver is not initialized")?;
+
+    let ret = match call {
+      DpiCall::Init { .. } => unreachable!(),
+      DpiCall::SetTimeout { timeout } => {
+        driver.set_timeout(timeout);
+        Value::Null
+      }
+      DpiCall::Watchdog => serde_json::to_value(driver.watchdog())?,
+      DpiCall::IssueVectorInstruction => serde_json::to_value(driver.issue_instruction())?,
+      DpiCall::RetireVectorInstruction(retire) => {
+        driver.retire_instruction(&retire);
+        Value::Null
+      }
+      DpiCall::RetireVectorMem => {
+        driver.retire_memory();
+        Value::Null
+      }
+      DpiCall::AxiTick => {
+        driver.tick();
+        Value::Null
+      }
+      DpiCall::AxiPushAW { channel_id, data_width, aw } => {
+        driver.axi_push_aw(channel_id, data_width, &aw);
+        Value::Null
+      }
+      DpiCall::AxiPushAR { channel_id, data_width, ar } => {
+        driver.axi_push_ar(channel_id, data_width, &ar);
+        Value::Null
+      }
+      DpiCall::AxiPushW { channel_id, wdata, wstrb, wlast } => {
+        driver.axi_push_w(channel_id, &wdata, &wstrb, wlast);
+        Value::Null
+      }
+      DpiCall::AxiPopB { channel_id } => serde_json::to_value(driver.axi_pop_b(channel_id))?,
+      DpiCall::AxiPopR { channel_id, data_width } => {
+        serde_json::to_value(driver.axi_pop_r(channel_id, data_width))?
+      }
+    };
+    Ok(ret)
+  })?;
+
+  if let Some(timeline) = driver.as_mut().and_then(|driver| driver.timeline.as_mut()) {
+    timeline.finish();
+  }
+  Ok(count)
+}
@@ -0,0 +1,176 @@
+//! Issue and retire timeline of vector instructions, written with `+t1_timeline_path`
+//!
+//! The timeline has one JSON line per vector instruction, once it is retired (and its memory
+//! accesses are retired, for loads and stores), e.g.
+//! `{"kind":"vector","index":3,"pc":"0x80000010","disasm":"vle32.v v8, (a0)","vtype":208,"vl":16,
+//! "issue_cycle":120,"retire_cycle":151,"mem_retire_cycle":149}`.
+//! Scalar instructions held at issue by the vector unit (fences, and scalar memory accesses
+//! waiting for vector loads and stores) have a line of kind "fence", with the first cycle they
+//! waited and the cycle they passed. Instructions not retired at the end have null cycles.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context as _;
+use dpi_common::plusarg::PlusArgDef;
+use serde::Serialize;
+use spike_rs::spike_event::SpikeEvent;
+
+pub const TIMELINE_PLUSARG: PlusArgDef = PlusArgDef::path(
+  "t1_timeline_path",
+  "write the issue and retire timeline of vector instructions to this file",
+);
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum TimelineEntry {
+  Vector {
+    /// in the order of issue
+    index: u64,
+    pc: String,
+    disasm: String,
+    vtype: u32,
+    vl: u32,
+    issue_cycle: u64,
+    retire_cycle: Option<u64>,
+    /// only for vector loads and stores
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mem_retire_cycle: Option<Option<u64>>,
+  },
+  Fence {
+    pc: String,
+    disasm: String,
+    wait_start_cycle: u64,
+    pass_cycle: Option<u64>,
+  },
+}
+
+pub(crate) struct Timeline {
+  writer: BufWriter<File>,
+  next_index: u64,
+  /// issued instructions not written yet, by index
+  inflight: BTreeMap<u64, TimelineEntry>,
+  /// indices waiting for retire, in the order of issue
+  retire_queue: VecDeque<u64>,
+  /// indices of loads and stores waiting for memory retire, in the order of issue
+  mem_queue: VecDeque<u64>,
+  /// the instruction waiting at issue
+  fence: Option<TimelineEntry>,
+}
+
+impl Timeline {
+  pub(crate) fn create(path: &Path) -> anyhow::Result<Self> {
+    let file = File::create(path)
+      .with_context(|| format!("failed to create timeline '{}'", path.display()))?;
+    Ok(Timeline {
+      writer: BufWriter::new(file),
+      next_index: 0,
+      inflight: BTreeMap::new(),
+      retire_queue: VecDeque::new(),
+      mem_queue: VecDeque::new(),
+      fence: None,
+    })
+  }
+
+  pub(crate) fn issue(&mut self, cycle: u64, se: &SpikeEvent) {
+    let index = self.next_index;
+    self.next_index += 1;
+
+    let is_mem = se.is_vload() || se.is_vstore();
+    self.inflight.insert(
+      index,
+      TimelineEntry::Vector {
+        index,
+        pc: format!("{:#x}", se.pc),
+        disasm: se.disasm.clone(),
+        vtype: se.vtype,
+        vl: se.vl,
+        issue_cycle: cycle,
+        retire_cycle: None,
+        mem_retire_cycle: is_mem.then_some(None),
+      },
+    );
+    self.retire_queue.push_back(index);
+    if is_mem {
+      self.mem_queue.push_back(index);
+    }
+  }
+
+  pub(crate) fn retire(&mut self, cycle: u64) {
+    let Some(index) = self.retire_queue.pop_front() else {
+      return;
+    };
+    if let Some(TimelineEntry::Vector { retire_cycle, .. }) = self.inflight.get_mut(&index) {
+      *retire_cycle = Some(cycle);
+    }
+    self.write_done(index);
+  }
+
+  pub(crate) fn retire_memory(&mut self, cycle: u64) {
+    let Some(index) = self.mem_queue.pop_front() else {
+      return;
+    };
+    if let Some(TimelineEntry::Vector { mem_retire_cycle, .. }) = self.inflight.get_mut(&index) {
+      *mem_retire_cycle = Some(Some(cycle));
+    }
+    self.write_done(index);
+  }
+
+  /// The scalar instruction `se` is held at issue
+  pub(crate) fn fence_wait(&mut self, cycle: u64, se: &SpikeEvent) {
+    if self.fence.is_none() {
+      self.fence = Some(TimelineEntry::Fence {
+        pc: format!("{:#x}", se.pc),
+        disasm: se.disasm.clone(),
+        wait_start_cycle: cycle,
+        pass_cycle: None,
+      });
+    }
+  }
+
+  /// The scalar instruction held at issue, if any, passes
+  pub(crate) fn fence_pass(&mut self, cycle: u64) {
+    if let Some(mut fence) = self.fence.take() {
+      if let TimelineEntry::Fence { pass_cycle, .. } = &mut fence {
+        *pass_cycle = Some(cycle);
+      }
+      self.write(&fence);
+    }
+  }
+
+  /// Write the instructions not retired yet, and flush
+  pub(crate) fn finish(&mut self) {
+    if let Some(fence) = self.fence.take() {
+      self.write(&fence);
+    }
+    for entry in std::mem::take(&mut self.inflight).into_values() {
+      self.write(&entry);
+    }
+    self.retire_queue.clear();
+    self.mem_queue.clear();
+    self.writer.flush().expect("failed to flush timeline");
+  }
+
+  // write the instruction `index` if it's retired, and its memory accesses are retired
+  fn write_done(&mut self, index: u64) {
+    let done = matches!(
+      self.inflight.get(&index),
+      Some(TimelineEntry::Vector {
+        retire_cycle: Some(_),
+        mem_retire_cycle: None | Some(Some(_)),
+        ..
+      })
+    );
+    if done {
+      let entry = self.inflight.remove(&index).unwrap();
+      self.write(&entry);
+    }
+  }
+
+  fn write(&mut self, entry: &TimelineEntry) {
+    serde_json::to_writer(&mut self.writer, entry).expect("failed to write timeline");
+    self.writer.write_all(b"\n").expect("failed to write timeline");
+  }
+}
@@ -0,0 +1,22 @@
+//! Replay DPI calls recorded by `+t1_dpi_record_path` into the t1rocketemu driver
+//!
+//! usage: t1rocketemu-dpi-replay <record> [+t1_log_filter=...] [+t1_log_path=...] [+t1_log_format=...]
+
+use std::path::PathBuf;
+
+use dpi_common::plusarg::PlusArgs;
+
+fn main() -> anyhow::Result<()> {
+  let (plusargs, args): (Vec<String>, Vec<String>) =
+    std::env::args().skip(1).partition(|arg| arg.starts_with('+'));
+  let [record] = args.as_slice() else {
+    anyhow::bail!("usage: t1rocketemu-dpi-replay <record> [+t1_log_*=...]");
+  };
+
+  let plusargs = PlusArgs::parse(&[&dpi_common::LOG_PLUSARGS], &plusargs)?;
+  dpi_common::setup_logger(&plusargs, dpi_t1rocketemu::get_t);
+
+  let count = dpi_t1rocketemu::replay::replay(&PathBuf::from(record))?;
+  println!("{count} DPI calls replayed, all return values match the record");
+  Ok(())
+}
@@ -2,6 +2,7 @@
 #![allow(unused_variables)]
 
 use dpi_common::DpiTarget;
+use dpi_common::dpi_target::instance_path;
 use std::{
   ffi::CString,
   path::{Path, PathBuf},
@@ -11,12 +12,21 @@ use svdpi::{
   dpi::param::{InBV, InStr, Out},
 };
 use tempfile::TempDir;
-use tracing::{debug, error, info};
+use tracing::{error, info};
 
 use crate::{
-  drive::{Driver, IncompleteRead, IncompleteWrite, OnlineArgs},
-  interconnect::BusError,
+  drive::{Driver, OnlineArgs},
+  interconnect::{
+    clint::ClintConfig,
+    framebuffer::{FrameBufferConfig, PixelFormat},
+    memory_map::MemoryMap,
+    uart::UartConfig,
+  },
+  replay::DpiCall,
 };
+use dpi_common::axi::{AxiAddr, RetAxiPopB, RetAxiPopR, StrbIterator};
+use dpi_common::axi_monitor::AxiMonitorConfig;
+use dpi_common::record::DpiRecorder;
 
 // --------------------------
 // preparing data structures
@@ -24,43 +34,21 @@ use crate::{
 
 static TARGET: DpiTarget<Driver> = DpiTarget::new();
 
-/// Wstrb iterator
-struct StrbIterator<'a> {
-  strb: &'a [u32],
-  total_width: usize,
-  current: usize,
-}
-
-impl Iterator for StrbIterator<'_> {
-  type Item = bool;
-  fn next(&mut self) -> Option<Self::Item> {
-    if self.total_width == self.current {
-      return None;
-    }
-    assert!(self.total_width > self.current);
-
-    let slot = self.current / 32;
-    let bit = self.current % 32;
-    // The wstrb are transfered in small endian
-    let extracted = (self.strb[slot] >> bit & 1) != 0;
-    self.current += 1;
-    Some(extracted)
-  }
-}
-
 //----------------------
 // dpi functions
 //----------------------
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn axi_tick(reset: u8) {
-  TARGET.with(|driver| {
+unsafe extern "C" fn axi_tick(instance: u32, reset: u8) {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::AxiTick);
     driver.tick();
   })
 }
 
 #[unsafe(no_mangle)]
 unsafe extern "C" fn axi_push_AW(
+  instance: u32,
   reset: u8,
   channel_id: u64,
   data_width: u64,
@@ -77,24 +65,23 @@ unsafe extern "C" fn axi_push_AW(
     return;
   }
   // dbg!((awid, awaddr, awlen, awsize, awuser, data_width));
-  TARGET.with(move |target| {
-    target.update_commit_cycle();
-    let w = IncompleteWrite::new(awid, awaddr, awlen, awsize, awuser, data_width);
-    let fifo = target.incomplete_writes.entry(channel_id).or_default();
-    fifo.push_back(w);
-    debug!(
-      "[{}] Write initialized: channel_id={} id={} at=0x{:x}",
-      crate::get_t(),
-      channel_id,
-      awid,
-      awaddr
-    );
+  TARGET.with(instance, move |target| {
+    let aw = AxiAddr {
+      id: awid,
+      addr: awaddr,
+      size: awsize,
+      len: awlen,
+      user: awuser,
+    };
+    target.record_call(|| DpiCall::AxiPushAW { channel_id, data_width, aw: aw.clone() });
+    target.axi_push_aw(channel_id, data_width, &aw);
   });
   ready.set(true);
 }
 
 #[unsafe(no_mangle)]
 unsafe extern "C" fn axi_push_AR(
+  instance: u32,
   reset: u8,
   channel_id: u64,
   data_width: u64,
@@ -111,24 +98,23 @@ unsafe extern "C" fn axi_push_AR(
     return;
   }
   // dbg!((arid, araddr, arlen, arsize, aruser, data_width));
-  TARGET.with(move |target| {
-    target.update_commit_cycle();
-    let r = IncompleteRead::new(araddr, arlen, arsize, aruser, data_width);
-    let fifo = target.incomplete_reads.entry((channel_id, arid)).or_default();
-    fifo.push_back(r);
-    debug!(
-      "[{}] Read initialized: channel_id={} id={} at=0x{:x}",
-      crate::get_t(),
-      channel_id,
-      arid,
-      araddr
-    );
+  TARGET.with(instance, move |target| {
+    let ar = AxiAddr {
+      id: arid,
+      addr: araddr,
+      size: arsize,
+      len: arlen,
+      user: aruser,
+    };
+    target.record_call(|| DpiCall::AxiPushAR { channel_id, data_width, ar: ar.clone() });
+    target.axi_push_ar(channel_id, data_width, &ar);
   });
   ready.set(true);
 }
 
 #[unsafe(no_mangle)]
 unsafe extern "C" fn axi_push_W(
+  instance: u32,
   reset: u8,
   channel_id: u64,
   data_width: u64,
@@ -143,104 +129,57 @@ unsafe extern "C" fn axi_push_W(
     return;
   }
   let wdata = &wdata.as_u8_slice()[..data_width as usize / 8];
-  let wstrb = wstrb.as_slice();
-  TARGET.with(|target| {
-    target.update_commit_cycle();
-    // TODO: maybe we don't assert this, to allow same-cycle AW/W (when W is sequenced before AW)
-    let channel = target
-      .incomplete_writes
-      .get_mut(&(channel_id))
-      .expect("No inflight write with this ID found!");
-    let w = channel.iter_mut().find(|w| !w.ready()).expect("No inflight write with this ID found!");
-    let wstrbit = StrbIterator {
-      strb: wstrb,
-      total_width: data_width as usize / 8,
-      current: 0,
-    };
-    w.push(wdata, wstrbit, wlast != 0);
-    if wlast != 0 {
-      debug!(
-        "[{}] Write fully sequenced: channel_id={} id={}",
-        crate::get_t(),
-        channel_id,
-        w.id()
-      );
-    }
+  let wstrb: Vec<bool> = StrbIterator {
+    strb: wstrb.as_slice(),
+    total_width: data_width as usize / 8,
+    current: 0,
+  }
+  .collect();
+  TARGET.with(instance, |target| {
+    target.record_call(|| DpiCall::AxiPushW {
+      channel_id,
+      wdata: wdata.to_vec(),
+      wstrb: wstrb.clone(),
+      wlast: wlast != 0,
+    });
+    target.axi_push_w(channel_id, wdata, &wstrb, wlast != 0);
     ready.set(true);
   })
 }
 
-// Packed result buffer:
-// ret[0]: valid
-// ret[2..3]: BID
-// ret[4..8]: BUSER
-#[repr(C)]
-struct RetAxiPopB {
-  bvalid: u8,
-  _padding_0: u8,
-  bid: u16,
-  buser: u32,
-}
-
 #[unsafe(no_mangle)]
-unsafe extern "C" fn axi_pop_B(reset: u8, channel_id: u64, data_width: u64, ret: *mut RetAxiPopB) {
+unsafe extern "C" fn axi_pop_B(
+  instance: u32,
+  reset: u8,
+  channel_id: u64,
+  data_width: u64,
+  ret: *mut RetAxiPopB,
+) {
   let ret = unsafe { &mut *ret };
   ret.bvalid = 0;
   if reset != 0 {
     return;
   }
-  TARGET.with(|target| {
-    ret.bvalid = 0;
-    let fifo = match target.incomplete_writes.get_mut(&channel_id) {
-      Some(f) => f,
-      None => return,
-    };
-    // TODO: find later writes with different IDs
-    if fifo.front().as_ref().is_none_or(|w| !w.done()) {
-      return;
-    }
-    let w = fifo.pop_front().unwrap();
-    match w.resp() {
-      Ok(()) => {}
-      Err(BusError) => panic!(
-        "SIM ERROR: write bus error, cid={cid}, id={id}, addr=0x{addr:08x}, width={width}",
-        cid = channel_id,
-        id = w.id(),
-        addr = w.addr(),
-        width = w.width(),
-      ),
+  TARGET.with(instance, |target| {
+    target.record_call(|| DpiCall::AxiPopB { channel_id });
+    let b = target.axi_pop_b(channel_id);
+    target.record_ret(&b);
+    if let Some(b) = b {
+      ret.bvalid = 1;
+      ret.bid = b.id;
+      ret.buser = b.user;
     }
-    debug!(
-      "[{}] Write finalized: channel_id={} id={}",
-      crate::get_t(),
-      channel_id,
-      w.id()
-    );
-
-    ret.bvalid = 1;
-    ret.bid = w.id() as u16;
-    ret.buser = w.user() as u32;
-    return;
   })
 }
 
-// Packed result buffer:
-// ret[0]: valid
-// ret[1]: rlast
-// ret[2..3]: rid
-// ret[4..8]: ruser (in 32 bit)
-// ret[8..]: rdata
-#[repr(C)]
-struct RetAxiPopR {
-  rvalid: u8,
-  rlast: u8,
-  rid: u16,
-  ruser: u32,
-  rdata: [u8; 1024 / 8],
-}
-
 #[unsafe(no_mangle)]
-unsafe extern "C" fn axi_pop_R(reset: u8, channel_id: u64, data_width: u64, ret: *mut RetAxiPopR) {
+unsafe extern "C" fn axi_pop_R(
+  instance: u32,
+  reset: u8,
+  channel_id: u64,
+  data_width: u64,
+  ret: *mut RetAxiPopR,
+) {
   let ret = unsafe { &mut *ret };
 
   ret.rvalid = 0;
@@ -248,53 +187,21 @@ unsafe extern "C" fn axi_pop_R(reset: u8, channel_id: u64, data_width: u64, ret:
     return;
   }
 
-  TARGET.with(|target| {
-    ret.rvalid = 0;
-    for (&(cid, id), fifo) in target.incomplete_reads.iter_mut() {
-      if cid != channel_id {
-        continue;
-      }; // TODO: we actually wants two levels of HashMap
-      if let Some(r) = fifo.front_mut() {
-        if r.done() {
-          let rdata_buf = &mut ret.rdata[..data_width as usize / 8];
-          let (resp, last) = r.pop(rdata_buf);
-          match resp {
-            Ok(()) => {}
-            Err(BusError) => panic!(
-              "SIM ERROR: read bus error, cid={cid}, id={id}, addr=0x{addr:08x}, width={width}",
-              addr = r.addr(),
-              width = r.width()
-            ),
-          }
-          debug!(
-            "[{}] Read data: channel_id={} id={} content={:?}",
-            crate::get_t(),
-            channel_id,
-            id,
-            rdata_buf,
-          );
-
-          ret.rvalid = 1;
-          ret.rlast = last as u8;
-          ret.rid = id as u16;
-          ret.ruser = r.user() as u32;
-
-          if last {
-            debug!(
-              "[{}] Read finalized: channel_id={} id={}",
-              crate::get_t(),
-              channel_id,
-              id
-            );
-            fifo.pop_front();
-          }
-          return;
-        }
-      }
+  TARGET.with(instance, |target| {
+    target.record_call(|| DpiCall::AxiPopR { channel_id, data_width });
+    let r = target.axi_pop_r(channel_id, data_width);
+    target.record_ret(&r);
+    if let Some(r) = r {
+      ret.rvalid = 1;
+      ret.rlast = r.last as u8;
+      ret.rid = r.id;
+      ret.ruser = r.user;
+      ret.rdata[..r.data.len()].copy_from_slice(&r.data);
     }
   })
 }
 
+/// returns the id of the new instance, passed as the first argument of its other DPI calls
 #[unsafe(no_mangle)]
 unsafe extern "C" fn t1_cosim_init(
   elf_file: InStr<'_>,
@@ -304,8 +211,10 @@ unsafe extern "C" fn t1_cosim_init(
   spike_isa: InStr<'_>,
   dramsim3_cfg: InStr<'_>,
   dramsim3_path: InStr<'_>,
-) {
-  dpi_common::setup_logger();
+) -> u32 {
+  let plusargs = dpi_common::plusarg::PlusArgs::from_args(crate::PLUSARGS);
+  dpi_common::record::set_sim_cycle(|| svdpi::get_time() / crate::CYCLE_PERIOD);
+  dpi_common::setup_logger(&plusargs, crate::get_t);
 
   let scope = SvScope::get_current().expect("failed to get scope in t1_cosim_init");
   let embedded_cfg_path: CString;
@@ -313,39 +222,99 @@ unsafe extern "C" fn t1_cosim_init(
   let dramsim3_cfg_str = dramsim3_cfg.get().to_str().unwrap();
   let dramsim3_path_str = dramsim3_path.get().to_str().unwrap();
 
-  let temp_dramsim3_path: PathBuf;
-  let run_path = if dramsim3_path_str.is_empty() {
-    let ds3_path = TempDir::new().expect("Failed to create dramsim3 runtime dir");
-    temp_dramsim3_path = ds3_path.path().into();
-    std::mem::forget(ds3_path);
-    &temp_dramsim3_path
-  } else {
-    Path::new(dramsim3_path_str)
-  };
-  info!(
-    "DRAMsim3 enabled with config: {:?}, result: {:?}",
-    dramsim3_cfg_str, run_path
-  );
+  TARGET.init(|instance| {
+    // other instances write their own files
+    let run_path: PathBuf = if dramsim3_path_str.is_empty() {
+      let ds3_path = TempDir::new().expect("Failed to create dramsim3 runtime dir");
+      let path = ds3_path.path().into();
+      std::mem::forget(ds3_path);
+      path
+    } else {
+      instance_path(Path::new(dramsim3_path_str), instance)
+    };
+    info!(
+      "DRAMsim3 enabled with config: {:?}, result: {:?}",
+      dramsim3_cfg_str, run_path
+    );
 
-  let args = OnlineArgs {
-    elf_file: elf_file.get().to_str().unwrap().into(),
-    dlen: dlen as u32,
-    lane_width: lane_width as u32,
-    vlen: vlen as u32,
-    spike_isa: spike_isa.get().to_str().unwrap().into(),
-    dramsim3_cfg_path: Path::new(dramsim3_cfg_str),
-    dramsim3_run_path: &run_path,
-  };
+    let args = OnlineArgs {
+      elf_file: elf_file.get().to_str().unwrap().into(),
+      dlen: dlen as u32,
+      lane_width: lane_width as u32,
+      vlen: vlen as u32,
+      spike_isa: spike_isa.get().to_str().unwrap().into(),
+      dramsim3_cfg_path: Path::new(dramsim3_cfg_str),
+      dramsim3_run_path: &run_path,
+      sim_result_path: instance_path(&plusargs.get_path("t1_sim_result_path").unwrap(), instance),
+      mmio_event_path: instance_path(&plusargs.get_path("t1_mmio_event_path").unwrap(), instance),
+      axi_monitor: AxiMonitorConfig::from_plusargs(&plusargs),
+      memory_map: match plusargs.get_path("t1_memory_map") {
+        Some(path) => MemoryMap::load(&path).unwrap(),
+        None => MemoryMap::default(),
+      },
+      uart: UartConfig {
+        stdout: plusargs.get_bool("t1_uart_stdout"),
+        output: plusargs.get_path("t1_uart_output").map(|path| instance_path(&path, instance)),
+        input: plusargs.get_path("t1_uart_input"),
+      },
+      clint: ClintConfig::new(
+        plusargs.get_int("t1_mtime_freq").unwrap(),
+        crate::get_sys_tck(),
+      ),
+      framebuffer: FrameBufferConfig {
+        width: plusargs.get_int("t1_fb_width").unwrap() as u32,
+        height: plusargs.get_int("t1_fb_height").unwrap() as u32,
+        format: PixelFormat::from_name(plusargs.get_str("t1_fb_format").unwrap()).unwrap(),
+        video: plusargs.get_path("t1_fb_video").map(|path| instance_path(&path, instance)),
+        fps: plusargs.get_int("t1_fb_fps").unwrap() as u32,
+        hash_log: plusargs.get_path("t1_fb_hash_log").map(|path| instance_path(&path, instance)),
+      },
+    };
 
-  TARGET.init(|| Driver::new(scope, &args));
+    let recorder = plusargs.get_path("t1_dpi_record_path").map(|path| {
+      let recorder = DpiRecorder::create(&instance_path(&path, instance)).unwrap();
+      recorder.call(
+        crate::get_t(),
+        &DpiCall::Init {
+          elf_file: args.elf_file.clone(),
+          dlen: args.dlen,
+          lane_width: args.lane_width,
+          vlen: args.vlen,
+          spike_isa: args.spike_isa.clone(),
+          dramsim3_cfg: args.dramsim3_cfg_path.to_owned(),
+          axi_monitor: args.axi_monitor.clone(),
+          memory_map: args.memory_map.clone(),
+          uart: args.uart.clone(),
+          clint: args.clint.clone(),
+          framebuffer: args.framebuffer.clone(),
+        },
+      );
+      recorder
+    });
+
+    let mut driver = Driver::new(Some(scope), &args);
+    driver.recorder = recorder;
+    driver
+  })
 }
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn t1_cosim_final() {
-  TARGET.with_optional(|driver| {
+unsafe extern "C" fn t1_cosim_final(instance: u32) {
+  TARGET.with_optional(instance, |driver| {
     if let Some(driver) = driver {
+      if let Some(recorder) = &driver.recorder {
+        recorder.flush();
+      }
+      driver.console.flush();
       let success = driver.exit_flag.is_finish();
-      dpi_common::util::write_perf_json("t1rocketemu", crate::get_t(), success, &driver.meta);
+      dpi_common::util::write_perf_json(
+        &driver.sim_result_path,
+        "t1rocketemu",
+        crate::get_t(),
+        success,
+        &driver.meta,
+        &driver.stats,
+      );
     } else {
       error!("'sim_result.json' generation skipped due to panic in DPI side");
     }
@@ -353,8 +322,11 @@ unsafe extern "C" fn t1_cosim_final() {
 }
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn t1_cosim_set_timeout(timeout: u64) {
-  TARGET.with(|driver| driver.set_timeout(timeout));
+unsafe extern "C" fn t1_cosim_set_timeout(instance: u32, timeout: u64) {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::SetTimeout { timeout });
+    driver.set_timeout(timeout)
+  });
 }
 
 /// evaluate at every cycle
@@ -363,17 +335,42 @@ unsafe extern "C" fn t1_cosim_set_timeout(timeout: u64) {
 ///   255 : quit successfully
 ///   otherwise : error
 #[unsafe(no_mangle)]
-unsafe extern "C" fn t1_cosim_watchdog() -> u8 {
-  TARGET.with(|driver| driver.watchdog())
+unsafe extern "C" fn t1_cosim_watchdog(instance: u32) -> u8 {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::Watchdog);
+    let status = driver.watchdog();
+    driver.record_ret(&status);
+    if status != 0 {
+      // the simulation may end without t1_cosim_final
+      if let Some(recorder) = &driver.recorder {
+        recorder.flush();
+      }
+      driver.console.flush();
+    }
+    status
+  })
 }
 
 #[unsafe(no_mangle)]
-unsafe extern "C" fn get_resetvector(mut resetvector: Out<'_, u64>) {
-  TARGET.with(|driver| {
+unsafe extern "C" fn get_resetvector(instance: u32, mut resetvector: Out<'_, u64>) {
+  TARGET.with(instance, |driver| {
     resetvector.set(driver.e_entry);
   });
 }
 
+/// interrupt lines of the rocket core, evaluate at every cycle
+/// bit 0 : msip
+/// bit 1 : mtip
+#[unsafe(no_mangle)]
+unsafe extern "C" fn get_interrupts(instance: u32, mut interrupts: Out<'_, u8>) {
+  TARGET.with(instance, |driver| {
+    driver.record_call(|| DpiCall::GetInterrupts);
+    let value = driver.get_interrupts();
+    driver.record_ret(&value);
+    interrupts.set(value);
+  });
+}
+
 //--------------------------------
 // import functions and wrappers
 //--------------------------------
@@ -1,10 +1,19 @@
 use crate::get_t;
+use crate::interconnect::clint::{ClintConfig, ClintRef};
+use crate::interconnect::framebuffer::FrameBufferConfig;
+use crate::interconnect::memory_map::MemoryMap;
 use crate::interconnect::simctrl::ExitFlagRef;
+use crate::interconnect::uart::{Console, UartConfig};
 use crate::interconnect::{
-  AddressSpace, BusError, DRAMModel, MemReqPayload, MemRespPayload, RegularMemory,
-  create_emu_addrspace,
+  AddressSpace, MemReqPayload, MemRespPayload, RegularMemory, create_emu_addrspace,
 };
-use dpi_common::util::MetaConfig;
+use crate::replay::DpiCall;
+use dpi_common::axi::{AxiAddr, AxiB, AxiR, AxiSlave};
+use dpi_common::axi_monitor::{AxiMonitor, AxiMonitorConfig};
+use dpi_common::mem_model::DRAMModel;
+use dpi_common::record::DpiRecorder;
+use dpi_common::util::{MetaConfig, SimStats};
+use serde::Serialize;
 use svdpi::SvScope;
 
 use anyhow::Context;
@@ -13,9 +22,12 @@ use elf::{
   abi::{EM_RISCV, ET_EXEC, PT_LOAD, STT_FUNC},
   endian::LittleEndian,
 };
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::HashMap;
 use std::os::unix::fs::FileExt;
-use std::{fs, path::Path};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
 use tracing::{debug, error, trace};
 
 #[derive(Debug)]
@@ -47,277 +59,39 @@ pub struct OnlineArgs<'a> {
   /// DRAMsim3 configuartion and run-path (if any)
   pub dramsim3_cfg_path: &'a Path,
   pub dramsim3_run_path: &'a Path,
-}
-
-/// An incomplete memory write
-/// Keeps track of both the data filling and the request into
-/// AddressSpace itself
-#[derive(Debug)]
-pub struct IncompleteWrite {
-  id: u64,
-  addr: u64,
-  bursts: usize,
-  width: usize, // In bytes
-  user: u64,
-
-  /// Is this request already sent to the memory?
-  sent: bool,
-  /// Is this request processed by the memory?
-  done: Option<Result<(), BusError>>,
-
-  data: Vec<u8>,
-  strb: Vec<bool>,
-
-  // Used for transfers
-  bus_size: usize,
-}
-
-impl IncompleteWrite {
-  pub fn new(
-    awid: u64,
-    awaddr: u64,
-    awlen: u64,
-    awsize: u64,
-    awuser: u64,
-    data_width: u64,
-  ) -> IncompleteWrite {
-    let bus_size = data_width / 8;
-    let size = 1 << awsize;
-
-    assert!(
-      awaddr % size == 0 && bus_size % size == 0,
-      "SIM ERROR: unaligned write addr={awaddr:#x} size={size}B dlen={bus_size}B"
-    );
-
-    // TODO: narrow burst is actually supported, but not tested
-    assert!(
-      !(size < bus_size && awlen > 0),
-      "SIM ERROR: narrow burst not supported, axsize={awsize}, axlen={awlen}, data_width={data_width}"
-    );
-
-    assert!(
-      awaddr % 4096 + size * (awlen + 1) <= 4096,
-      "SIM ERROR: axi write transaction across 4KiB boundary, addr={awaddr:#x}, awsize={awsize}, awlen={awlen}",
-    );
-
-    let tsize = (size * (awlen + 1)) as usize;
-    let data = Vec::with_capacity(tsize);
-    let strb = Vec::with_capacity(tsize);
-
-    IncompleteWrite {
-      id: awid,
-      addr: awaddr,
-      bursts: awlen as usize + 1,
-      width: size as usize,
-      user: awuser,
-
-      sent: false,
-      done: None,
-      data,
-      strb,
-      bus_size: bus_size as usize,
-    }
-  }
-
-  /// Add an AXI W channel beat
-  pub fn push(&mut self, wdata: &[u8], wstrb: impl Iterator<Item = bool>, wlast: bool) {
-    assert_eq!(
-      wdata.len(),
-      self.bus_size,
-      "Mismatch data width across DPI calls"
-    );
-
-    let next_addr = self.addr as usize + self.data.len();
-    let wire_offset = next_addr % self.bus_size;
-    assert!(
-      wire_offset as usize + self.width <= self.bus_size,
-      "Sanity check for data width: IncompleteWrite::push"
-    );
-
-    self.data.extend(&wdata[wire_offset..wire_offset + self.width]);
-    self.strb.extend(wstrb.skip(wire_offset).take(self.width));
-
-    assert_eq!(self.data.len(), self.strb.len());
-    assert!(self.data.len() <= (self.bursts * self.width) as usize);
-
-    assert_eq!(wlast, self.ready());
-  }
-
-  /// Ready to send to mem
-  pub fn ready(&self) -> bool {
-    self.data.len() == (self.bursts * self.width) as usize
-  }
-
-  pub fn complete_ok(&mut self) {
-    assert!(self.done.is_none(), "Write transaction is already done");
-    self.done = Some(Ok(()));
-  }
-
-  pub fn complete_with_error(&mut self, e: BusError) {
-    assert!(self.done.is_none(), "Write transaction is already done");
-    self.done = Some(Err(e));
-  }
-
-  pub fn done(&self) -> bool {
-    self.done.is_some()
-  }
-
-  pub fn resp(&self) -> Result<(), BusError> {
-    self.done.as_ref().expect("Write transanction is not done").clone()
-  }
-
-  pub fn id(&self) -> u64 {
-    self.id
-  }
-
-  pub fn user(&self) -> u64 {
-    self.user
-  }
-
-  pub fn addr(&self) -> u64 {
-    self.addr
-  }
-
-  pub fn width(&self) -> usize {
-    self.width
-  }
-}
-
-/// An incomplete memory read
-/// Keeps track of both the data draining and the request into
-/// AddressSpace itself
-#[derive(Debug)]
-pub struct IncompleteRead {
-  addr: u64,
-  bursts: usize,
-  width: usize,
-  user: u64,
-
-  /// Is this request sent to memory?
-  sent: bool,
-  /// The number of bytes already returned to the RTL
-  returned: usize,
-  /// The fetched data. None if the response has not arrived yet
-  data: Option<Result<Vec<u8>, BusError>>,
-
-  // Used for transfers
-  bus_size: usize,
-}
-
-impl IncompleteRead {
-  pub fn new(araddr: u64, arlen: u64, arsize: u64, aruser: u64, data_width: u64) -> IncompleteRead {
-    let bus_size = data_width / 8;
-    let size = 1 << arsize;
-
-    assert!(
-      araddr % size == 0 && bus_size % size == 0,
-      "SIM ERROR: unaligned read addr={araddr:#x} size={size}B dlen={bus_size}B"
-    );
-
-    // TODO: narrow burst is actually supported, but not tested
-    assert!(
-      !(size < bus_size && arlen > 0),
-      "SIM ERROR: narrow burst not supported, axsize={arsize}, axlen={arlen}, data_width={data_width}"
-    );
-
-    assert!(
-      araddr % 4096 + size * (arlen + 1) <= 4096,
-      "SIM ERROR: axi read transaction across 4KiB boundary, addr={araddr:#x}, arsize={arsize}, arlen={arlen}",
-    );
-
-    IncompleteRead {
-      addr: araddr,
-      bursts: arlen as usize + 1,
-      width: size as usize,
-      user: aruser,
 
-      sent: false,
-      returned: 0,
-      data: None,
+  /// Path to write the simulation result
+  pub sim_result_path: PathBuf,
 
-      bus_size: bus_size as usize,
-    }
-  }
-
-  /// Drain one beat into the AXI R channel
-  ///
-  /// Returns true if this is the last beat in the response (rlast)
-  pub fn pop(&mut self, rdata_buf: &mut [u8]) -> (Result<(), BusError>, bool) {
-    assert_eq!(
-      rdata_buf.len(),
-      self.bus_size,
-      "Mismatch data width across DPI calls"
-    );
-
-    assert!(
-      self.returned < self.bursts * self.width,
-      "All data already taken by RTL"
-    );
-
-    let data = self
-      .data
-      .as_ref()
-      .expect("IncompleteRead::pop called on request that hasn't gotten its data!");
-
-    let resp = match data {
-      Err(e) => Err(e.clone()),
-      Ok(data) => {
-        // Find in-line offset
-        let result_offset = (self.addr as usize + self.returned) % self.bus_size;
-
-        let dst = &mut rdata_buf[result_offset..(result_offset + self.width)];
-        let src = &data[self.returned..(self.returned + self.width)];
-        dst.copy_from_slice(src);
-        Ok(())
-      }
-    };
-
-    self.returned += self.width;
-    assert!(self.returned <= self.bursts * self.width);
-
-    let last = self.returned == self.bursts * self.width;
-    (resp, last)
-  }
-
-  pub fn complete_with_data(&mut self, data: Vec<u8>) {
-    assert!(self.data.is_none(), "Read transaction is already done");
-    assert_eq!(
-      data.len(),
-      self.bursts * self.width,
-      "Transaction data width mismatch"
-    );
+  /// Path to write the events of the simctrl registers
+  pub mmio_event_path: PathBuf,
 
-    self.data = Some(Ok(data));
-  }
+  /// Devices of the address space
+  pub memory_map: MemoryMap,
 
-  pub fn complete_with_error(&mut self, e: BusError) {
-    assert!(self.data.is_none(), "Read transaction is already done");
-    self.data = Some(Err(e));
-  }
+  /// Output and input of the uart
+  pub uart: UartConfig,
 
-  pub fn done(&self) -> bool {
-    self.data.is_some()
-  }
+  /// Frequency of the clint mtime
+  pub clint: ClintConfig,
 
-  pub fn user(&self) -> u64 {
-    self.user
-  }
+  /// Geometry and outputs of the framebuffer
+  pub framebuffer: FrameBufferConfig,
 
-  pub fn addr(&self) -> u64 {
-    self.addr
-  }
-
-  pub fn width(&self) -> usize {
-    self.width
-  }
+  /// Checks of the AXI protocol
+  pub axi_monitor: AxiMonitorConfig,
 }
 
 pub(crate) struct Driver {
-  // SvScope from t1rocket_cosim_init
+  // SvScope from t1rocket_cosim_init, absent when replaying
   #[allow(unused)]
-  scope: SvScope,
+  scope: Option<SvScope>,
+
+  pub(crate) recorder: Option<DpiRecorder>,
 
   pub(crate) meta: MetaConfig,
+  pub(crate) stats: SimStats,
+  pub(crate) sim_result_path: PathBuf,
 
   pub(crate) dlen: u32,
   pub(crate) e_entry: u64,
@@ -329,22 +103,38 @@ pub(crate) struct Driver {
   addr_space: AddressSpace,
 
   pub(crate) exit_flag: ExitFlagRef,
+  pub(crate) console: Console,
+  clint: ClintRef,
 
-  /// (channel_id, id) -> data
-  pub(crate) incomplete_writes: BTreeMap<u64, VecDeque<IncompleteWrite>>,
-  pub(crate) incomplete_reads: BTreeMap<(u64, u64), VecDeque<IncompleteRead>>,
+  axi: AxiSlave,
 }
 
 impl Driver {
-  pub(crate) fn new(scope: SvScope, args: &OnlineArgs<'_>) -> Self {
-    let dram_model = DRAMModel::new(args.dramsim3_cfg_path, args.dramsim3_run_path);
-    let (mut addr_space, exit_flag) = create_emu_addrspace(Some(dram_model));
+  pub(crate) fn new(scope: Option<SvScope>, args: &OnlineArgs<'_>) -> Self {
+    let console = Console::new(&args.uart);
+    let clint = ClintRef::new(args.clint.clone());
+    let (mut addr_space, exit_flag) = create_emu_addrspace(
+      &args.memory_map,
+      &args.uart,
+      &console,
+      &clint,
+      &args.framebuffer,
+      &args.mmio_event_path,
+      || {
+        DRAMModel::new(
+          args.dramsim3_cfg_path,
+          args.dramsim3_run_path,
+          crate::get_sys_tck(),
+        )
+      },
+    );
     let e_entry =
       Self::load_elf(Path::new(&args.elf_file), &mut addr_space).expect("fail creating simulator");
     // pass e_entry to rocket
 
     Self {
       scope,
+      recorder: None,
 
       meta: MetaConfig {
         vlen: args.vlen,
@@ -352,8 +142,10 @@ impl Driver {
         lane_width: args.lane_width,
         isa: args.spike_isa.clone(),
         elf_file: Some(args.elf_file.clone()),
-        dramsim3_enabled: true,
+        dramsim3_enabled: args.memory_map.uses_dramsim3(),
       },
+      stats: SimStats::new(),
+      sim_result_path: args.sim_result_path.clone(),
 
       dlen: args.dlen,
       e_entry,
@@ -365,9 +157,10 @@ impl Driver {
       addr_space,
 
       exit_flag,
+      console,
+      clint,
 
-      incomplete_reads: BTreeMap::new(),
-      incomplete_writes: BTreeMap::new(),
+      axi: AxiSlave::new(AxiMonitor::new(args.axi_monitor.clone(), get_t)),
     }
   }
 
@@ -416,6 +209,18 @@ impl Driver {
     Ok(elf.ehdr.e_entry)
   }
 
+  pub(crate) fn record_call(&self, call: impl FnOnce() -> DpiCall) {
+    if let Some(recorder) = &self.recorder {
+      recorder.call(get_t(), &call());
+    }
+  }
+
+  pub(crate) fn record_ret(&self, ret: &impl Serialize) {
+    if let Some(recorder) = &self.recorder {
+      recorder.ret(ret);
+    }
+  }
+
   pub fn update_commit_cycle(&mut self) {
     self.last_commit_cycle = get_t();
   }
@@ -436,6 +241,8 @@ impl Driver {
     }
     self.next_tick = desired_tick + 1;
 
+    self.clint.tick(desired_tick);
+
     // Then this function handles the real ticking, which contains three steps:
     // 1. Send all requests
     // 2. Ticking the AddressSpace
@@ -443,91 +250,73 @@ impl Driver {
     // This way, memory accesses can be returned in the next cycle for peripherals with no latency
 
     // Allow sending multiple
-    for (cid, fifo) in self.incomplete_writes.iter_mut() {
-      // Always handled in-order, find first pending
-      let w = fifo.iter_mut().find(|w| !w.sent);
-      if w.as_ref().is_none_or(|w| !w.ready()) {
-        continue;
-      }
-      let w = w.unwrap();
-
-      // [16 bit W = 0][ 16 bit cid ][ 32 bit id ]
-      let mapped_id = cid << 32 | w.id;
-      let payload = MemReqPayload::Write(w.data.as_slice(), Some(w.strb.as_slice()));
-
+    let addr_space = &mut self.addr_space;
+    let stats = &mut self.stats;
+    self.axi.send_writes(|mem_id, w| {
+      let payload = MemReqPayload::Write(w.data(), Some(w.strb()));
       debug!(
         "[{}] Committing write: {:?} -> 0x{:x}",
         get_t(),
         payload,
-        w.addr
+        w.addr()
       );
-      if self.addr_space.req(
-        mapped_id,
-        w.addr as u32,
-        (w.bursts * w.width) as u32,
-        payload,
-      ) {
-        w.sent = true;
-      }
-    }
-
-    for ((cid, id), fifo) in self.incomplete_reads.iter_mut() {
-      let r = fifo.iter_mut().find(|w| !w.sent);
-      if r.is_none() {
-        continue;
+      let sent = addr_space.req(mem_id, w.addr() as u32, w.total_size() as u32, payload);
+      if sent {
+        stats.count_write(w.strb());
       }
-      let r = r.unwrap();
+      sent
+    });
 
-      // [16 bit W = 0][ 16 bit cid ][ 32 bit id ]
-      let mapped_id = 1 << 48 | cid << 32 | id;
+    self.axi.send_reads(|mem_id, r| {
       let payload = MemReqPayload::Read;
-      if self.addr_space.req(
-        mapped_id,
-        r.addr as u32,
-        (r.bursts * r.width) as u32,
-        payload,
-      ) {
-        r.sent = true;
+      let sent = addr_space.req(mem_id, r.addr() as u32, r.total_size() as u32, payload);
+      if sent {
+        stats.count_read(r.total_size());
       }
-    }
+      sent
+    });
 
     self.addr_space.tick();
 
-    while let Some((mapped_id, payload)) = self.addr_space.resp() {
-      let is_write = mapped_id >> 48 == 0;
-      let cid = mapped_id >> 32 & 0xFFFF;
-      let id = mapped_id & 0xFFFFFFFF;
-
-      if !is_write {
-        let r = self
-          .incomplete_reads
-          .get_mut(&(cid, id))
-          .and_then(|f| f.iter_mut().find(|r| !r.done()))
-          .expect("Returned read has no corresponding pending data");
-        assert!(r.sent);
-        match payload {
-          MemRespPayload::ReadBuffered(buf) => r.complete_with_data(buf.to_owned()),
-          MemRespPayload::ReadRegister(buf) => r.complete_with_data(Vec::from(buf)),
-          MemRespPayload::ReadError(e) => r.complete_with_error(e),
-          MemRespPayload::WriteAck | MemRespPayload::WriteError(_) => unreachable!(),
-        }
-      } else {
-        let w = self
-          .incomplete_writes
-          .get_mut(&cid)
-          .and_then(|f| f.iter_mut().find(|w| w.id == id && !w.done()))
-          .expect("Returned write has no corresponding pending data");
-        match payload {
-          MemRespPayload::ReadBuffered(_)
-          | MemRespPayload::ReadRegister(_)
-          | MemRespPayload::ReadError(_) => unreachable!(),
-          MemRespPayload::WriteAck => w.complete_ok(),
-          MemRespPayload::WriteError(e) => w.complete_with_error(e),
-        }
+    while let Some((mem_id, payload)) = self.addr_space.resp() {
+      match payload {
+        MemRespPayload::ReadBuffered(buf) => self.axi.complete_read(mem_id, Ok(buf.to_owned())),
+        MemRespPayload::ReadRegister(buf) => self.axi.complete_read(mem_id, Ok(Vec::from(buf))),
+        MemRespPayload::ReadError(e) => self.axi.complete_read(mem_id, Err(e)),
+        MemRespPayload::WriteAck => self.axi.complete_write(mem_id, Ok(())),
+        MemRespPayload::WriteError(e) => self.axi.complete_write(mem_id, Err(e)),
       }
     }
   }
 
+  /// Interrupt lines to the TestBench, bit 0: msip, bit 1: mtip
+  pub(crate) fn get_interrupts(&self) -> u8 {
+    (self.clint.msip() as u8) | (self.clint.mtip() as u8) << 1
+  }
+
+  pub(crate) fn axi_push_aw(&mut self, channel_id: u64, data_width: u64, aw: &AxiAddr) {
+    self.update_commit_cycle();
+    self.axi.push_aw(channel_id, data_width, aw);
+  }
+
+  pub(crate) fn axi_push_ar(&mut self, channel_id: u64, data_width: u64, ar: &AxiAddr) {
+    self.update_commit_cycle();
+    self.axi.push_ar(channel_id, data_width, ar);
+  }
+
+  pub(crate) fn axi_push_w(&mut self, channel_id: u64, wdata: &[u8], wstrb: &[bool], wlast: bool) {
+    self.update_commit_cycle();
+    self.axi.push_w(channel_id, wdata, wstrb, wlast);
+  }
+
+  pub(crate) fn axi_pop_b(&mut self, channel_id: u64) -> Option<AxiB> {
+    self.axi.pop_b(channel_id)
+  }
+
+  pub(crate) fn axi_pop_r(&mut self, channel_id: u64, data_width: u64) -> Option<AxiR> {
+    self.axi.pop_r(channel_id, data_width)
+  }
+
   pub(crate) fn set_timeout(&mut self, timeout: u64) {
     self.timeout = timeout;
   }
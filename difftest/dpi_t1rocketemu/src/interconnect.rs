@@ -1,17 +1,20 @@
-use std::{
-  any::Any,
-  cell::Cell,
-  collections::{BinaryHeap, HashSet, VecDeque},
-  path::Path,
-  rc::Rc,
-};
-use tracing::{debug, error};
-
-use framebuffer::FrameBuffer;
+use std::{any::Any, collections::VecDeque, path::Path};
+use tracing::error;
+
+pub use dpi_common::axi::BusError;
+use dpi_common::mem_model::{DRAMModel, FixedLatencyModel, MemIdent, MemoryModel, TrivialModel};
+
+use clint::{Clint, ClintRef};
+use framebuffer::{FrameBuffer, FrameBufferConfig};
+use memory_map::{DeviceKind, MemoryMap, RegionModel};
 use simctrl::{ExitFlagRef, SimCtrl};
+use uart::{Console, Uart, UartConfig};
 
+pub mod clint;
 pub mod framebuffer;
+pub mod memory_map;
 pub mod simctrl;
+pub mod uart;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct AddrInfo {
@@ -102,12 +105,6 @@ pub trait DeviceExt: Device + Sized {
   }
 }
 
-// It has no payload,
-// code generates bus error shall log detailed error message.
-// TODO: may distinguish SLVERR and DECERR in the future?
-#[derive(Debug, Clone)]
-pub struct BusError;
-
 // Represents a MMIO devices consists of 4-byte 'registers'.
 // Support only 4-byte aligned read/write, not support write mask
 // `offset` is offset in bytes from base address, guaranteed to be multiple of 4.
@@ -183,293 +180,17 @@ impl<T: RegDevice + Send + 'static> Device for WrappedRegDevice<T> {
   fn tick(&mut self) {} // This is a no-op for Reg devices
 }
 
-/// An abstract memory request (identifier) for memory models
-#[derive(Debug)]
-pub struct MemIdent {
-  id: u64,
-  req: AddrInfo,
-  is_write: bool,
-}
-
-impl Into<MemIdent> for InflightMem {
-  fn into(self) -> MemIdent {
-    MemIdent {
-      id: self.id,
-      req: self.req,
-      is_write: self.is_write,
-    }
-  }
-}
-
-/// The memory model interface
-///
-/// A memory model simulates the latency of a memory device,
-/// but don't keep track of its content.
-pub trait MemoryModel {
-  fn push(&mut self, req: MemIdent);
-  fn pop(&mut self) -> Option<MemIdent>;
-  fn tick(&mut self);
-}
-
-/// AddrSet repersents a subset of a specific memory slice, where
-/// each element (line) have uniform width
-///
-/// A memory request with arbitrary length may need to be segmented
-/// into multiple smaller memory requests. AddrSets are used to individually
-/// keep track of these requests' progress.
-#[derive(Clone, Debug)]
-pub struct AddrSet {
-  pub base: u32,
-  pub line_size: u16,
-  pub set: u16,
-}
-
-impl AddrSet {
-  /// Create an addr set based on the unaligned base, unaligned length, and the line size
-  ///
-  /// the returned set contains all elements initially.
-  pub fn new(unaligned_base: u32, len: u32, line_size: u32) -> AddrSet {
-    let base = (unaligned_base / line_size) * line_size;
-    let prepend = unaligned_base - base;
-    let line_num = ((len + prepend) + line_size - 1) / line_size;
-    assert!(
-      line_num <= 16,
-      "line number {} > 16 not supported!",
-      line_num
-    );
-    let set = if line_num == 16 {
-      u16::MAX
-    } else {
-      ((1 << line_num) - 1) as u16
-    };
-
-    AddrSet { base, line_size: line_size as u16, set }
-  }
-
-  /// Returns if this set is empty
-  pub fn empty(&self) -> bool {
-    self.set == 0
-  }
-
-  /// Remove a element based on its index
-  ///
-  /// Returns if the element is previously in the set
-  pub fn remove(&mut self, idx: u32) -> bool {
-    if idx >= 16 {
-      return false;
-    }
-    let currently_set = self.set & (1u16 << idx) != 0;
-    self.set &= !(1u16 << idx);
-    currently_set
-  }
-
-  /// Remove a element based on its address
-  /// The address has to reside within the range, and is aligned
-  /// to the line size.
-  ///
-  /// Returns if the element is previously in the set
-  pub fn remove_addr(&mut self, addr: u32) -> bool {
-    if addr < self.base {
-      return false;
-    }
-    let diff = addr - self.base;
-    if diff % self.line_size as u32 != 0 {
-      return false;
-    }
-    let idx = diff / self.line_size as u32;
-    self.remove(idx)
-  }
-}
-
-impl Iterator for AddrSet {
-  type Item = (u32, u32); // (addr, offset)
-
-  fn next(&mut self) -> Option<Self::Item> {
-    if self.set == 0 {
-      return None;
-    }
-    let last_one = self.set.trailing_zeros();
-    let addr = self.base + self.line_size as u32 * last_one;
-    self.remove(last_one);
-    return Some((addr, last_one));
-  }
-}
-
-/// Book-keeping structure for DRAMsim models
-#[derive(Debug)]
-struct InflightMem {
-  id: u64,
-  req: AddrInfo,
-  is_write: bool,
-
-  /// Un-sent requests
-  req_wait: AddrSet,
-  /// Un-acked responses
-  resp_wait: AddrSet,
-}
-
-impl InflightMem {
-  fn from_ident(ident: MemIdent, line_size: u32) -> Self {
-    let set = AddrSet::new(ident.req.offset, ident.req.len, line_size);
-    Self {
-      id: ident.id,
-      req: ident.req,
-      is_write: ident.is_write,
-
-      req_wait: set.clone(),
-      resp_wait: set,
-    }
-  }
-
-  /// Returns if all sub-requests are sent to the memory model
-  fn sent(&self) -> bool {
-    self.req_wait.empty()
-  }
-
-  /// Returns if all sub-responses are received
-  fn done(&self) -> bool {
-    self.resp_wait.empty()
-  }
-}
-
-/// The DRAM memory model based on DRAMsim3
-///
-/// The ticking speed of this model should be the system clock speed,
-/// as it keeps track of the DRAM clock tick internally and compensates
-/// for the clock speed difference.
-pub struct DRAMModel {
-  sys: dramsim3::MemorySystem,
-  inflights: Vec<InflightMem>,
-  // TODO: implement cache
-  _cached: BinaryHeap<u32>,
-  dram_tick: usize,
-  sys_tick: usize,
-}
-
-// FIXME: impl Send in upstream MemorySystem
-unsafe impl Send for DRAMModel {}
-
-impl DRAMModel {
-  pub fn new(ds_cfg: &Path, ds_path: &Path) -> Self {
-    let chunk_size: Rc<Cell<u32>> = Rc::new(Cell::new(0));
-    let chunk_size_clone = chunk_size.clone();
-    let sys =
-      dramsim3::MemorySystem::new(ds_cfg, ds_path).expect("dramsim3 MemorySystem creation failed");
-    let ret = DRAMModel {
-      sys,
-      inflights: vec![],
-      _cached: BinaryHeap::new(),
-      dram_tick: 0,
-      sys_tick: 0,
-    };
-
-    chunk_size_clone.set(ret.req_size());
-    ret
-  }
-
-  /// Size of each request to DRAM, in bytes
-  fn req_size(&self) -> u32 {
-    (self.sys.burst_length() * self.sys.bus_bits() / 8) as u32
-  }
-}
-
-impl MemoryModel for DRAMModel {
-  fn push(&mut self, req: MemIdent) {
-    // TODO: done if in cache
-    debug!("DRAM Pushing: {:x}, size = {}", req.req.offset, req.req.len);
-    self.inflights.push(InflightMem::from_ident(req, self.req_size()));
-  }
-
-  fn pop(&mut self) -> Option<MemIdent> {
-    // Take exact one of the inflight request that are fully done
-    // Also ensure no preceding requests that has conflicting ID
-    let mut blocked = HashSet::with_capacity(32);
-    let inf = &mut self.inflights;
-    for i in 0..inf.len() {
-      if inf[i].done() && !blocked.contains(&inf[i].id) {
-        return Some(inf.remove(i).into());
-      } else {
-        blocked.insert(inf[i].id);
-      }
-    }
-    None
-  }
-
-  fn tick(&mut self) {
-    for inflight in &mut self.inflights {
-      if inflight.sent() {
-        continue;
-      }
-      for (addr, idx) in inflight.req_wait.clone() {
-        if !self.sys.can_add(addr as u64, inflight.is_write) {
-          continue;
-        }
-        debug!(
-          "DRAM Memory request: {:x}, write={}, id=0x{:x}",
-          addr, inflight.is_write, inflight.id
-        );
-        self.sys.add(addr as u64, inflight.is_write);
-        inflight.req_wait.remove(idx);
-      }
-    }
-
-    self.sys_tick += 1;
-    let dram_tck = self.sys.tck(); // In ns
-    let sys_tck = crate::get_sys_tck();
-    while self.sys_tick as f64 * sys_tck > self.dram_tick as f64 * dram_tck {
-      let inflights = &mut self.inflights;
-      self.sys.tick(|addr, is_write| {
-        debug!("DRAM Memory response: {:x}, write={}", addr, is_write);
-        for req in &mut *inflights {
-          if req.is_write == is_write && req.resp_wait.remove_addr(addr as u32) {
-            debug!("Found req: id={:x}", req.id);
-            return;
-          }
-        }
-        debug!("All requests: {:#?}", inflights);
-        panic!("Unexpected memory response!");
-      });
-      self.dram_tick += 1;
-    }
-  }
-}
-
-/// A trivial memory model, where all requests are immediately resolved
-/// and served in the FIFO order
-#[derive(Default)]
-pub struct TrivialModel {
-  holding: VecDeque<MemIdent>,
-}
-
-impl MemoryModel for TrivialModel {
-  fn push(&mut self, req: MemIdent) {
-    self.holding.push_back(req);
-  }
-  fn pop(&mut self) -> Option<MemIdent> {
-    self.holding.pop_front()
-  }
-  fn tick(&mut self) {}
-}
-
 /// Repersents a bulk memory device, with its memory model
-pub struct RegularMemory<M: MemoryModel> {
+pub struct RegularMemory {
   data: Vec<u8>,
-  model: M,
-}
-
-impl RegularMemory<TrivialModel> {
-  pub fn with_content(data: Vec<u8>) -> Self {
-    RegularMemory { data, model: TrivialModel::default() }
-  }
+  model: Box<dyn MemoryModel + Send>,
 }
 
-impl RegularMemory<DRAMModel> {
-  pub fn with_content_and_model(data: Vec<u8>, model: DRAMModel) -> Self {
+impl RegularMemory {
+  pub fn with_content_and_model(data: Vec<u8>, model: Box<dyn MemoryModel + Send>) -> Self {
     RegularMemory { data, model }
   }
-}
 
-impl<M: MemoryModel + Send + 'static> RegularMemory<M> {
   fn execute_read(&mut self, addr: AddrInfo) -> &[u8] {
     &self.data[addr.as_range()]
   }
@@ -480,12 +201,13 @@ impl<M: MemoryModel + Send + 'static> RegularMemory<M> {
   }
 }
 
-impl<M: MemoryModel + Send + 'static> Device for RegularMemory<M> {
+impl Device for RegularMemory {
   fn req(&mut self, req: MemReq<'_>) -> bool {
     // dbg!(&req);
     let ident = MemIdent {
       id: req.id,
-      req: req.addr,
+      addr: req.addr.offset,
+      len: req.addr.len,
       is_write: req.payload.is_write(),
     };
     self.model.push(ident);
@@ -504,7 +226,9 @@ impl<M: MemoryModel + Send + 'static> Device for RegularMemory<M> {
     let payload = if popped.is_write {
       MemRespPayload::WriteAck
     } else {
-      MemRespPayload::ReadBuffered(self.execute_read(popped.req))
+      MemRespPayload::ReadBuffered(
+        self.execute_read(AddrInfo { offset: popped.addr, len: popped.len }),
+      )
     };
     // dbg!(&payload);
 
@@ -620,37 +344,54 @@ impl AddressSpace {
   }
 }
 
-/// Memory map:
-/// - 0x0400_0000 - 0x0600_0000 : framebuffer
-/// - 0x1000_0000 - 0x1000_1000 : simctrl
-/// - 0x2000_0000 - 0x4000_0000 : sram
-/// - 0x4000_0000 - 0xc000_0000 : dram
-pub fn create_emu_addrspace(dram_model: Option<DRAMModel>) -> (AddressSpace, ExitFlagRef) {
-  const SIMCTRL_BASE: u32 = 0x1000_0000;
-  const SIMCTRL_SIZE: u32 = 0x0000_1000; // one page
-  const DISPLAY_BASE: u32 = 0x0400_0000;
-  const DISPLAY_SIZE: u32 = 0x0200_0000;
-  const SRAM_BASE: u32 = 0x2000_0000;
-  const SRAM_SIZE: u32 = 0x2000_0000;
-  const DRAM_BASE: u32 = 0x4000_0000;
-  const DRAM_SIZE: u32 = 0x8000_0000;
-
+/// Create the address space of `map`, `new_dram_model` creates the model of the dramsim3 region.
+/// The uart and the simctrl uart write register print to `console`, the clint drives `clint`,
+/// the simctrl writes its events to `mmio_event_path`.
+pub fn create_emu_addrspace(
+  map: &MemoryMap,
+  uart: &UartConfig,
+  console: &Console,
+  clint: &ClintRef,
+  framebuffer: &FrameBufferConfig,
+  mmio_event_path: &Path,
+  new_dram_model: impl FnOnce() -> DRAMModel,
+) -> (AddressSpace, ExitFlagRef) {
   let exit_flag = ExitFlagRef::new();
 
-  let sram = RegularMemory::with_content(vec![0; SRAM_SIZE as usize]);
-  let dram_entry = match dram_model {
-    None => {
-      RegularMemory::with_content(vec![0; DRAM_SIZE as usize]).with_addr(DRAM_BASE, DRAM_SIZE)
-    }
-    Some(model) => RegularMemory::with_content_and_model(vec![0; DRAM_SIZE as usize], model)
-      .with_addr(DRAM_BASE, DRAM_SIZE),
-  };
-
-  let devices = vec![
-    sram.with_addr(SRAM_BASE, SRAM_SIZE),
-    dram_entry,
-    FrameBuffer::new().with_addr(DISPLAY_BASE, DISPLAY_SIZE),
-    WrappedRegDevice::new(SimCtrl::new(exit_flag.clone())).with_addr(SIMCTRL_BASE, SIMCTRL_SIZE),
-  ];
+  let mut new_dram_model = Some(new_dram_model);
+  let devices = map
+    .regions
+    .iter()
+    .map(|region| {
+      let (base, size) = (region.base as u32, region.size as u32);
+      match region.device {
+        DeviceKind::Memory => {
+          let model: Box<dyn MemoryModel + Send> = match region.model {
+            RegionModel::Trivial => Box::new(TrivialModel::default()),
+            RegionModel::Fixed { latency } => Box::new(FixedLatencyModel::new(latency)),
+            RegionModel::Dramsim3 => Box::new(new_dram_model
+              .take()
+              .expect("dramsim3 is used by a single region")(
+            )),
+          };
+          RegularMemory::with_content_and_model(vec![0; region.size as usize], model)
+            .with_addr(base, size)
+        }
+        DeviceKind::Framebuffer => FrameBuffer::new(framebuffer).with_addr(base, size),
+        DeviceKind::Simctrl => WrappedRegDevice::new(SimCtrl::new(
+          exit_flag.clone(),
+          console.clone(),
+          mmio_event_path,
+        ))
+        .with_addr(base, size),
+        DeviceKind::Uart => {
+          WrappedRegDevice::new(Uart::new(console.clone(), uart)).with_addr(base, size)
+        }
+        DeviceKind::Clint => {
+          WrappedRegDevice::new(Clint::new(clint.clone(), crate::get_t)).with_addr(base, size)
+        }
+      }
+    })
+    .collect();
   (AddressSpace::new(devices), exit_flag)
 }
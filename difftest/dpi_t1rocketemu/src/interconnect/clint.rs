@@ -0,0 +1,159 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, trace};
+
+use super::{BusError, RegDevice};
+
+/// Frequency of mtime, set by `+t1_mtime_freq`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClintConfig {
+  /// in Hz
+  pub mtime_freq: u64,
+  /// frequency of the simulated clock in Hz
+  pub core_freq: u64,
+}
+
+impl ClintConfig {
+  /// `sys_tck` is the period of the simulated clock in ns
+  pub fn new(mtime_freq: u64, sys_tck: f64) -> Self {
+    ClintConfig {
+      mtime_freq,
+      core_freq: (1e9 / sys_tck).round() as u64,
+    }
+  }
+}
+
+struct ClintState {
+  config: ClintConfig,
+  /// mtime at `base_cycle`, updated when mtime is written
+  base_mtime: u64,
+  base_cycle: u64,
+  mtime: u64,
+  mtimecmp: u64,
+  msip: bool,
+}
+
+impl ClintState {
+  fn mtime_at(&self, cycle: u64) -> u64 {
+    let elapsed = (cycle.saturating_sub(self.base_cycle)) as u128;
+    let ticks = elapsed * self.config.mtime_freq as u128 / self.config.core_freq as u128;
+    self.base_mtime.wrapping_add(ticks as u64)
+  }
+
+  fn set_mtime(&mut self, cycle: u64, mtime: u64) {
+    self.base_mtime = mtime;
+    self.base_cycle = cycle;
+    self.mtime = mtime;
+  }
+}
+
+/// The interrupt lines of the CLINT, shared by the device and the driver,
+/// which ticks mtime and reports the interrupts to the TestBench
+#[derive(Clone)]
+pub struct ClintRef(Arc<Mutex<ClintState>>);
+
+impl ClintRef {
+  pub fn new(config: ClintConfig) -> Self {
+    ClintRef(Arc::new(Mutex::new(ClintState {
+      config,
+      base_mtime: 0,
+      base_cycle: 0,
+      mtime: 0,
+      mtimecmp: u64::MAX,
+      msip: false,
+    })))
+  }
+
+  /// Advance mtime to the `cycle` of the simulated clock
+  pub fn tick(&self, cycle: u64) {
+    let mut state = self.0.lock().unwrap();
+    state.mtime = state.mtime_at(cycle);
+  }
+
+  pub fn mtip(&self) -> bool {
+    let state = self.0.lock().unwrap();
+    state.mtime >= state.mtimecmp
+  }
+
+  pub fn msip(&self) -> bool {
+    self.0.lock().unwrap().msip
+  }
+}
+
+/// CLINT of a single hart, in the layout of SiFive's
+///
+/// Reg map:
+/// - 0x0000 : msip, only bit 0 is writable
+/// - 0x4000 : mtimecmp, low 32 bits
+/// - 0x4004 : mtimecmp, high 32 bits
+/// - 0xBFF8 : mtime, low 32 bits
+/// - 0xBFFC : mtime, high 32 bits
+///
+/// mtime counts at `ClintConfig::mtime_freq`, following the simulated clock,
+/// mtimecmp is all ones at reset so that no timer interrupt is pending.
+pub struct Clint {
+  clint: ClintRef,
+  get_t: fn() -> u64,
+}
+
+impl Clint {
+  /// `get_t` gives the cycle of the simulated clock
+  pub fn new(clint: ClintRef, get_t: fn() -> u64) -> Self {
+    Clint { clint, get_t }
+  }
+}
+
+fn set_lo(value: u64, lo: u32) -> u64 {
+  (value & !0xffff_ffff) | lo as u64
+}
+
+fn set_hi(value: u64, hi: u32) -> u64 {
+  (value & 0xffff_ffff) | (hi as u64) << 32
+}
+
+impl RegDevice for Clint {
+  fn reg_read(&mut self, offset: u32) -> Result<u32, BusError> {
+    let state = self.clint.0.lock().unwrap();
+    let mtime = state.mtime_at((self.get_t)());
+    let value = match offset {
+      0x0000 => state.msip as u32,
+      0x4000 => state.mtimecmp as u32,
+      0x4004 => (state.mtimecmp >> 32) as u32,
+      0xbff8 => mtime as u32,
+      0xbffc => (mtime >> 32) as u32,
+      _ => {
+        error!("clint: invalid read addr: base + 0x{offset:04x}");
+        return Err(BusError);
+      }
+    };
+    Ok(value)
+  }
+
+  fn reg_write(&mut self, offset: u32, value: u32) -> Result<(), BusError> {
+    let cycle = (self.get_t)();
+    let mut state = self.clint.0.lock().unwrap();
+    match offset {
+      0x0000 => state.msip = value & 1 != 0,
+      0x4000 => state.mtimecmp = set_lo(state.mtimecmp, value),
+      0x4004 => state.mtimecmp = set_hi(state.mtimecmp, value),
+      0xbff8 => {
+        let mtime = set_lo(state.mtime_at(cycle), value);
+        state.set_mtime(cycle, mtime);
+      }
+      0xbffc => {
+        let mtime = set_hi(state.mtime_at(cycle), value);
+        state.set_mtime(cycle, mtime);
+      }
+      _ => {
+        error!("clint: invalid write addr: base + 0x{offset:04x}");
+        return Err(BusError);
+      }
+    }
+    trace!(
+      "[{cycle}] clint: msip={} mtime={} mtimecmp={}",
+      state.msip, state.mtime, state.mtimecmp
+    );
+    Ok(())
+  }
+}
@@ -1,43 +1,276 @@
-use std::{env, fs::File, io::BufWriter, path::PathBuf};
-
+use std::{
+  env,
+  fs::File,
+  io::{BufWriter, Write as _},
+  path::{Path, PathBuf},
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::get_t;
 use crate::interconnect::memcpy_mask;
 
 use super::{AddrInfo, Device, MemResp, MemRespPayload};
 
 /// Device memory layout:
 /// `0x000_0000 - 0x1FF_0000` addressable frame buffer memory
-///   Picture is in packed RGB24 layout, row-major. See tests/disp/simple for example.
-///   DISPLAY_WIDTH * DISPLAY_HEIGHT writable, out-of-range writes will be ignored
+///   Picture is packed in the pixel format, row-major. See tests/disp/simple for example.
+///   The frame is the first WIDTH * HEIGHT pixels
 /// `0x1FF_0000 - 0x200_0000` control registers
-/// `0x1FF_0000`: read as frame counter, write to flush frame buffer as png
-/// `0x1FF_0004`: output dimensions
-///     0x04-06 16bits: DISPLAY_WIDTH (currently hardcoded)
-///     0x06-08 16bits: DISPLAY_HEIGHT (currently hardcoded)
-/// TODO: configurable dimension and color depth support?
+/// `0x1FF_0000`: read as frame counter, write to flush frame buffer
+/// `0x1FF_0004`: output dimensions, writable
+///     0x04-06 16bits: WIDTH
+///     0x06-08 16bits: HEIGHT
+/// `0x1FF_0008`: pixel format, writable, see `PixelFormat`
+///
+/// Flushed frames are saved as png files, or appended to a y4m video skipping the frames
+/// identical to the previous one. The CRC32 of each flushed frame may be logged for
+/// regression checks.
 /// TODO: behavior emulation closer to actual LCD display?
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PixelFormat {
+  /// 3 bytes: R, G, B
+  Rgb888 = 0,
+  /// 2 bytes, little endian: R[15:11], G[10:5], B[4:0]
+  Rgb565 = 1,
+  /// 4 bytes: R, G, B, A
+  Rgba8888 = 2,
+  /// 1 byte: luma
+  Gray8 = 3,
+}
+
+impl PixelFormat {
+  fn from_reg(value: u32) -> Option<Self> {
+    match value {
+      0 => Some(PixelFormat::Rgb888),
+      1 => Some(PixelFormat::Rgb565),
+      2 => Some(PixelFormat::Rgba8888),
+      3 => Some(PixelFormat::Gray8),
+      _ => None,
+    }
+  }
+
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "rgb888" => Some(PixelFormat::Rgb888),
+      "rgb565" => Some(PixelFormat::Rgb565),
+      "rgba8888" => Some(PixelFormat::Rgba8888),
+      "gray8" => Some(PixelFormat::Gray8),
+      _ => None,
+    }
+  }
+
+  fn bytes_per_pixel(self) -> u32 {
+    match self {
+      PixelFormat::Rgb888 => 3,
+      PixelFormat::Rgb565 => 2,
+      PixelFormat::Rgba8888 => 4,
+      PixelFormat::Gray8 => 1,
+    }
+  }
+}
+
+/// Initial geometry and outputs of the frame buffer, set by the `+t1_fb_*` plusargs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrameBufferConfig {
+  pub width: u32,
+  pub height: u32,
+  pub format: PixelFormat,
+  /// append the flushed frames to this y4m video instead of png files
+  pub video: Option<PathBuf>,
+  /// frame rate in the y4m header
+  pub fps: u32,
+  /// log the CRC32 of each flushed frame to this file, as JSON lines
+  pub hash_log: Option<PathBuf>,
+}
+
+impl Default for FrameBufferConfig {
+  fn default() -> Self {
+    FrameBufferConfig {
+      width: 960,
+      height: 720,
+      format: PixelFormat::Rgb888,
+      video: None,
+      fps: 30,
+      hash_log: None,
+    }
+  }
+}
+
 enum Holding {
   WriteDone(u64),
   Reading(u64, AddrInfo),
 }
 
+struct Y4mWriter {
+  writer: BufWriter<File>,
+  width: u32,
+  height: u32,
+  last_hash: Option<u32>,
+}
+
+impl Y4mWriter {
+  fn create(path: &Path, width: u32, height: u32, fps: u32) -> anyhow::Result<Self> {
+    let file =
+      File::create(path).with_context(|| format!("failed to create video '{}'", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    writeln!(writer, "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C444")?;
+    Ok(Y4mWriter { writer, width, height, last_hash: None })
+  }
+
+  /// Append `rgb` unless it's identical to the previous frame, returns whether it's written
+  fn write_frame(&mut self, rgb: &[u8], hash: u32) -> anyhow::Result<bool> {
+    if self.last_hash == Some(hash) {
+      return Ok(false);
+    }
+    self.last_hash = Some(hash);
+
+    // BT.601, limited range
+    let pixels = rgb.chunks_exact(3).map(|p| (p[0] as i32, p[1] as i32, p[2] as i32));
+    let mut planes = vec![0u8; rgb.len()];
+    let (y, uv) = planes.split_at_mut(rgb.len() / 3);
+    let (u, v) = uv.split_at_mut(rgb.len() / 3);
+    for (i, (r, g, b)) in pixels.enumerate() {
+      y[i] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+      u[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+      v[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+    self.writer.write_all(b"FRAME\n")?;
+    self.writer.write_all(&planes)?;
+    self.writer.flush()?;
+    Ok(true)
+  }
+}
+
+#[derive(Serialize)]
+struct FrameHash {
+  frame: u32,
+  cycle: u64,
+  width: u32,
+  height: u32,
+  format: PixelFormat,
+  crc32: String,
+}
+
 pub struct FrameBuffer {
   vram: Vec<u8>,
   frame_counter: u32,
   holding: Option<Holding>,
-}
 
-const DISPLAY_WIDTH: u32 = 960;
-const DISPLAY_HEIGHT: u32 = 720;
+  width: u32,
+  height: u32,
+  format: PixelFormat,
+
+  fps: u32,
+  video_path: Option<PathBuf>,
+  video: Option<Y4mWriter>,
+  hash_log: Option<BufWriter<File>>,
+}
 
 const REG_START: u32 = 0x1FF0000;
 
 impl FrameBuffer {
-  pub fn new() -> Self {
-    FrameBuffer {
-      vram: vec![0u8; (DISPLAY_WIDTH * DISPLAY_HEIGHT * 3) as usize],
+  pub fn new(config: &FrameBufferConfig) -> Self {
+    let hash_log = config.hash_log.as_ref().map(|path| {
+      BufWriter::new(
+        File::create(path)
+          .unwrap_or_else(|e| panic!("failed to create frame hash log {path:?}: {e}")),
+      )
+    });
+    let fb = FrameBuffer {
+      vram: vec![0u8; REG_START as usize],
       frame_counter: 0,
       holding: None,
+      width: config.width,
+      height: config.height,
+      format: config.format,
+      fps: config.fps,
+      video_path: config.video.clone(),
+      video: None,
+      hash_log,
+    };
+    assert!(
+      fb.fits(config.width, config.height, config.format),
+      "framebuffer: {}x{} {:?} does not fit in the frame buffer memory",
+      config.width,
+      config.height,
+      config.format
+    );
+    fb
+  }
+
+  fn fits(&self, width: u32, height: u32, format: PixelFormat) -> bool {
+    width as u64 * height as u64 * format.bytes_per_pixel() as u64 <= REG_START as u64
+  }
+
+  fn frame(&self) -> &[u8] {
+    let len = self.width * self.height * self.format.bytes_per_pixel();
+    &self.vram[..len as usize]
+  }
+
+  // the frame in packed RGB24
+  fn frame_rgb(&self) -> Vec<u8> {
+    let frame = self.frame();
+    match self.format {
+      PixelFormat::Rgb888 => frame.to_vec(),
+      PixelFormat::Rgb565 => frame
+        .chunks_exact(2)
+        .flat_map(|p| {
+          let p = u16::from_le_bytes([p[0], p[1]]);
+          let (r, g, b) = ((p >> 11) as u8, (p >> 5) as u8 & 0x3f, p as u8 & 0x1f);
+          [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+        })
+        .collect(),
+      PixelFormat::Rgba8888 => frame.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+      PixelFormat::Gray8 => frame.iter().flat_map(|&l| [l, l, l]).collect(),
+    }
+  }
+
+  fn flush(&mut self) -> anyhow::Result<()> {
+    let hash = crc32fast::hash(self.frame());
+
+    if let Some(log) = &mut self.hash_log {
+      let entry = FrameHash {
+        frame: self.frame_counter,
+        cycle: get_t(),
+        width: self.width,
+        height: self.height,
+        format: self.format,
+        crc32: format!("{hash:08x}"),
+      };
+      serde_json::to_writer(&mut *log, &entry)?;
+      log.write_all(b"\n")?;
+      log.flush()?;
+    }
+
+    match &self.video_path {
+      Some(path) => {
+        let video = match &mut self.video {
+          Some(video) => video,
+          None => self.video.insert(Y4mWriter::create(path, self.width, self.height, self.fps)?),
+        };
+        if (video.width, video.height) != (self.width, self.height) {
+          error!(
+            "framebuffer: frame {} is {}x{}, the video is {}x{}, skipped",
+            self.frame_counter, self.width, self.height, video.width, video.height
+          );
+          return Ok(());
+        }
+        let rgb = self.frame_rgb();
+        let video = self.video.as_mut().unwrap();
+        if !video.write_frame(&rgb, hash)? {
+          info!(
+            "framebuffer: frame {} is a duplicate, skipped",
+            self.frame_counter
+          );
+        }
+        Ok(())
+      }
+      None => self.save_png(),
     }
   }
 
@@ -53,12 +286,27 @@ impl FrameBuffer {
 
     let file = BufWriter::new(File::create(path)?);
 
-    let mut encoder = png::Encoder::new(file, DISPLAY_WIDTH, DISPLAY_HEIGHT);
-    encoder.set_color(png::ColorType::Rgb);
+    let mut encoder = png::Encoder::new(file, self.width, self.height);
     encoder.set_depth(png::BitDepth::Eight);
     encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
-
-    encoder.write_header()?.write_image_data(&self.vram)?;
+    match self.format {
+      PixelFormat::Rgb888 => {
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.write_header()?.write_image_data(self.frame())?;
+      }
+      PixelFormat::Rgba8888 => {
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.write_header()?.write_image_data(self.frame())?;
+      }
+      PixelFormat::Gray8 => {
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.write_header()?.write_image_data(self.frame())?;
+      }
+      PixelFormat::Rgb565 => {
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.write_header()?.write_image_data(&self.frame_rgb())?;
+      }
+    }
 
     Ok(())
   }
@@ -66,19 +314,32 @@ impl FrameBuffer {
   fn reg_read(&mut self, reg_offset: u32) -> u32 {
     match reg_offset {
       0 => self.frame_counter,
-      4 => (DISPLAY_HEIGHT << 16) + DISPLAY_WIDTH,
+      4 => (self.height << 16) + self.width,
+      8 => self.format as u32,
 
       _ => panic!(),
     }
   }
 
   fn reg_write(&mut self, reg_offset: u32, value: u32) {
-    let _ = value;
     match reg_offset {
       0 => {
-        self.save_png().unwrap();
+        self.flush().unwrap();
         self.frame_counter += 1;
       }
+      4 => {
+        let (width, height) = (value & 0xffff, value >> 16);
+        if width == 0 || height == 0 || !self.fits(width, height, self.format) {
+          error!("framebuffer: invalid dimensions {width}x{height}, ignored");
+        } else {
+          self.width = width;
+          self.height = height;
+        }
+      }
+      8 => match PixelFormat::from_reg(value) {
+        Some(format) if self.fits(self.width, self.height, format) => self.format = format,
+        _ => error!("framebuffer: invalid pixel format {value}, ignored"),
+      },
 
       _ => panic!(),
     }
@@ -0,0 +1,245 @@
+//! Memory map of the emulator, given as a JSON file by `+t1_memory_map`
+//!
+//! Each region maps a device, addresses and sizes are integers or hex strings, and memory
+//! regions may have a latency model ("trivial" by default), e.g.
+//!
+//! ```json
+//! { "regions": [
+//!   { "name": "simctrl", "device": "simctrl", "base": "0x10000000", "size": "0x1000" },
+//!   { "name": "dram", "device": "memory", "base": "0x40000000", "size": "0x40000000",
+//!     "model": { "model": "dramsim3" } }
+//! ] }
+//! ```
+//!
+//! The "dramsim3" model uses the configuration given by `+t1_dramsim3_cfg`, it may be used
+//! by a single region. Exactly one "simctrl" region is required for the simulation to exit.
+
+#[cfg(test)]
+mod tests;
+
+use std::path::Path;
+
+use anyhow::{Context as _, bail, ensure};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+  Memory,
+  Framebuffer,
+  Simctrl,
+  Uart,
+  Clint,
+}
+
+/// Latency model of a memory region
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum RegionModel {
+  #[default]
+  Trivial,
+  Fixed {
+    latency: u64,
+  },
+  Dramsim3,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Region {
+  pub name: String,
+  pub device: DeviceKind,
+  #[serde(with = "addr")]
+  pub base: u64,
+  #[serde(with = "addr")]
+  pub size: u64,
+  #[serde(default)]
+  pub model: RegionModel,
+}
+
+impl Region {
+  fn new(name: &str, device: DeviceKind, base: u64, size: u64, model: RegionModel) -> Self {
+    Region { name: name.to_string(), device, base, size, model }
+  }
+
+  /// End of the region, `None` if it overflows
+  fn end(&self) -> Option<u64> {
+    self.base.checked_add(self.size)
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryMap {
+  pub regions: Vec<Region>,
+}
+
+/// Memory map:
+/// - 0x0200_0000 - 0x0201_0000 : clint
+/// - 0x0400_0000 - 0x0600_0000 : framebuffer
+/// - 0x1000_0000 - 0x1000_1000 : simctrl
+/// - 0x1000_1000 - 0x1000_2000 : uart
+/// - 0x2000_0000 - 0x4000_0000 : sram
+/// - 0x4000_0000 - 0xc000_0000 : dram, with dramsim3
+impl Default for MemoryMap {
+  fn default() -> Self {
+    use DeviceKind::*;
+    MemoryMap {
+      regions: vec![
+        Region::new(
+          "sram",
+          Memory,
+          0x2000_0000,
+          0x2000_0000,
+          RegionModel::Trivial,
+        ),
+        Region::new(
+          "dram",
+          Memory,
+          0x4000_0000,
+          0x8000_0000,
+          RegionModel::Dramsim3,
+        ),
+        Region::new(
+          "framebuffer",
+          Framebuffer,
+          0x0400_0000,
+          0x0200_0000,
+          RegionModel::Trivial,
+        ),
+        Region::new(
+          "simctrl",
+          Simctrl,
+          0x1000_0000,
+          0x0000_1000,
+          RegionModel::Trivial,
+        ),
+        Region::new("uart", Uart, 0x1000_1000, 0x0000_1000, RegionModel::Trivial),
+        Region::new(
+          "clint",
+          Clint,
+          0x0200_0000,
+          0x0001_0000,
+          RegionModel::Trivial,
+        ),
+      ],
+    }
+  }
+}
+
+impl MemoryMap {
+  pub fn load(path: &Path) -> anyhow::Result<Self> {
+    let content = std::fs::read_to_string(path)
+      .with_context(|| format!("failed to read memory map '{}'", path.display()))?;
+    let map: MemoryMap = serde_json::from_str(&content)
+      .with_context(|| format!("failed to parse memory map '{}'", path.display()))?;
+    map.validate().with_context(|| format!("invalid memory map '{}'", path.display()))?;
+    Ok(map)
+  }
+
+  pub fn validate(&self) -> anyhow::Result<()> {
+    for region in &self.regions {
+      let name = &region.name;
+      ensure!(region.size > 0, "region '{name}' is empty");
+      // the address space maps regions with 32-bit sizes, so a region can't cover all of it
+      ensure!(
+        region.size < 1 << 32 && region.end().is_some_and(|end| end <= 1 << 32),
+        "region '{name}' ({:#x} + {:#x}) exceeds the 32-bit address space",
+        region.base,
+        region.size
+      );
+      ensure!(
+        region.device == DeviceKind::Memory || region.model == RegionModel::Trivial,
+        "region '{name}' is not a memory, it can't have a memory model"
+      );
+      ensure!(
+        region.device != DeviceKind::Framebuffer || region.size == 0x200_0000,
+        "framebuffer region '{name}' must have the size 0x2000000"
+      );
+    }
+
+    let mut sorted: Vec<&Region> = self.regions.iter().collect();
+    sorted.sort_by_key(|region| region.base);
+    for pair in sorted.windows(2) {
+      let [prev, next] = pair else { unreachable!() };
+      // the ends are checked above
+      let (prev_end, next_end) = (prev.end().unwrap(), next.end().unwrap());
+      if prev_end > next.base {
+        bail!(
+          "region '{}' ({:#x}..{:#x}) overlaps with '{}' ({:#x}..{:#x})",
+          prev.name,
+          prev.base,
+          prev_end,
+          next.name,
+          next.base,
+          next_end
+        );
+      }
+    }
+
+    for (i, region) in self.regions.iter().enumerate() {
+      ensure!(
+        self.regions[..i].iter().all(|other| other.name != region.name),
+        "region name '{}' is duplicated",
+        region.name
+      );
+    }
+
+    let count = |pred: &dyn Fn(&Region) -> bool| self.regions.iter().filter(|r| pred(r)).count();
+    ensure!(
+      count(&|r| r.device == DeviceKind::Simctrl) == 1,
+      "exactly one simctrl region is required"
+    );
+    ensure!(
+      count(&|r| r.device == DeviceKind::Framebuffer) <= 1,
+      "at most one framebuffer region is supported"
+    );
+    ensure!(
+      count(&|r| r.device == DeviceKind::Uart) <= 1,
+      "at most one uart region is supported"
+    );
+    ensure!(
+      count(&|r| r.device == DeviceKind::Clint) <= 1,
+      "at most one clint region is supported"
+    );
+    ensure!(
+      count(&|r| r.model == RegionModel::Dramsim3) <= 1,
+      "at most one region may use the dramsim3 model"
+    );
+    Ok(())
+  }
+
+  pub fn uses_dramsim3(&self) -> bool {
+    self.regions.iter().any(|region| region.model == RegionModel::Dramsim3)
+  }
+}
+
+/// Address or size as an integer or a hex string, for `#[serde(with = "...")]`
+mod addr {
+  use serde::{Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{value:#x}"))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Addr {
+      Int(u64),
+      Str(String),
+    }
+
+    match Addr::deserialize(deserializer)? {
+      Addr::Int(value) => Ok(value),
+      Addr::Str(s) => {
+        let digits = s.replace('_', "");
+        let parsed = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+          Some(hex) => u64::from_str_radix(hex, 16),
+          None => digits.parse(),
+        };
+        parsed.map_err(|_| serde::de::Error::custom(format!("invalid address '{s}'")))
+      }
+    }
+  }
+}
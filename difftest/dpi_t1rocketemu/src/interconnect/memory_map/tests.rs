@@ -0,0 +1,131 @@
+//! Validation of memory maps
+
+use super::{DeviceKind, MemoryMap, Region, RegionModel};
+
+fn region(name: &str, device: DeviceKind, base: u64, size: u64) -> Region {
+  Region::new(name, device, base, size, RegionModel::Trivial)
+}
+
+fn map(regions: Vec<Region>) -> MemoryMap {
+  MemoryMap { regions }
+}
+
+fn simctrl() -> Region {
+  region("simctrl", DeviceKind::Simctrl, 0x1000_0000, 0x1000)
+}
+
+#[track_caller]
+fn assert_invalid(map: &MemoryMap, expected: &str) {
+  let err = map.validate().expect_err("memory map should be invalid");
+  let message = format!("{err:#}");
+  assert!(message.contains(expected), "unexpected error: {message}");
+}
+
+#[test]
+fn default_map_is_valid() {
+  MemoryMap::default().validate().unwrap();
+}
+
+#[test]
+fn parses_hex_and_integer_addresses() {
+  let map: MemoryMap = serde_json::from_str(
+    r#"{ "regions": [
+      { "name": "simctrl", "device": "simctrl", "base": "0x1000_0000", "size": 4096 },
+      { "name": "dram", "device": "memory", "base": "0x40000000", "size": "0x1000",
+        "model": { "model": "fixed", "latency": 3 } }
+    ] }"#,
+  )
+  .unwrap();
+  map.validate().unwrap();
+  assert_eq!(map.regions[0].base, 0x1000_0000);
+  assert_eq!(map.regions[0].size, 0x1000);
+  assert_eq!(map.regions[1].model, RegionModel::Fixed { latency: 3 });
+}
+
+#[test]
+fn rejects_overlapping_regions() {
+  let map = map(vec![
+    simctrl(),
+    region("sram", DeviceKind::Memory, 0x2000_0000, 0x2000_0000),
+    region("dram", DeviceKind::Memory, 0x3fff_f000, 0x1000),
+  ]);
+  assert_invalid(
+    &map,
+    "region 'sram' (0x20000000..0x40000000) overlaps with 'dram'",
+  );
+}
+
+#[test]
+fn accepts_adjacent_regions() {
+  let map = map(vec![
+    simctrl(),
+    region("sram", DeviceKind::Memory, 0x2000_0000, 0x2000_0000),
+    region("dram", DeviceKind::Memory, 0x4000_0000, 0x1000),
+  ]);
+  map.validate().unwrap();
+}
+
+#[test]
+fn rejects_duplicate_names() {
+  let map = map(vec![
+    simctrl(),
+    region("mem", DeviceKind::Memory, 0x2000_0000, 0x1000),
+    region("mem", DeviceKind::Memory, 0x4000_0000, 0x1000),
+  ]);
+  assert_invalid(&map, "region name 'mem' is duplicated");
+}
+
+#[test]
+fn requires_exactly_one_simctrl() {
+  let sram = region("sram", DeviceKind::Memory, 0x2000_0000, 0x1000);
+  assert_invalid(
+    &map(vec![sram.clone()]),
+    "exactly one simctrl region is required",
+  );
+
+  let second = region("simctrl2", DeviceKind::Simctrl, 0x1000_1000, 0x1000);
+  assert_invalid(
+    &map(vec![simctrl(), second, sram]),
+    "exactly one simctrl region is required",
+  );
+}
+
+#[test]
+fn rejects_regions_beyond_the_address_space() {
+  let map = map(vec![
+    simctrl(),
+    region("dram", DeviceKind::Memory, 0xffff_f000, 0x2000),
+  ]);
+  assert_invalid(
+    &map,
+    "region 'dram' (0xfffff000 + 0x2000) exceeds the 32-bit address space",
+  );
+}
+
+#[test]
+fn rejects_overflowing_base() {
+  let map = map(vec![
+    simctrl(),
+    region("dram", DeviceKind::Memory, u64::MAX, 0x1000),
+  ]);
+  assert_invalid(
+    &map,
+    "region 'dram' (0xffffffffffffffff + 0x1000) exceeds the 32-bit address space",
+  );
+}
+
+#[test]
+fn rejects_region_covering_the_address_space() {
+  let map = map(vec![region("dram", DeviceKind::Memory, 0, 1 << 32)]);
+  assert_invalid(
+    &map,
+    "region 'dram' (0x0 + 0x100000000) exceeds the 32-bit address space",
+  );
+}
+
+#[test]
+fn rejects_model_on_devices() {
+  let mut uart = region("uart", DeviceKind::Uart, 0x1000_1000, 0x1000);
+  uart.model = RegionModel::Dramsim3;
+  assert_invalid(&map(vec![simctrl(), uart]), "region 'uart' is not a memory");
+}
@@ -1,6 +1,7 @@
 use std::{
   fs::File,
   io::Write as _,
+  path::Path,
   sync::{
     Arc,
     atomic::{AtomicU32, Ordering},
@@ -11,7 +12,7 @@ use tracing::{error, info, warn};
 
 use crate::get_t;
 
-use super::{BusError, RegDevice};
+use super::{BusError, RegDevice, uart::Console};
 
 #[derive(Default, Debug, Clone)]
 pub struct ExitFlagRef(Arc<AtomicU32>);
@@ -39,16 +40,19 @@ pub const EXIT_CODE: u32 = 0xdead_beef;
 ///
 /// Event file:
 /// all writes to uart/profile write register are recorded blindly
-/// to "mmio-event.jsonl"
+/// to `+t1_mmio_event_path` ("mmio-event.jsonl" by default), the uart writes also go
+/// to the console
 pub struct SimCtrl {
   exit_flag: ExitFlagRef,
   event_file: File,
+  console: Console,
 }
 
 impl SimCtrl {
-  pub fn new(exit_flag: ExitFlagRef) -> Self {
-    let event_file = File::create("mmio-event.jsonl").unwrap();
-    SimCtrl { exit_flag, event_file }
+  pub fn new(exit_flag: ExitFlagRef, console: Console, event_path: &Path) -> Self {
+    let event_file = File::create(event_path)
+      .unwrap_or_else(|e| panic!("failed to create mmio event file {event_path:?}: {e}"));
+    SimCtrl { exit_flag, event_file, console }
   }
 
   fn append_event(&mut self, event: &str, value: u32) {
@@ -78,6 +82,7 @@ impl RegDevice for SimCtrl {
         }
       }
       0x10 => {
+        self.console.write(value as u8);
         self.append_event("uart-write", value);
       }
       0x14 => {
@@ -0,0 +1,292 @@
+use std::{
+  collections::VecDeque,
+  fs::File,
+  io::{self, BufWriter, Read as _, Write as _},
+  os::unix::fs::FileTypeExt as _,
+  path::{Path, PathBuf},
+  sync::{
+    Arc, Mutex,
+    mpsc::{self, Receiver, TryRecvError},
+  },
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use super::{BusError, RegDevice};
+
+/// Where the UART output goes and its input comes from, set by the `+t1_uart_*` plusargs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UartConfig {
+  /// print the output to stdout
+  pub stdout: bool,
+  /// also write the output to this file
+  pub output: Option<PathBuf>,
+  /// read the input from this file or pipe
+  pub input: Option<PathBuf>,
+}
+
+impl UartConfig {
+  /// The input is a pipe, whose bytes are received as its writer delivers them
+  pub fn input_is_pipe(&self) -> bool {
+    self
+      .input
+      .as_ref()
+      .is_some_and(|path| std::fs::metadata(path).is_ok_and(|meta| meta.file_type().is_fifo()))
+  }
+}
+
+struct ConsoleInner {
+  stdout: bool,
+  file: Option<BufWriter<File>>,
+}
+
+/// The text output of the UART (and of the simctrl uart write register), flushed at each line
+#[derive(Clone)]
+pub struct Console(Arc<Mutex<ConsoleInner>>);
+
+impl Console {
+  pub fn new(config: &UartConfig) -> Self {
+    let file = config.output.as_ref().map(|path| {
+      BufWriter::new(
+        File::create(path).unwrap_or_else(|e| panic!("failed to create uart output {path:?}: {e}")),
+      )
+    });
+    Console(Arc::new(Mutex::new(ConsoleInner {
+      stdout: config.stdout,
+      file,
+    })))
+  }
+
+  pub fn write(&self, byte: u8) {
+    let mut inner = self.0.lock().unwrap();
+    let flush = byte == b'\n';
+    if inner.stdout {
+      let mut stdout = std::io::stdout().lock();
+      stdout.write_all(&[byte]).unwrap();
+      if flush {
+        stdout.flush().unwrap();
+      }
+    }
+    if let Some(file) = &mut inner.file {
+      file.write_all(&[byte]).unwrap();
+      if flush {
+        file.flush().unwrap();
+      }
+    }
+  }
+
+  pub fn flush(&self) {
+    let mut inner = self.0.lock().unwrap();
+    if inner.stdout {
+      std::io::stdout().flush().unwrap();
+    }
+    if let Some(file) = &mut inner.file {
+      file.flush().unwrap();
+    }
+  }
+}
+
+/// A pipe read by a background thread, which sends the chunks it reads (or the error that
+/// stops it) to the simulation thread. The thread doesn't log, since the logger reads
+/// the simulation time.
+struct RxPipe {
+  path: PathBuf,
+  receiver: Receiver<io::Result<Vec<u8>>>,
+}
+
+impl RxPipe {
+  fn spawn(path: &Path) -> Self {
+    let (sender, receiver) = mpsc::channel();
+    let thread_path = path.to_path_buf();
+    std::thread::spawn(move || {
+      // opening a pipe blocks until it has a writer
+      let mut file = match File::open(&thread_path) {
+        Ok(file) => file,
+        Err(e) => {
+          let _ = sender.send(Err(e));
+          return;
+        }
+      };
+      let mut buf = [0u8; 256];
+      loop {
+        let chunk = match file.read(&mut buf) {
+          Ok(0) => break,
+          Ok(n) => Ok(buf[..n].to_vec()),
+          Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+          Err(e) => Err(e),
+        };
+        let stop = chunk.is_err();
+        if sender.send(chunk).is_err() || stop {
+          break;
+        }
+      }
+    });
+    RxPipe { path: path.to_path_buf(), receiver }
+  }
+}
+
+const LCR_DLAB: u32 = 0x80;
+const FCR_FIFO_ENABLE: u32 = 0x01;
+const FCR_CLEAR_RX: u32 = 0x02;
+const IER_RX_AVAILABLE: u32 = 0x01;
+const IER_TX_EMPTY: u32 = 0x02;
+const LSR_DATA_READY: u32 = 0x01;
+const LSR_TX_EMPTY: u32 = 0x60; // THRE | TEMT, transmitting is instant
+const MSR_CONNECTED: u32 = 0xb0; // DCD | DSR | CTS
+
+/// 16550 compatible UART, with registers 4 bytes apart (reg-shift = 2)
+///
+/// Reg map:
+/// - 0x00 : RBR (read) / THR (write), DLL if LCR.DLAB is set
+/// - 0x04 : IER, DLM if LCR.DLAB is set
+/// - 0x08 : IIR (read) / FCR (write)
+/// - 0x0C : LCR
+/// - 0x10 : MCR
+/// - 0x14 : LSR, writes are ignored
+/// - 0x18 : MSR, writes are ignored
+/// - 0x1C : SCR
+///
+/// Transmitted bytes go to the console at once. A regular input file is received at once
+/// when the UART is created, so that runs are deterministic, while a pipe is read in
+/// background so that it doesn't block the simulation (and its bytes arrive whenever the
+/// host delivers them). The divisor, line and modem control have no effect, and no interrupt
+/// line is connected yet (IIR still reports the pending interrupts enabled by IER).
+pub struct Uart {
+  console: Console,
+  rx_pipe: Option<RxPipe>,
+  rx: VecDeque<u8>,
+
+  ier: u32,
+  fcr: u32,
+  lcr: u32,
+  mcr: u32,
+  scr: u32,
+  dll: u32,
+  dlm: u32,
+}
+
+impl Uart {
+  pub fn new(console: Console, config: &UartConfig) -> Self {
+    let mut rx = VecDeque::new();
+    let mut rx_pipe = None;
+    if let Some(path) = &config.input {
+      if config.input_is_pipe() {
+        rx_pipe = Some(RxPipe::spawn(path));
+      } else {
+        let content =
+          std::fs::read(path).unwrap_or_else(|e| panic!("failed to read uart input {path:?}: {e}"));
+        rx.extend(content);
+      }
+    }
+
+    Uart {
+      console,
+      rx_pipe,
+      rx,
+      ier: 0,
+      fcr: 0,
+      lcr: 0,
+      mcr: 0,
+      scr: 0,
+      dll: 0,
+      dlm: 0,
+    }
+  }
+
+  fn poll_rx(&mut self) {
+    let Some(pipe) = &self.rx_pipe else {
+      return;
+    };
+    loop {
+      match pipe.receiver.try_recv() {
+        Ok(Ok(chunk)) => self.rx.extend(chunk),
+        Ok(Err(e)) => error!("uart: failed to read input {:?}: {e}", pipe.path),
+        Err(TryRecvError::Empty) => break,
+        Err(TryRecvError::Disconnected) => {
+          info!("uart: input {:?} is closed", pipe.path);
+          self.rx_pipe = None;
+          break;
+        }
+      }
+    }
+  }
+
+  fn dlab(&self) -> bool {
+    self.lcr & LCR_DLAB != 0
+  }
+
+  fn lsr(&self) -> u32 {
+    let data_ready = if self.rx.is_empty() {
+      0
+    } else {
+      LSR_DATA_READY
+    };
+    LSR_TX_EMPTY | data_ready
+  }
+
+  fn iir(&self) -> u32 {
+    let fifo = if self.fcr & FCR_FIFO_ENABLE != 0 {
+      0xc0
+    } else {
+      0
+    };
+    let interrupt = if self.ier & IER_RX_AVAILABLE != 0 && !self.rx.is_empty() {
+      0x04
+    } else if self.ier & IER_TX_EMPTY != 0 {
+      0x02
+    } else {
+      0x01 // none pending
+    };
+    fifo | interrupt
+  }
+}
+
+impl RegDevice for Uart {
+  fn reg_read(&mut self, offset: u32) -> Result<u32, BusError> {
+    self.poll_rx();
+    let value = match offset {
+      0x00 if self.dlab() => self.dll,
+      0x00 => self.rx.pop_front().map_or(0, u32::from),
+      0x04 if self.dlab() => self.dlm,
+      0x04 => self.ier,
+      0x08 => self.iir(),
+      0x0c => self.lcr,
+      0x10 => self.mcr,
+      0x14 => self.lsr(),
+      0x18 => MSR_CONNECTED,
+      0x1c => self.scr,
+      _ => {
+        error!("uart: invalid read addr: base + 0x{offset:02x}");
+        return Err(BusError);
+      }
+    };
+    Ok(value)
+  }
+
+  fn reg_write(&mut self, offset: u32, value: u32) -> Result<(), BusError> {
+    let value = value & 0xff;
+    match offset {
+      0x00 if self.dlab() => self.dll = value,
+      0x00 => self.console.write(value as u8),
+      0x04 if self.dlab() => self.dlm = value,
+      0x04 => self.ier = value & 0x0f,
+      0x08 => {
+        if value & FCR_CLEAR_RX != 0 {
+          self.poll_rx();
+          self.rx.clear();
+        }
+        self.fcr = value;
+      }
+      0x0c => self.lcr = value,
+      0x10 => self.mcr = value,
+      0x14 | 0x18 => {}
+      0x1c => self.scr = value,
+      _ => {
+        error!("uart: invalid write addr: base + 0x{offset:02x}");
+        return Err(BusError);
+      }
+    }
+    Ok(())
+  }
+}
@@ -1,6 +1,106 @@
+use dpi_common::plusarg::PlusArgDef;
+
 mod dpi;
 mod drive;
 mod interconnect;
+pub mod replay;
+
+/// All plusargs of the emulator, its own and the shared ones
+pub const PLUSARGS: &[&[PlusArgDef]] = &[
+  EMU_PLUSARGS,
+  &dpi_common::LOG_PLUSARGS,
+  &[dpi_common::record::RECORD_PLUSARG],
+  &dpi_common::axi_monitor::AXI_MONITOR_PLUSARGS,
+];
+
+// keep in sync with the plusargs parsed in TestBench.verbatimModule
+const EMU_PLUSARGS: &[PlusArgDef] = &[
+  PlusArgDef::path("t1_elf_file", "path to elf file").required().rtl(),
+  PlusArgDef::path(
+    "t1_wave_path",
+    "path to wave dump file, if trace is enabled",
+  )
+  .rtl(),
+  PlusArgDef::int("t1_timeout", "max cycle between two AXI DPI call").default("1000000").rtl(),
+  PlusArgDef::int(
+    "t1_timeout_after_quit",
+    "max cycle for TestBench to become idle after quit",
+  )
+  .default("10000")
+  .rtl(),
+  PlusArgDef::path("t1_dramsim3_cfg", "path to the dramsim3 configuration").required().rtl(),
+  PlusArgDef::path(
+    "t1_dramsim3_path",
+    "path of the output of dramsim3, under temp if absent",
+  )
+  .rtl(),
+  PlusArgDef::path(
+    "t1_memory_map",
+    "path to the JSON memory map, the default one if absent",
+  ),
+  PlusArgDef::bool("t1_uart_stdout", "print the uart output to stdout").default("1"),
+  PlusArgDef::path("t1_uart_output", "also write the uart output to this file"),
+  PlusArgDef::path(
+    "t1_uart_input",
+    "read the uart input from this file or pipe",
+  ),
+  PlusArgDef::int("t1_mtime_freq", "frequency of the clint mtime in Hz").default("1000000"),
+  PlusArgDef::int("t1_fb_width", "initial width of the framebuffer").default("960"),
+  PlusArgDef::int("t1_fb_height", "initial height of the framebuffer").default("720"),
+  PlusArgDef::enumeration(
+    "t1_fb_format",
+    &["rgb888", "rgb565", "rgba8888", "gray8"],
+    "initial pixel format of the framebuffer",
+  )
+  .default("rgb888"),
+  PlusArgDef::path(
+    "t1_fb_video",
+    "append the flushed frames to this y4m video instead of png files",
+  ),
+  PlusArgDef::int("t1_fb_fps", "frame rate of the y4m video").default("30"),
+  PlusArgDef::path(
+    "t1_fb_hash_log",
+    "log the CRC32 of each flushed frame to this file",
+  ),
+  PlusArgDef::int(
+    "t1_debug_global_timeout",
+    "max cycle for whole simulation, for debug only",
+  )
+  .rtl(),
+  PlusArgDef::int(
+    "t1_debug_dump_start",
+    "cycle when wave dump starts, for debug only",
+  )
+  .rtl(),
+  PlusArgDef::int(
+    "t1_debug_dump_end",
+    "cycle when wave dump ends, for debug only",
+  )
+  .rtl(),
+  PlusArgDef::int(
+    "t1_dev_rtl_event_off",
+    "set to 1 to disable rtl event recording",
+  )
+  .rtl(),
+  PlusArgDef::path("t1_dev_rtl_event_path", "path to rtl event jsonl file").rtl(),
+  PlusArgDef::int(
+    "t1_dev_vrf_snapshot",
+    "set to 1 to log the VRF each time the vector unit becomes idle",
+  )
+  .rtl(),
+  PlusArgDef::int(
+    "t1_dev_final_state",
+    "set to 1 to log the VRF, XRF and FRF at exit",
+  )
+  .rtl(),
+  PlusArgDef::path("t1_sim_result_path", "path to the simulation result")
+    .default("sim_result.json"),
+  PlusArgDef::path(
+    "t1_mmio_event_path",
+    "path to the events of the simctrl uart and profile registers",
+  )
+  .default("mmio-event.jsonl"),
+];
 
 // keep in sync with TestBench.verbatimModule
 // the value is measured in simulation time unit
@@ -11,7 +111,7 @@ pub fn get_sys_tck() -> f64 {
   0.8 // 1.25 GHz
 }
 
-/// get cycle
+/// get cycle, or the recorded one when replaying DPI calls
 pub fn get_t() -> u64 {
-  svdpi::get_time() / CYCLE_PERIOD
+  dpi_common::record::cycle()
 }
@@ -0,0 +1,178 @@
+//! DPI calls recorded with `+t1_dpi_record_path`, and their replay into a `Driver`
+//! without the simulator. See `dpi_common::record` for the format.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use dpi_common::record::strobe;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tempfile::TempDir;
+use tracing::warn;
+
+use dpi_common::axi::AxiAddr;
+use dpi_common::axi_monitor::AxiMonitorConfig;
+
+use crate::drive::{Driver, OnlineArgs};
+use crate::interconnect::clint::ClintConfig;
+use crate::interconnect::framebuffer::FrameBufferConfig;
+use crate::interconnect::memory_map::MemoryMap;
+use crate::interconnect::uart::UartConfig;
+
+// Init is called once, no need to box it
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "call")]
+pub(crate) enum DpiCall {
+  #[serde(rename = "t1_cosim_init")]
+  Init {
+    elf_file: String,
+    dlen: u32,
+    lane_width: u32,
+    vlen: u32,
+    spike_isa: String,
+    dramsim3_cfg: PathBuf,
+    axi_monitor: AxiMonitorConfig,
+    memory_map: MemoryMap,
+    uart: UartConfig,
+    clint: ClintConfig,
+    framebuffer: FrameBufferConfig,
+  },
+  #[serde(rename = "t1_cosim_set_timeout")]
+  SetTimeout { timeout: u64 },
+  #[serde(rename = "t1_cosim_watchdog")]
+  Watchdog,
+  #[serde(rename = "get_interrupts")]
+  GetInterrupts,
+  #[serde(rename = "axi_tick")]
+  AxiTick,
+  #[serde(rename = "axi_push_AW")]
+  AxiPushAW {
+    channel_id: u64,
+    data_width: u64,
+    #[serde(flatten)]
+    aw: AxiAddr,
+  },
+  #[serde(rename = "axi_push_AR")]
+  AxiPushAR {
+    channel_id: u64,
+    data_width: u64,
+    #[serde(flatten)]
+    ar: AxiAddr,
+  },
+  #[serde(rename = "axi_push_W")]
+  AxiPushW {
+    channel_id: u64,
+    #[serde(with = "hex::serde")]
+    wdata: Vec<u8>,
+    #[serde(with = "strobe")]
+    wstrb: Vec<bool>,
+    wlast: bool,
+  },
+  #[serde(rename = "axi_pop_B")]
+  AxiPopB { channel_id: u64 },
+  #[serde(rename = "axi_pop_R")]
+  AxiPopR { channel_id: u64, data_width: u64 },
+}
+
+/// Replay a record into a new driver, check that it returns the recorded values.
+/// Returns the number of calls replayed.
+pub fn replay(record: &Path) -> anyhow::Result<u64> {
+  let mut driver: Option<Driver> = None;
+  // output of dramsim3 is not kept
+  let dramsim3_run_dir = TempDir::new().context("failed to create dramsim3 runtime dir")?;
+  // nor are the uart, framebuffer and simctrl outputs, so that the recorded run's ones are
+  // left intact
+  let output_dir = TempDir::new().context("failed to create replay output dir")?;
+
+  dpi_common::record::replay(record, |call: DpiCall| {
+    if let DpiCall::Init {
+      elf_file,
+      dlen,
+      lane_width,
+      vlen,
+      spike_isa,
+      dramsim3_cfg,
+      axi_monitor,
+      memory_map,
+      uart,
+      clint,
+      framebuffer,
+    } = &call
+    {
+      anyhow::ensure!(driver.is_none(), "driver is already initialized");
+      let args = OnlineArgs {
+        elf_file: elf_file.clone(),
+        dlen: *dlen,
+        lane_width: *lane_width,
+        vlen: *vlen,
+        spike_isa: spike_isa.clone(),
+        dramsim3_cfg_path: dramsim3_cfg,
+        dramsim3_run_path: dramsim3_run_dir.path(),
+        // not written when replaying
+        sim_result_path: PathBuf::new(),
+        mmio_event_path: output_dir.path().join("mmio-event.jsonl"),
+        axi_monitor: axi_monitor.clone(),
+        memory_map: memory_map.clone(),
+        uart: replay_uart(uart),
+        clint: clint.clone(),
+        framebuffer: FrameBufferConfig {
+          // a video is written instead of png files
+          video: Some(output_dir.path().join("video.y4m")),
+          hash_log: framebuffer.hash_log.as_ref().map(|_| output_dir.path().join("hash_log.jsonl")),
+          ..framebuffer.clone()
+        },
+      };
+      driver = Some(Driver::new(None, &args));
+      return Ok(Value::Null);
+    }
+    let driver = driver.as_mut().context("driver is not initialized")?;
+
+    let ret = match call {
+      DpiCall::Init { .. } => unreachable!(),
+      DpiCall::SetTimeout { timeout } => {
+        driver.set_timeout(timeout);
+        Value::Null
+      }
+      DpiCall::Watchdog => serde_json::to_value(driver.watchdog())?,
+      DpiCall::GetInterrupts => serde_json::to_value(driver.get_interrupts())?,
+      DpiCall::AxiTick => {
+        driver.tick();
+        Value::Null
+      }
+      DpiCall::AxiPushAW { channel_id, data_width, aw } => {
+        driver.axi_push_aw(channel_id, data_width, &aw);
+        Value::Null
+      }
+      DpiCall::AxiPushAR { channel_id, data_width, ar } => {
+        driver.axi_push_ar(channel_id, data_width, &ar);
+        Value::Null
+      }
+      DpiCall::AxiPushW { channel_id, wdata, wstrb, wlast } => {
+        driver.axi_push_w(channel_id, &wdata, &wstrb, wlast);
+        Value::Null
+      }
+      DpiCall::AxiPopB { channel_id } => serde_json::to_value(driver.axi_pop_b(channel_id))?,
+      DpiCall::AxiPopR { channel_id, data_width } => {
+        serde_json::to_value(driver.axi_pop_r(channel_id, data_width))?
+      }
+    };
+    Ok(ret)
+  })
+}
+
+/// The uart of the replay: the output isn't written, and a regular input file is received
+/// again. The bytes of a pipe aren't recorded, so it isn't reopened (that would block) and
+/// the reads of the uart won't match the record.
+fn replay_uart(uart: &UartConfig) -> UartConfig {
+  let input = if uart.input_is_pipe() {
+    warn!(
+      "uart input {:?} is a pipe, it is not replayed",
+      uart.input.as_ref().unwrap()
+    );
+    None
+  } else {
+    uart.input.clone()
+  };
+  UartConfig { stdout: uart.stdout, output: None, input }
+}
@@ -0,0 +1,159 @@
+//! Payloads of RTL events
+//!
+//! An event log has one JSON object per line, tagged by its "event" field, e.g.
+//! `{"event":"Issue","idx":3,"cycle":1024}`. The flavor decides which kinds of event appear.
+//!
+//! The log may start with an `EventLogHeader` line. Logs without it are version 1,
+//! which is what the RTL emitted before the schema is versioned.
+
+use anyhow::{bail, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::hex;
+
+pub const EVENT_LOG_SCHEMA: &str = "t1-rtl-event";
+
+/// Bumped on any incompatible change of the event payloads
+pub const EVENT_LOG_VERSION: u32 = 1;
+
+/// The first line of an event log
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EventLogHeader {
+  pub schema: String,
+  pub version: u32,
+  pub flavor: String,
+}
+
+impl EventLogHeader {
+  pub fn new(flavor: &str) -> Self {
+    EventLogHeader {
+      schema: EVENT_LOG_SCHEMA.to_string(),
+      version: EVENT_LOG_VERSION,
+      flavor: flavor.to_string(),
+    }
+  }
+
+  /// Whether a line of the log is the header rather than an event
+  pub fn is_header(line: &str) -> bool {
+    line.starts_with('{') && line.contains("\"schema\"") && !line.contains("\"event\"")
+  }
+
+  pub fn check(&self, flavor: &str) -> anyhow::Result<()> {
+    ensure!(
+      self.schema == EVENT_LOG_SCHEMA,
+      "event log has schema '{}', expect '{EVENT_LOG_SCHEMA}'",
+      self.schema
+    );
+    if self.version > EVENT_LOG_VERSION {
+      bail!(
+        "event log has schema version {}, newer than the supported version {EVENT_LOG_VERSION}, \
+        please update t1-sim-checker",
+        self.version
+      );
+    }
+    ensure!(
+      self.flavor == flavor,
+      "event log is emitted by '{}', but checked as '{flavor}'",
+      self.flavor
+    );
+    Ok(())
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct IssueEvent {
+  pub idx: u8,
+  pub cycle: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LsuEnqEvent {
+  pub enq: u32,
+  pub cycle: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VrfWriteEvent {
+  pub issue_idx: u8,
+  pub vrf_idx: usize,
+  #[serde(with = "hex::mask", default)]
+  pub mask: Vec<bool>,
+  #[serde(with = "hex::bytes", default)]
+  pub data: Vec<u8>,
+  pub cycle: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryWriteEvent {
+  #[serde(with = "hex::mask", default)]
+  pub mask: Vec<bool>,
+  #[serde(with = "hex::bytes", default)]
+  pub data: Vec<u8>,
+  pub lsu_idx: u8,
+  #[serde(with = "hex::word", default)]
+  pub address: u32,
+  pub cycle: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CheckRdEvent {
+  #[serde(with = "hex::word", default)]
+  pub data: u32,
+  pub issue_idx: u8,
+  pub cycle: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VrfScoreboardEvent {
+  pub count: u32,
+  pub issue_idx: u8,
+  pub cycle: u64,
+}
+
+/// Whole VRF emitted at a fence
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VrfSnapshotEvent {
+  #[serde(with = "hex::bytes", default)]
+  pub data: Vec<u8>,
+  pub cycle: u64,
+}
+
+/// Register files dumped at exit
+/// (xrf and frf are lists of 32 hex strings, absent if not dumped)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FinalStateEvent {
+  #[serde(with = "hex::bytes", default)]
+  pub vrf: Vec<u8>,
+  #[serde(with = "hex::words", default)]
+  pub xrf: Vec<u32>,
+  #[serde(with = "hex::words", default)]
+  pub frf: Vec<u32>,
+  pub cycle: u64,
+}
+
+/// Scalar register write of the rocket core, also used for float registers
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RegWriteEvent {
+  pub idx: u8,
+  #[serde(with = "hex::word", default)]
+  pub data: u32,
+  pub cycle: u64,
+}
+
+/// Scalar register to be written by a long-latency instruction
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RegWriteWaitEvent {
+  pub idx: u8,
+  pub cycle: u64,
+}
@@ -0,0 +1,83 @@
+//! Hex string encodings of event fields, for `#[serde(with = "...")]`
+//!
+//! The RTL prints wide values with `%x`, thus without leading zeros,
+//! and possibly padded with spaces.
+
+use num_bigint::BigUint;
+use serde::{Deserialize, Deserializer, Serializer};
+
+fn parse_biguint<E: serde::de::Error>(s: &str) -> Result<BigUint, E> {
+  BigUint::parse_bytes(s.trim_start().as_bytes(), 16)
+    .ok_or_else(|| E::custom("Failed to parse BigUint from hex string"))
+}
+
+fn parse_u32<E: serde::de::Error>(s: &str) -> Result<u32, E> {
+  u32::from_str_radix(s.trim_start_matches(' '), 16).map_err(E::custom)
+}
+
+/// Bytes as a hex number, the first byte is the least significant
+pub mod bytes {
+  use super::*;
+
+  pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&BigUint::from_bytes_le(data).to_str_radix(16))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s: &str = Deserialize::deserialize(deserializer)?;
+    Ok(parse_biguint(s)?.to_bytes_le())
+  }
+}
+
+/// Bits as a hex number, the first bit is the least significant
+pub mod mask {
+  use super::*;
+
+  pub fn serialize<S: Serializer>(mask: &[bool], serializer: S) -> Result<S::Ok, S::Error> {
+    let bytes: Vec<u8> = mask
+      .chunks(8)
+      .map(|bits| bits.iter().enumerate().fold(0, |byte, (i, &b)| byte | ((b as u8) << i)))
+      .collect();
+    super::bytes::serialize(&bytes, serializer)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<bool>, D::Error> {
+    let s: &str = Deserialize::deserialize(deserializer)?;
+    let bytes = parse_biguint(s)?.to_bytes_le();
+    Ok(bytes.iter().flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1u8 == 1u8)).collect())
+  }
+}
+
+/// A 32-bit value, e.g. a register or an address
+pub mod word {
+  use super::*;
+
+  pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{value:x}"))
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let s: &str = Deserialize::deserialize(deserializer)?;
+    parse_u32(s)
+  }
+}
+
+/// A list of registers, each as a hex string
+pub mod words {
+  use serde::ser::SerializeSeq;
+
+  use super::*;
+
+  pub fn serialize<S: Serializer>(values: &[u32], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_seq(Some(values.len()))?;
+    for value in values {
+      seq.serialize_element(&format!("{value:x}"))?;
+    }
+    seq.end()
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u32>, D::Error> {
+    let strs: Vec<&str> = Deserialize::deserialize(deserializer)?;
+    strs.iter().map(|s| parse_u32(s)).collect()
+  }
+}
@@ -0,0 +1,13 @@
+//! Versioned schema of the files exchanged between the emulators and t1-sim-checker
+//!
+//! - `sim_result`: 'sim_result.json' written by the DPI library at the end of simulation
+//! - `event`: RTL events, one JSON object per line in the event log
+//!
+//! Both carry a version. Readers accept every version up to the one they are built with,
+//! and refuse newer ones with an explicit error, instead of failing on a random field.
+
+pub mod event;
+pub mod hex;
+pub mod sim_result;
+
+pub use sim_result::SimResult;
@@ -0,0 +1,119 @@
+//! 'sim_result.json', the result and configuration of a simulation
+
+use std::path::PathBuf;
+
+use anyhow::{Context as _, bail};
+use serde::{Deserialize, Serialize};
+
+/// Bumped on any incompatible change of `SimResult`
+pub const SIM_RESULT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SimResult {
+  /// absent in files written before the schema is versioned, which are version 1
+  #[serde(default = "legacy_version")]
+  pub version: u32,
+  pub flavor: String,
+  pub meta_vlen: u32,
+  pub meta_dlen: u32,
+  pub meta_lane_width: u32,
+  pub meta_isa: String,
+  /// recorded for offline use, only if it is an absolute path
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub meta_elf_file: Option<PathBuf>,
+  pub meta_memory_model: String,
+  pub total_cycles: u64,
+  pub success: bool,
+
+  // statistics below are absent in files written by older emulators
+  /// instructions executed by the reference model, absent if the emulator does not run one
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub insts: Option<InstCounts>,
+  /// cycles the emulator could not issue an instruction, absent if it does not issue
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub issue_stalls: Option<u64>,
+  #[serde(default)]
+  pub mem_traffic: MemTraffic,
+  #[serde(default)]
+  pub wall_clock_secs: f64,
+  /// simulated cycles per wall-clock second
+  #[serde(default)]
+  pub sim_speed_hz: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct InstCounts {
+  pub scalar: u64,
+  pub vector: u64,
+  /// scalar and vector loads
+  pub loads: u64,
+  /// scalar and vector stores
+  pub stores: u64,
+}
+
+/// Memory requests served by the emulator
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MemTraffic {
+  pub reads: u64,
+  pub writes: u64,
+  pub read_bytes: u64,
+  /// bytes enabled by the write strobe
+  pub write_bytes: u64,
+}
+
+fn legacy_version() -> u32 {
+  1
+}
+
+impl Default for SimResult {
+  fn default() -> Self {
+    SimResult {
+      version: SIM_RESULT_VERSION,
+      flavor: String::new(),
+      meta_vlen: 0,
+      meta_dlen: 0,
+      meta_lane_width: 0,
+      meta_isa: String::new(),
+      meta_elf_file: None,
+      meta_memory_model: String::new(),
+      total_cycles: 0,
+      success: false,
+      insts: None,
+      issue_stalls: None,
+      mem_traffic: MemTraffic::default(),
+      wall_clock_secs: 0.0,
+      sim_speed_hz: 0.0,
+    }
+  }
+}
+
+impl SimResult {
+  /// Parse the content of 'sim_result.json'. The version is checked before the fields,
+  /// so a file from a newer emulator reports the version rather than a missing field.
+  pub fn from_json(content: &str) -> anyhow::Result<SimResult> {
+    let value: serde_json::Value =
+      serde_json::from_str(content).context("sim result is not json")?;
+    let version = match value.get("version") {
+      None => legacy_version(),
+      Some(version) => version
+        .as_u64()
+        .and_then(|version| u32::try_from(version).ok())
+        .with_context(|| format!("invalid sim result version {version}"))?,
+    };
+    if version > SIM_RESULT_VERSION {
+      bail!(
+        "sim result has schema version {version}, newer than the supported version \
+        {SIM_RESULT_VERSION}, please update t1-sim-checker"
+      );
+    }
+
+    serde_json::from_value(value)
+      .with_context(|| format!("sim result does not match schema version {version}"))
+  }
+
+  pub fn to_json(&self) -> String {
+    let mut content = serde_json::to_string_pretty(self).unwrap();
+    content.push('\n');
+    content
+  }
+}
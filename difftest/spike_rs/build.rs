@@ -1,6 +1,11 @@
 use std::env;
 
 fn main() {
+  // scripted reference models only, nothing to link
+  if env::var_os("CARGO_FEATURE_SPIKE").is_none() {
+    return;
+  }
+
   println!(
     "cargo::rustc-link-search=native={}",
     env::var("SPIKE_LIB_DIR").expect("SPIKE_LIB_DIR should be set")
@@ -1,10 +1,15 @@
+pub mod model;
 pub mod runner;
 pub mod spike_event;
 pub mod util;
 
+#[cfg(feature = "spike")]
 use anyhow::ensure;
+#[cfg(feature = "spike")]
 use libc::c_char;
+#[cfg(feature = "spike")]
 use std::ffi::{CStr, CString};
+#[cfg(feature = "spike")]
 use tracing::trace;
 
 pub fn clip(binary: u32, a: i32, b: i32) -> u32 {
@@ -18,14 +23,17 @@ pub fn clip(binary: u32, a: i32, b: i32) -> u32 {
   (binary >> a) & mask
 }
 
+#[cfg(feature = "spike")]
 pub struct Spike {
   spike: *mut (),
   pub mem: Vec<u8>,
   pub size: usize,
 }
 
+#[cfg(feature = "spike")]
 unsafe impl Send for Spike {}
 
+#[cfg(feature = "spike")]
 extern "C" fn default_addr_to_mem(target: *mut (), addr: u64) -> *mut u8 {
   let spike = target as *mut Spike;
   let addr = addr as usize;
@@ -36,8 +44,10 @@ extern "C" fn default_addr_to_mem(target: *mut (), addr: u64) -> *mut u8 {
   }
 }
 
+#[cfg(feature = "spike")]
 type FfiCallback = extern "C" fn(*mut (), u64) -> *mut u8;
 
+#[cfg(feature = "spike")]
 impl Spike {
   // we need to have a boxed SpikeCObject, since its pointer will be passed to C to perform FFI call
   pub fn new(
@@ -88,16 +98,19 @@ impl Spike {
   }
 }
 
+#[cfg(feature = "spike")]
 impl Drop for Spike {
   fn drop(&mut self) {
     unsafe { spike_destruct(self.spike) }
   }
 }
 
+#[cfg(feature = "spike")]
 pub struct Processor {
   processor: *mut (),
 }
 
+#[cfg(feature = "spike")]
 impl Processor {
   pub fn disassemble(&self) -> String {
     let bytes = unsafe { proc_disassemble(self.processor) };
@@ -168,16 +181,19 @@ impl Processor {
   }
 }
 
+#[cfg(feature = "spike")]
 impl Drop for Processor {
   fn drop(&mut self) {
     unsafe { proc_destruct(self.processor) }
   }
 }
 
+#[cfg(feature = "spike")]
 pub struct State {
   state: *mut (),
 }
 
+#[cfg(feature = "spike")]
 impl State {
   pub fn set_pc(&self, pc: u64) {
     unsafe { state_set_pc(self.state, pc) }
@@ -240,12 +256,14 @@ impl State {
   }
 }
 
+#[cfg(feature = "spike")]
 impl Drop for State {
   fn drop(&mut self) {
     unsafe { state_destruct(self.state) }
   }
 }
 
+#[cfg(feature = "spike")]
 #[link(name = "spike_interfaces")]
 unsafe extern "C" {
   pub fn spike_register_callback(target: *mut (), callback: FfiCallback);
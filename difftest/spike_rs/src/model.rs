@@ -0,0 +1,246 @@
+//! Reference models stepped by `SpikeRunner`
+//!
+//! `SpikeModel` runs the ELF in spike. `ScriptedModel` replays events given in memory,
+//! so that the checker logic can be tested without spike: built without the default
+//! `spike` feature, spike_rs does not link the spike libraries.
+
+use std::collections::VecDeque;
+#[cfg(feature = "spike")]
+use std::path::Path;
+
+use tracing::debug;
+
+#[cfg(feature = "spike")]
+use crate::Spike;
+use crate::runner::ArchState;
+#[cfg(feature = "spike")]
+use crate::runner::SpikeArgs;
+use crate::spike_event::{
+  ISSUE_IDX_DEFAULT, LSU_IDX_DEFAULT, MemWriteRecord, SingleMemWrite, SingleVrfWrite, SpikeEvent,
+};
+#[cfg(feature = "spike")]
+use crate::util::load_elf;
+
+pub trait ReferenceModel: Send {
+  /// Execute one instruction, and record its architectural changes
+  fn step(&mut self) -> SpikeEvent;
+
+  /// pc of the next instruction to execute
+  fn pc(&self) -> u64;
+
+  fn arch_state(&self) -> ArchState;
+}
+
+#[cfg(feature = "spike")]
+pub struct SpikeModel {
+  spike: Box<Spike>,
+  vlen: u32,
+  do_log_vrf: bool,
+}
+
+#[cfg(feature = "spike")]
+impl SpikeModel {
+  pub fn new(args: &SpikeArgs, do_log_vrf: bool) -> Self {
+    // load the elf file
+    // initialize spike
+    let mut spike = args.to_spike_c_handler();
+
+    let entry_addr = load_elf(&mut spike, Path::new(&args.elf_file)).unwrap();
+
+    // initialize processor
+    let proc = spike.get_proc();
+    let state = proc.get_state();
+    proc.reset();
+    state.set_pc(entry_addr);
+
+    SpikeModel { spike, vlen: args.vlen, do_log_vrf }
+  }
+
+  pub fn load_elf(&mut self, fname: &Path) -> anyhow::Result<u64> {
+    load_elf(&mut self.spike, fname)
+  }
+
+  // just execute one instruction for non-difftest
+  pub fn exec(&self) -> anyhow::Result<()> {
+    let spike = &self.spike;
+    let proc = spike.get_proc();
+    let state = proc.get_state();
+
+    let new_pc = proc.func();
+
+    state.handle_pc(new_pc).unwrap();
+
+    Ok(())
+  }
+}
+
+#[cfg(feature = "spike")]
+impl ReferenceModel for SpikeModel {
+  fn step(&mut self) -> SpikeEvent {
+    let spike = &self.spike;
+    let proc = self.spike.get_proc();
+    let state = proc.get_state();
+
+    state.set_mcycle(0);
+
+    let mut event = SpikeEvent::new(spike, self.do_log_vrf);
+    state.clear();
+
+    let new_pc = if event.is_v() || event.is_exit() {
+      // inst is v / quit
+      event.pre_log_arch_changes(spike, self.vlen).unwrap();
+      let new_pc_ = proc.func();
+      event.log_arch_changes(spike, self.vlen).unwrap();
+      new_pc_
+    } else {
+      // inst is scalar
+      let new_pc_ = proc.func();
+      event.log_mem_write(spike).unwrap();
+      event.log_reg_write(spike).unwrap();
+      new_pc_
+    };
+
+    state.handle_pc(new_pc).unwrap();
+
+    event
+  }
+
+  fn pc(&self) -> u64 {
+    self.spike.get_proc().get_state().get_pc()
+  }
+
+  fn arch_state(&self) -> ArchState {
+    let proc = self.spike.get_proc();
+    let state = proc.get_state();
+    let vlen_in_bytes = self.vlen / 8;
+
+    ArchState {
+      pc: state.get_pc(),
+      xrf: (0..32).map(|idx| state.get_reg(idx, false)).collect(),
+      frf: (0..32).map(|idx| state.get_reg(idx, true)).collect(),
+      vrf: (0..32)
+        .flat_map(|idx| (0..vlen_in_bytes).map(move |offset| (idx, offset)))
+        .map(|(idx, offset)| proc.get_vreg_data(idx, offset))
+        .collect(),
+      vtype: proc.vu_get_vtype(),
+      vl: proc.vu_get_vl(),
+      vstart: proc.vu_get_vstart(),
+      vxrm: proc.vu_get_vxrm(),
+      vxsat: proc.vu_get_vxsat(),
+    }
+  }
+}
+
+/// Replays a script of events in order. The architectural state follows the
+/// register and VRF writes recorded in the events.
+pub struct ScriptedModel {
+  events: VecDeque<SpikeEvent>,
+  state: ArchState,
+}
+
+impl ScriptedModel {
+  pub fn new(events: impl IntoIterator<Item = SpikeEvent>, vlen: u32) -> Self {
+    let events: VecDeque<_> = events.into_iter().collect();
+    let state = ArchState {
+      pc: events.front().map_or(0, |se| se.pc),
+      xrf: vec![0; 32],
+      frf: vec![0; 32],
+      vrf: vec![0; vlen as usize / 8 * 32],
+      vtype: 0,
+      vl: 0,
+      vstart: 0,
+      vxrm: 0,
+      vxsat: false,
+    };
+    ScriptedModel { events, state }
+  }
+}
+
+impl ReferenceModel for ScriptedModel {
+  fn step(&mut self) -> SpikeEvent {
+    let event = self.events.pop_front().expect("scripted model runs out of events");
+    debug!("ScriptedStep: {}", event.describe_insn());
+
+    for (&offset, write) in &event.vrf_access_record.all_writes {
+      self.state.vrf[offset] = write.byte;
+    }
+    if event.is_rd_written {
+      self.state.xrf[event.rd_idx as usize] = event.rd_bits;
+    }
+    if event.is_fd_written {
+      self.state.frf[event.rd_idx as usize] = event.rd_bits;
+    }
+    self.state.vtype = event.vtype;
+    self.state.vl = event.vl;
+    self.state.pc = self.events.front().map_or(event.pc + 4, |se| se.pc);
+
+    event
+  }
+
+  fn pc(&self) -> u64 {
+    self.state.pc
+  }
+
+  fn arch_state(&self) -> ArchState {
+    self.state.clone()
+  }
+}
+
+// constructors of events for scripted models
+impl SpikeEvent {
+  pub fn scripted(pc: u64, inst_bits: u32, disasm: &str) -> Self {
+    SpikeEvent {
+      do_log_vrf: true,
+      lsu_idx: LSU_IDX_DEFAULT,
+      issue_idx: ISSUE_IDX_DEFAULT,
+      disasm: disasm.to_string(),
+      pc,
+      inst_bits,
+      ..Default::default()
+    }
+  }
+
+  /// `changed` is false if spike writes the byte with its original value
+  pub fn with_vrf_write(mut self, vrf_idx: usize, byte: u8, changed: bool) -> Self {
+    let write = SingleVrfWrite { byte, changed, executed: false };
+    self.vrf_access_record.all_writes.insert(vrf_idx, write);
+    self
+  }
+
+  pub fn with_mem_write(mut self, addr: u32, byte: u8) -> Self {
+    self
+      .mem_access_record
+      .all_writes
+      .entry(addr)
+      .or_insert(MemWriteRecord { writes: vec![], num_completed_writes: 0 })
+      .writes
+      .push(SingleMemWrite { val: byte, executed: false });
+    self
+  }
+
+  pub fn with_rd_write(mut self, rd_idx: u32, bits: u32) -> Self {
+    self.rd_idx = rd_idx;
+    self.rd_bits = bits;
+    self.is_rd_written = true;
+    self
+  }
+
+  pub fn with_fd_write(mut self, rd_idx: u32, bits: u32) -> Self {
+    self.rd_idx = rd_idx;
+    self.rd_bits = bits;
+    self.is_fd_written = true;
+    self
+  }
+
+  /// vxsat before and after executing the instruction
+  pub fn with_vxsat(mut self, before: bool, after: bool) -> Self {
+    self.vxsat = before;
+    self.vxsat_after = after;
+    self
+  }
+
+  pub fn with_exit(mut self) -> Self {
+    self.is_exit = true;
+    self
+  }
+}
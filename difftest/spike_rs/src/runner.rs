@@ -1,13 +1,16 @@
 use std::collections::VecDeque;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use tracing::debug;
 
+#[cfg(feature = "spike")]
 use crate::Spike;
+use crate::model::ReferenceModel;
+#[cfg(feature = "spike")]
+use crate::model::SpikeModel;
 use crate::spike_event::SpikeEvent;
-use crate::util::load_elf;
 
 pub struct SpikeRunner {
-  spike: Box<Spike>,
+  model: Box<dyn ReferenceModel>,
 
   /// commit queue
   /// in the spike thread, spike should detech if this queue is full, if not
@@ -39,12 +42,50 @@ pub struct SpikeRunner {
   /// for mcycle csr update
   pub spike_cycle: u64,
 
-  pub do_log_vrf: bool,
-
   // register file scoreboard
   pub rf_board: Vec<Option<u32>>,
   // float reg file scoreboard
   pub frf_board: Vec<Option<u32>>,
+
+  /// the last `step_history_depth` spike steps, the latest is at the back
+  pub step_history: VecDeque<StepRecord>,
+  pub step_history_depth: usize,
+
+  /// events stepped ahead by `prefetch`, the oldest is at the front
+  lookahead: VecDeque<SpikeEvent>,
+
+  /// the exit instruction has been taken by `spike_step`
+  pub exited: bool,
+}
+
+/// A brief record of one spike step, kept for failure diagnosis
+#[derive(Debug, Clone)]
+pub struct StepRecord {
+  pub spike_cycle: u64,
+  pub pc: u64,
+  pub inst_bits: u32,
+  pub disasm: String,
+
+  // length of the queues before the step
+  pub commit_queue_len: usize,
+  pub vector_queue_len: usize,
+  pub scalar_queue_len: usize,
+  pub float_queue_len: usize,
+}
+
+/// Architectural state of spike
+#[derive(Debug, Clone)]
+pub struct ArchState {
+  pub pc: u64,
+  pub xrf: Vec<u32>,
+  pub frf: Vec<u32>,
+  /// VLEN/8 bytes per vreg, v0 first
+  pub vrf: Vec<u8>,
+  pub vtype: u32,
+  pub vl: u32,
+  pub vstart: u16,
+  pub vxrm: u32,
+  pub vxsat: bool,
 }
 
 pub struct SpikeArgs {
@@ -67,8 +108,9 @@ pub struct SpikeArgs {
   pub set: String,
 }
 
+#[cfg(feature = "spike")]
 impl SpikeArgs {
-  fn to_spike_c_handler(&self) -> Box<Spike> {
+  pub(crate) fn to_spike_c_handler(&self) -> Box<Spike> {
     let lvl = "M";
     Spike::new(
       &self.set,
@@ -83,88 +125,121 @@ impl SpikeArgs {
 pub const MEM_SIZE: usize = 1usize << 32;
 
 impl SpikeRunner {
+  #[cfg(feature = "spike")]
   pub fn new(args: &SpikeArgs, do_log_vrf: bool) -> Self {
-    // load the elf file
-    // initialize spike
-    let mut spike = args.to_spike_c_handler();
-
-    let entry_addr = load_elf(&mut spike, Path::new(&args.elf_file)).unwrap();
+    Self::with_model(
+      Box::new(SpikeModel::new(args, do_log_vrf)),
+      args.vlen,
+      args.dlen,
+    )
+  }
 
-    // initialize processor
-    let proc = spike.get_proc();
-    let state = proc.get_state();
-    proc.reset();
-    state.set_pc(entry_addr);
+  /// Built without spike, only scripted models can be run
+  #[cfg(not(feature = "spike"))]
+  pub fn new(_args: &SpikeArgs, _do_log_vrf: bool) -> Self {
+    panic!("spike_rs is built without the `spike` feature, spike is not available")
+  }
 
+  pub fn with_model(model: Box<dyn ReferenceModel>, vlen: u32, dlen: u32) -> Self {
     SpikeRunner {
-      spike,
+      model,
       commit_queue: VecDeque::new(),
       vector_queue: VecDeque::new(),
       scalar_queue: VecDeque::new(),
       float_queue: VecDeque::new(),
-      vlen: args.vlen,
-      dlen: args.dlen,
+      vlen,
+      dlen,
       cycle: 0,
       spike_cycle: 0,
-      do_log_vrf,
       rf_board: vec![None; 32],
       frf_board: vec![None; 32],
+      step_history: VecDeque::new(),
+      step_history_depth: 0,
+      lookahead: VecDeque::new(),
+      exited: false,
     }
   }
 
-  pub fn load_elf(&mut self, fname: &Path) -> anyhow::Result<u64> {
-    load_elf(&mut *self.spike, fname)
+  /// pc of the next instruction to execute in spike, prefetched events are not counted
+  pub fn pc(&self) -> u64 {
+    self.model.pc()
   }
 
-  // just execute one instruction for non-difftest
-  pub fn exec(&self) -> anyhow::Result<()> {
-    let spike = &self.spike;
-    let proc = spike.get_proc();
-    let state = proc.get_state();
+  /// Current architectural state, which is ahead of the checker if events are prefetched
+  pub fn arch_state(&self) -> ArchState {
+    self.model.arch_state()
+  }
 
-    let new_pc = proc.func();
+  // take the next spike event for difftest, from the prefetched events if any
+  pub fn spike_step(&mut self) -> SpikeEvent {
+    let event = match self.lookahead.pop_front() {
+      Some(prefetched) => prefetched,
+      None => self.execute_step(),
+    };
+    self.exited |= event.is_exit();
 
-    state.handle_pc(new_pc).unwrap();
+    if self.step_history_depth > 0 {
+      if self.step_history.len() == self.step_history_depth {
+        self.step_history.pop_front();
+      }
+      self.step_history.push_back(StepRecord {
+        spike_cycle: event.spike_cycle,
+        pc: event.pc,
+        inst_bits: event.inst_bits,
+        disasm: event.disasm.clone(),
+        commit_queue_len: self.commit_queue.len(),
+        vector_queue_len: self.vector_queue.len(),
+        scalar_queue_len: self.scalar_queue.len(),
+        float_queue_len: self.float_queue.len(),
+      });
+    }
 
-    Ok(())
+    event
   }
 
-  // execute the spike processor for one instruction and record
-  // the spike event for difftest
-  pub fn spike_step(&mut self) -> SpikeEvent {
-    let spike = &self.spike;
-    let proc = self.spike.get_proc();
-    let state = proc.get_state();
-
-    let mcycle = (self.cycle + self.spike_cycle) as usize;
-    state.set_mcycle(0);
+  /// Step spike ahead by at most `n` instructions, so that spike execution overlaps with
+  /// waiting for RTL events. The results of difftest are unaffected, since spike runs
+  /// independently of the RTL. Prefetching stops at the exit instruction.
+  pub fn prefetch(&mut self, n: usize) {
+    for _ in 0..n {
+      if self.lookahead.back().is_some_and(|se| se.is_exit()) {
+        return;
+      }
+      let event = self.execute_step();
+      self.lookahead.push_back(event);
+    }
+  }
 
-    let mut event = SpikeEvent::new(spike, self.do_log_vrf);
-    state.clear();
+  pub fn prefetched_len(&self) -> usize {
+    self.lookahead.len()
+  }
 
-    let new_pc = if event.is_v() || event.is_exit() {
-      // inst is v / quit
-      debug!(
-        "SpikeStep: spike run vector insn ({}), mcycle={mcycle}",
-        event.describe_insn(),
-      );
-      event.pre_log_arch_changes(spike, self.vlen).unwrap();
-      let new_pc_ = proc.func();
-      event.log_arch_changes(spike, self.vlen).unwrap();
-      new_pc_
-    } else {
-      // inst is scalar
-      debug!(
-        "SpikeStep: spike run scalar insn ({}), mcycle={mcycle}",
-        event.describe_insn(),
+  /// Step until the exit instruction is taken, at most `max_steps` steps,
+  /// returns the number of steps
+  pub fn step_to_exit(&mut self, max_steps: u64) -> anyhow::Result<u64> {
+    let mut steps = 0;
+    while !self.exited {
+      anyhow::ensure!(
+        steps < max_steps,
+        "spike does not exit in {max_steps} steps"
       );
-      let new_pc_ = proc.func();
-      event.log_mem_write(spike).unwrap();
-      event.log_reg_write(spike).unwrap();
-      new_pc_
-    };
+      self.spike_step();
+      steps += 1;
+    }
+    Ok(steps)
+  }
 
-    state.handle_pc(new_pc).unwrap();
+  // execute the reference model for one instruction and record
+  // the spike event for difftest
+  fn execute_step(&mut self) -> SpikeEvent {
+    let mcycle = self.cycle + self.spike_cycle;
+    let mut event = self.model.step();
+    event.spike_cycle = self.spike_cycle;
+    debug!(
+      "SpikeStep: spike run {} insn ({}), mcycle={mcycle}",
+      if event.is_v() { "vector" } else { "scalar" },
+      event.describe_insn(),
+    );
 
     self.spike_cycle += 1;
 
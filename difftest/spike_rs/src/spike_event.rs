@@ -1,8 +1,10 @@
 use Default;
-use anyhow::ensure;
+use anyhow::{bail, ensure};
 use std::collections::HashMap;
+#[cfg(feature = "spike")]
 use tracing::trace;
 
+#[cfg(feature = "spike")]
 use crate::Spike;
 use crate::clip;
 
@@ -39,6 +41,8 @@ pub struct SingleVrfWrite {
 
 #[derive(Default, Debug, Clone)]
 pub struct VdWriteRecord {
+  // only logged from spike
+  #[cfg_attr(not(feature = "spike"), allow(dead_code))]
   vd_bytes: Vec<u8>,
 }
 
@@ -95,6 +99,8 @@ pub struct SpikeEvent {
   pub rd_bits: u32,
 
   // mutable states
+  /// vxsat after executing the instruction, `vxsat` is the one before
+  pub vxsat_after: bool,
   pub is_rd_written: bool,
   pub is_fd_written: bool,
   pub vd_write_record: VdWriteRecord,
@@ -103,9 +109,13 @@ pub struct SpikeEvent {
 
   // exit
   pub is_exit: bool,
+
+  /// number of spike steps before this instruction
+  pub spike_cycle: u64,
 }
 
 impl SpikeEvent {
+  #[cfg(feature = "spike")]
   pub fn new(spike: &Spike, do_log_vrf: bool) -> Self {
     let proc = spike.get_proc();
     let state = proc.get_state();
@@ -149,6 +159,7 @@ impl SpikeEvent {
 
       rd_bits: Default::default(),
 
+      vxsat_after: false,
       is_rd_written: false,
       is_fd_written: false,
       vd_write_record: Default::default(),
@@ -156,6 +167,8 @@ impl SpikeEvent {
       vrf_access_record: Default::default(),
 
       is_exit: false,
+
+      spike_cycle: 0,
     }
   }
 
@@ -333,6 +346,7 @@ impl SpikeEvent {
     // ))
   }
 
+  #[cfg(feature = "spike")]
   pub fn pre_log_arch_changes(&mut self, spike: &Spike, vlen: u32) -> anyhow::Result<()> {
     if self.do_log_vrf {
       // record the vrf writes before executing the insn
@@ -352,17 +366,21 @@ impl SpikeEvent {
     Ok(())
   }
 
+  #[cfg(feature = "spike")]
   pub fn log_arch_changes(&mut self, spike: &Spike, vlen: u32) -> anyhow::Result<()> {
     if self.do_log_vrf {
       self.log_vrf_write(spike, vlen).unwrap();
-      self.log_reg_write(spike).unwrap();
     }
+    // scalar results are also checked online, without logging VRF writes
+    self.log_reg_write(spike).unwrap();
+    self.vxsat_after = spike.get_proc().vu_get_vxsat();
     self.log_mem_write(spike).unwrap();
     self.log_mem_read(spike).unwrap();
 
     Ok(())
   }
 
+  #[cfg(feature = "spike")]
   fn log_vrf_write(&mut self, spike: &Spike, vlen: u32) -> anyhow::Result<()> {
     let proc = spike.get_proc();
     // record vrf writes
@@ -400,6 +418,7 @@ impl SpikeEvent {
     Ok(())
   }
 
+  #[cfg(feature = "spike")]
   pub fn log_reg_write(&mut self, spike: &Spike) -> anyhow::Result<()> {
     let proc = spike.get_proc();
     let state = proc.get_state();
@@ -447,6 +466,7 @@ impl SpikeEvent {
     Ok(())
   }
 
+  #[cfg(feature = "spike")]
   pub fn log_mem_write(&mut self, spike: &Spike) -> anyhow::Result<()> {
     let proc = spike.get_proc();
     let state = proc.get_state();
@@ -479,6 +499,7 @@ impl SpikeEvent {
     Ok(())
   }
 
+  #[cfg(feature = "spike")]
   fn log_mem_read(&mut self, spike: &Spike) -> anyhow::Result<()> {
     let proc = spike.get_proc();
     let state = proc.get_state();
@@ -520,6 +541,50 @@ impl SpikeEvent {
     Ok(())
   }
 
+  /// Check the scalar register write reported by the RTL on retirement. Spike does not log
+  /// writes to x0, so an unexpected write is only accepted on x0.
+  pub fn check_retire_rd(&self, written: bool, rd: u32, data: u32) -> anyhow::Result<()> {
+    let reg = if self.is_fd_written { "f" } else { "x" };
+    match (written, self.is_rd_written || self.is_fd_written) {
+      (true, true) => ensure!(
+        rd == self.rd_idx && data == self.rd_bits,
+        "expect to write {reg}{} = {:#010x}, actual {reg}{rd} = {data:#010x}",
+        self.rd_idx,
+        self.rd_bits
+      ),
+      (true, false) => ensure!(
+        rd == 0,
+        "expect no scalar register write, actual x{rd} = {data:#010x}"
+      ),
+      (false, true) => bail!(
+        "expect to write {reg}{} = {:#010x}, actual no scalar register write",
+        self.rd_idx,
+        self.rd_bits
+      ),
+      (false, false) => {}
+    }
+
+    Ok(())
+  }
+
+  /// Check the saturation reported by the RTL on retirement. Spike only has the sticky vxsat,
+  /// so the instruction is known to saturate only if it sets vxsat.
+  pub fn check_retire_vxsat(&self, saturated: bool) -> anyhow::Result<()> {
+    if saturated {
+      ensure!(
+        self.vxsat_after,
+        "RTL reports saturation, but vxsat is not set in spike"
+      );
+    } else {
+      ensure!(
+        self.vxsat || !self.vxsat_after,
+        "expect saturation setting vxsat, but RTL reports none"
+      );
+    }
+
+    Ok(())
+  }
+
   pub fn check_is_ready_for_commit(&self, cycle: u64) -> anyhow::Result<()> {
     for (addr, record) in &self.mem_access_record.all_writes {
       ensure!(
@@ -541,3 +606,6 @@ impl SpikeEvent {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,100 @@
+//! Checks of the scalar results and vxsat reported by the RTL on retirement
+
+use super::SpikeEvent;
+
+fn addi(rd: u32, bits: u32) -> SpikeEvent {
+  // addi rd, rd, 1
+  let inst_bits = 0x00100013 | (rd << 15) | (rd << 7);
+  SpikeEvent::scripted(0x1000, inst_bits, "addi").with_rd_write(rd, bits)
+}
+
+fn fadd(rd: u32, bits: u32) -> SpikeEvent {
+  // fadd.s rd, rd, rd
+  let inst_bits = 0x00000053 | (rd << 20) | (rd << 15) | (rd << 7);
+  SpikeEvent::scripted(0x1000, inst_bits, "fadd.s").with_fd_write(rd, bits)
+}
+
+fn vadd() -> SpikeEvent {
+  // vadd.vv v1, v2, v3
+  SpikeEvent::scripted(0x1000, 0x022180d7, "vadd.vv v1, v2, v3")
+}
+
+fn vsadd() -> SpikeEvent {
+  // vsadd.vv v1, v2, v3
+  SpikeEvent::scripted(0x1000, 0x862180d7, "vsadd.vv v1, v2, v3")
+}
+
+#[track_caller]
+fn assert_error(result: anyhow::Result<()>, expected: &str) {
+  let error = result.expect_err("check should fail");
+  assert!(
+    error.to_string().contains(expected),
+    "unexpected error: {error}"
+  );
+}
+
+#[test]
+fn retire_rd_matches() {
+  addi(1, 5).check_retire_rd(true, 1, 5).unwrap();
+  fadd(2, 0x3f80_0000).check_retire_rd(true, 2, 0x3f80_0000).unwrap();
+  vadd().check_retire_rd(false, 0, 0).unwrap();
+}
+
+#[test]
+fn retire_rd_mismatches() {
+  assert_error(
+    addi(1, 5).check_retire_rd(true, 1, 6),
+    "expect to write x1 = 0x00000005, actual x1 = 0x00000006",
+  );
+  assert_error(
+    addi(1, 5).check_retire_rd(true, 2, 5),
+    "expect to write x1 = 0x00000005, actual x2 = 0x00000005",
+  );
+  assert_error(
+    fadd(2, 0x3f80_0000).check_retire_rd(true, 2, 0),
+    "expect to write f2 = 0x3f800000, actual f2 = 0x00000000",
+  );
+}
+
+#[test]
+fn retire_rd_write_to_x0() {
+  // addi x0, x0, 1, whose write to x0 is not logged by spike
+  let addi_x0 = SpikeEvent::scripted(0x1000, 0x00100013, "addi");
+  addi_x0.check_retire_rd(true, 0, 1).unwrap();
+
+  assert_error(
+    vadd().check_retire_rd(true, 3, 1),
+    "expect no scalar register write, actual x3 = 0x00000001",
+  );
+}
+
+#[test]
+fn retire_rd_missing_write() {
+  assert_error(
+    addi(1, 5).check_retire_rd(false, 0, 0),
+    "expect to write x1 = 0x00000005, actual no scalar register write",
+  );
+}
+
+#[test]
+fn retire_vxsat_set() {
+  vsadd().with_vxsat(false, true).check_retire_vxsat(true).unwrap();
+  // vxsat is sticky, an instruction saturating again is not visible in spike
+  vsadd().with_vxsat(true, true).check_retire_vxsat(true).unwrap();
+  vsadd().with_vxsat(true, true).check_retire_vxsat(false).unwrap();
+
+  assert_error(
+    vsadd().with_vxsat(false, true).check_retire_vxsat(false),
+    "expect saturation setting vxsat, but RTL reports none",
+  );
+}
+
+#[test]
+fn retire_vxsat_unset() {
+  vsadd().with_vxsat(false, false).check_retire_vxsat(false).unwrap();
+
+  assert_error(
+    vsadd().with_vxsat(false, false).check_retire_vxsat(true),
+    "RTL reports saturation, but vxsat is not set in spike",
+  );
+}
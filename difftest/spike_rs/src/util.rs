@@ -1,3 +1,4 @@
+#[cfg(feature = "spike")]
 use crate::Spike;
 use std::fs::File;
 use std::io::Read;
@@ -5,6 +6,7 @@ use std::path::Path;
 use xmas_elf::program::{ProgramHeader, Type};
 use xmas_elf::{ElfFile, header};
 
+#[cfg(feature = "spike")]
 pub fn load_elf(spike: &mut Spike, fname: &Path) -> anyhow::Result<u64> {
   let mut file = File::open(fname).unwrap();
   let mut buffer = Vec::new();
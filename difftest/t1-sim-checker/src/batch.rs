@@ -0,0 +1,368 @@
+//! Batch regression over many cases
+//!
+//! Every case is checked by a child t1-sim-checker process, so that a crash in spike
+//! only fails its own case. Cases are run in parallel by a pool of worker threads.
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::{Context as _, bail};
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+use tracing::{info, warn};
+
+use crate::CheckReport;
+
+/// names of the event file looked up next to 'sim_result.json', in order
+const EVENT_FILE_NAMES: &[&str] = &[
+  "rtl-event.jsonl",
+  "rtl-event.jsonl.gz",
+  "rtl-event.jsonl.zst",
+  "rtl-event.bin",
+  "rtl-event.bin.gz",
+  "rtl-event.bin.zst",
+];
+
+/// An entry of the manifest, relative paths are relative to the manifest
+#[derive(Deserialize, Debug)]
+struct Case {
+  /// defaults to the directory containing the sim result
+  name: Option<String>,
+  sim_result: PathBuf,
+  rtl_event_file: PathBuf,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+  Passed,
+  Failed,
+  /// the checker is killed by a signal, e.g. an abort in spike
+  Crashed,
+}
+
+#[derive(Serialize, Debug)]
+struct CaseResult {
+  case: String,
+  flavor: Option<String>,
+  status: Status,
+  /// events processed before finishing or failing
+  events: Option<u64>,
+  runtime_secs: f64,
+  first_error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+  total: usize,
+  passed: usize,
+  failed: usize,
+  cases: &'a [CaseResult],
+}
+
+pub struct BatchOptions<'a> {
+  /// a directory searched for 'sim_result.json', or a JSON manifest of cases
+  pub input: &'a Path,
+  pub jobs: usize,
+  pub summary_json: Option<&'a Path>,
+  pub summary_markdown: Option<&'a Path>,
+  /// directory to keep the log of every case, named `<index>-<case>.log`
+  pub log_dir: Option<&'a Path>,
+  /// extra arguments passed to every check
+  pub checker_args: &'a [String],
+}
+
+pub fn run_batch(options: &BatchOptions) -> anyhow::Result<()> {
+  let cases = if options.input.is_dir() {
+    discover_cases(options.input)?
+  } else {
+    load_manifest(options.input)?
+  };
+  if cases.is_empty() {
+    bail!("no case found in {:?}", options.input);
+  }
+  if let Some(log_dir) = options.log_dir {
+    fs::create_dir_all(log_dir).with_context(|| format!("in create {log_dir:?}"))?;
+  }
+  // reports of the checks, and their logs if not kept
+  let work_dir = TempDir::new().context("in create batch work dir")?;
+
+  let checker = std::env::current_exe().context("in locating t1-sim-checker")?;
+  let jobs = options.jobs.clamp(1, cases.len());
+  info!("checking {} cases with {jobs} workers", cases.len());
+
+  let next_case = AtomicUsize::new(0);
+  let results: Mutex<Vec<Option<CaseResult>>> = Mutex::new(cases.iter().map(|_| None).collect());
+  thread::scope(|scope| {
+    for _ in 0..jobs {
+      scope.spawn(|| {
+        loop {
+          let idx = next_case.fetch_add(1, Ordering::Relaxed);
+          let Some(case) = cases.get(idx) else { break };
+          let result = check_case(&checker, idx, case, options, work_dir.path());
+          results.lock().unwrap()[idx] = Some(result);
+        }
+      });
+    }
+  });
+
+  let results: Vec<_> = results.into_inner().unwrap().into_iter().flatten().collect();
+  let passed = results.iter().filter(|r| r.status == Status::Passed).count();
+  let summary = Summary {
+    total: results.len(),
+    passed,
+    failed: results.len() - passed,
+    cases: &results,
+  };
+
+  if let Some(path) = options.summary_json {
+    let file = File::create(path).with_context(|| format!("in create {path:?}"))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &summary)?;
+    writeln!(writer)?;
+    writer.flush()?;
+  }
+  if let Some(path) = options.summary_markdown {
+    fs::write(path, summary.to_markdown()).with_context(|| format!("in write {path:?}"))?;
+  }
+
+  eprintln!(
+    "Totally {} cases checked, {} passed, {} failed",
+    summary.total, passed, summary.failed
+  );
+  if summary.failed > 0 {
+    bail!("{} of {} cases failed", summary.failed, summary.total);
+  }
+
+  Ok(())
+}
+
+fn discover_cases(dir: &Path) -> anyhow::Result<Vec<Case>> {
+  fn walk(root: &Path, dir: &Path, cases: &mut Vec<Case>) -> anyhow::Result<()> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+      .with_context(|| format!("in read dir {dir:?}"))?
+      .map(|entry| entry.map(|entry| entry.path()))
+      .collect::<Result<_, _>>()?;
+    entries.sort();
+
+    let sim_result = dir.join("sim_result.json");
+    if sim_result.is_file() {
+      match EVENT_FILE_NAMES.iter().map(|name| dir.join(name)).find(|path| path.is_file()) {
+        Some(rtl_event_file) => {
+          let name = dir.strip_prefix(root).unwrap_or(dir).display().to_string();
+          let name = if name.is_empty() {
+            ".".to_string()
+          } else {
+            name
+          };
+          cases.push(Case { name: Some(name), sim_result, rtl_event_file });
+        }
+        None => warn!("skip {sim_result:?}, no rtl event file found next to it"),
+      }
+    }
+
+    for entry in entries.iter().filter(|path| path.is_dir()) {
+      walk(root, entry, cases)?;
+    }
+    Ok(())
+  }
+
+  let mut cases = vec![];
+  walk(dir, dir, &mut cases)?;
+  Ok(cases)
+}
+
+fn load_manifest(path: &Path) -> anyhow::Result<Vec<Case>> {
+  let file = File::open(path).with_context(|| format!("in open manifest {path:?}"))?;
+  let mut cases: Vec<Case> = serde_json::from_reader(BufReader::new(file))
+    .with_context(|| format!("in parsing manifest {path:?}"))?;
+
+  let base = path.parent().unwrap_or(Path::new("."));
+  for case in &mut cases {
+    case.sim_result = base.join(&case.sim_result);
+    case.rtl_event_file = base.join(&case.rtl_event_file);
+  }
+  Ok(cases)
+}
+
+fn check_case(
+  checker: &Path,
+  idx: usize,
+  case: &Case,
+  options: &BatchOptions,
+  work_dir: &Path,
+) -> CaseResult {
+  let name = match &case.name {
+    Some(name) => name.clone(),
+    None => case.sim_result.parent().unwrap_or(Path::new(".")).display().to_string(),
+  };
+  let flavor = read_flavor(&case.sim_result);
+  // prefixed by the index, as names may collide once the separators are replaced
+  let file_name = format!("{idx:04}-{}", name.replace(['/', '\\'], "_"));
+  let log_path = options.log_dir.unwrap_or(work_dir).join(format!("{file_name}.log"));
+  let report_path = work_dir.join(format!("{file_name}.report.json"));
+
+  info!("checking case {name}");
+  let start = Instant::now();
+  let status =
+    File::create(&log_path).with_context(|| format!("in create {log_path:?}")).and_then(|log| {
+      Command::new(checker)
+        .arg("--sim-result")
+        .arg(&case.sim_result)
+        .arg("--rtl-event-file")
+        .arg(&case.rtl_event_file)
+        .arg("--report-json")
+        .arg(&report_path)
+        .args(options.checker_args)
+        .stdout(Stdio::null())
+        .stderr(log)
+        .status()
+        .context("failed to run checker")
+    });
+  let runtime_secs = start.elapsed().as_secs_f64();
+
+  let exit_status = match status {
+    Ok(status) => status,
+    Err(e) => {
+      return CaseResult {
+        case: name,
+        flavor,
+        status: Status::Crashed,
+        events: None,
+        runtime_secs,
+        first_error: Some(format!("{e:#}")),
+      };
+    }
+  };
+
+  let status = if exit_status.success() {
+    Status::Passed
+  } else if exit_status.signal().is_some() {
+    Status::Crashed
+  } else {
+    Status::Failed
+  };
+  let first_error = match status {
+    Status::Passed => None,
+    Status::Failed => first_error(&log_path),
+    Status::Crashed => {
+      Some(first_error(&log_path).unwrap_or_else(|| format!("checker {exit_status}")))
+    }
+  };
+  info!("case {name} {status:?} in {runtime_secs:.2}s");
+
+  // absent if the checker crashed
+  let report: Option<CheckReport> =
+    fs::read_to_string(&report_path).ok().and_then(|content| serde_json::from_str(&content).ok());
+
+  CaseResult {
+    case: name,
+    flavor,
+    status,
+    events: report.map(|report| report.events),
+    runtime_secs,
+    first_error,
+  }
+}
+
+fn read_flavor(sim_result: &Path) -> Option<String> {
+  #[derive(Deserialize)]
+  struct Flavor {
+    flavor: String,
+  }
+
+  let content = fs::read_to_string(sim_result).ok()?;
+  serde_json::from_str::<Flavor>(&content).ok().map(|f| f.flavor)
+}
+
+// the error returned from main with its root cause, or the first panic,
+// scanned from the log file
+fn first_error(log_path: &Path) -> Option<String> {
+  let log = BufReader::new(File::open(log_path).ok()?);
+  let mut lines = log.lines().map_while(Result::ok).map(|line| strip_ansi(&line));
+  let mut panic = None;
+  while let Some(line) = lines.next() {
+    if let Some(error) = line.strip_prefix("Error: ") {
+      let cause = lines.by_ref().skip_while(|line| line != "Caused by:").nth(1).map(|line| {
+        let line = line.trim();
+        // multiple causes are numbered as "0: ..."
+        match line.split_once(": ") {
+          Some((n, cause)) if n.parse::<u32>().is_ok() => cause.to_string(),
+          _ => line.to_string(),
+        }
+      });
+      return Some(match cause {
+        Some(cause) => format!("{error}: {cause}"),
+        None => error.to_string(),
+      });
+    }
+    if panic.is_none() && line.contains("panicked at") {
+      panic = Some(match lines.next() {
+        Some(reason) => format!("{line} {reason}"),
+        None => line,
+      });
+    }
+  }
+  panic
+}
+
+fn strip_ansi(text: &str) -> String {
+  let mut stripped = String::with_capacity(text.len());
+  let mut chars = text.chars();
+  while let Some(c) = chars.next() {
+    if c == '\x1b' {
+      // CSI sequence: ESC '[' params final-byte
+      if chars.next() == Some('[') {
+        for c in chars.by_ref() {
+          if c.is_ascii_alphabetic() {
+            break;
+          }
+        }
+      }
+    } else {
+      stripped.push(c);
+    }
+  }
+  stripped
+}
+
+impl Summary<'_> {
+  fn to_markdown(&self) -> String {
+    let mut md = String::new();
+    writeln!(
+      md,
+      "{} cases: {} passed, {} failed\n",
+      self.total, self.passed, self.failed
+    )
+    .unwrap();
+    writeln!(
+      md,
+      "| case | flavor | status | events | runtime (s) | first error |"
+    )
+    .unwrap();
+    writeln!(md, "|---|---|---|---|---|---|").unwrap();
+    for result in self.cases {
+      let status = serde_json::to_value(result.status).unwrap();
+      writeln!(
+        md,
+        "| {} | {} | {} | {} | {:.2} | {} |",
+        result.case,
+        result.flavor.as_deref().unwrap_or("-"),
+        status.as_str().unwrap(),
+        result.events.map_or("-".to_string(), |events| events.to_string()),
+        result.runtime_secs,
+        result.first_error.as_deref().unwrap_or("").replace('|', "\\|"),
+      )
+      .unwrap();
+    }
+    md
+  }
+}
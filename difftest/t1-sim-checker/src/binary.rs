@@ -0,0 +1,166 @@
+//! Compact binary encoding of RTL events
+//!
+//! Layout of a binary event file:
+//! - header: `MAGIC`, one byte `VERSION`, one byte flavor length, flavor name
+//! - records: one byte tag followed by the fields of the variant,
+//!   integers are little endian, byte vectors are prefixed by u32 length,
+//!   masks are prefixed by u32 bit length and packed into bytes (LSB first)
+//!
+//! The encoding of each variant is defined by the flavor, see `BinaryEvent`.
+
+use std::io::{self, BufRead, Read, Write};
+
+use anyhow::{Context as _, bail, ensure};
+
+pub const MAGIC: &[u8; 4] = b"T1EV";
+pub const VERSION: u8 = 1;
+
+// tags of binary records shared by all flavors, flavor specific tags start from 0x10
+pub const TAG_ISSUE: u8 = 0x00;
+pub const TAG_LSU_ENQ: u8 = 0x01;
+pub const TAG_VRF_WRITE: u8 = 0x02;
+pub const TAG_MEMORY_WRITE: u8 = 0x03;
+pub const TAG_CHECK_RD: u8 = 0x04;
+pub const TAG_VRF_SCOREBOARD: u8 = 0x05;
+pub const TAG_VRF_SNAPSHOT: u8 = 0x06;
+pub const TAG_FINAL_STATE: u8 = 0x07;
+
+pub trait BinaryEvent: Sized {
+  fn encode<W: Write>(&self, w: &mut BinaryWriter<W>) -> io::Result<()>;
+
+  /// `tag` is the first byte of the record, which is already consumed
+  fn decode<R: Read>(tag: u8, r: &mut BinaryReader<R>) -> anyhow::Result<Self>;
+}
+
+pub struct BinaryWriter<W: Write> {
+  writer: W,
+}
+
+impl<W: Write> BinaryWriter<W> {
+  pub fn new(mut writer: W, flavor: &str) -> io::Result<Self> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, flavor.len() as u8])?;
+    writer.write_all(flavor.as_bytes())?;
+    Ok(BinaryWriter { writer })
+  }
+
+  pub fn write_event<E: BinaryEvent>(&mut self, event: &E) -> io::Result<()> {
+    event.encode(self)
+  }
+
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+
+  pub fn put_u8(&mut self, value: u8) -> io::Result<()> {
+    self.writer.write_all(&[value])
+  }
+
+  pub fn put_u32(&mut self, value: u32) -> io::Result<()> {
+    self.writer.write_all(&value.to_le_bytes())
+  }
+
+  pub fn put_u64(&mut self, value: u64) -> io::Result<()> {
+    self.writer.write_all(&value.to_le_bytes())
+  }
+
+  pub fn put_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+    self.put_u32(bytes.len() as u32)?;
+    self.writer.write_all(bytes)
+  }
+
+  pub fn put_mask(&mut self, mask: &[bool]) -> io::Result<()> {
+    self.put_u32(mask.len() as u32)?;
+    let packed: Vec<u8> = mask
+      .chunks(8)
+      .map(|bits| bits.iter().enumerate().fold(0u8, |acc, (i, &b)| acc | (b as u8) << i))
+      .collect();
+    self.writer.write_all(&packed)
+  }
+}
+
+pub struct BinaryReader<R: Read> {
+  record: usize,
+  reader: R,
+}
+
+impl<R: BufRead> BinaryReader<R> {
+  /// Consume the file header, check it against the expected flavor
+  pub fn new(mut reader: R, flavor: &str) -> anyhow::Result<Self> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic).context("in reading binary event header")?;
+    ensure!(
+      &magic == MAGIC,
+      "not a binary event file (bad magic {magic:02x?})"
+    );
+
+    let mut version_and_len = [0u8; 2];
+    reader.read_exact(&mut version_and_len).context("in reading binary event header")?;
+    let [version, flavor_len] = version_and_len;
+    ensure!(
+      version == VERSION,
+      "unsupported binary event version {version}, expected {VERSION}"
+    );
+
+    let mut file_flavor = vec![0u8; flavor_len as usize];
+    reader.read_exact(&mut file_flavor).context("in reading binary event header")?;
+    let file_flavor = String::from_utf8_lossy(&file_flavor);
+    ensure!(
+      file_flavor == flavor,
+      "binary event file is recorded for flavor '{file_flavor}', expected '{flavor}'"
+    );
+
+    Ok(BinaryReader { record: 0, reader })
+  }
+
+  pub fn next_event<E: BinaryEvent>(&mut self) -> anyhow::Result<Option<E>> {
+    // a clean end of file is only allowed at record boundary
+    if self.reader.fill_buf()?.is_empty() {
+      return Ok(None);
+    }
+
+    self.record += 1;
+    let tag = self.get_u8()?;
+    let event = E::decode(tag, self)
+      .with_context(|| format!("binary decoding error at record {}", self.record))?;
+    Ok(Some(event))
+  }
+}
+
+impl<R: Read> BinaryReader<R> {
+  pub fn get_u8(&mut self) -> anyhow::Result<u8> {
+    let mut buf = [0u8; 1];
+    self.reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+  }
+
+  pub fn get_u32(&mut self) -> anyhow::Result<u32> {
+    let mut buf = [0u8; 4];
+    self.reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+  }
+
+  pub fn get_u64(&mut self) -> anyhow::Result<u64> {
+    let mut buf = [0u8; 8];
+    self.reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+  }
+
+  pub fn get_bytes(&mut self) -> anyhow::Result<Vec<u8>> {
+    let len = self.get_u32()? as usize;
+    let mut buf = vec![0u8; len];
+    self.reader.read_exact(&mut buf)?;
+    Ok(buf)
+  }
+
+  pub fn get_mask(&mut self) -> anyhow::Result<Vec<bool>> {
+    let len = self.get_u32()? as usize;
+    let mut packed = vec![0u8; len.div_ceil(8)];
+    self.reader.read_exact(&mut packed)?;
+    Ok((0..len).map(|i| (packed[i / 8] >> (i % 8)) & 1 == 1).collect())
+  }
+}
+
+pub fn unknown_tag<T>(tag: u8) -> anyhow::Result<T> {
+  bail!("unknown binary event tag {tag:#04x}")
+}
@@ -0,0 +1,87 @@
+//! Failure context window
+//!
+//! Keeps the latest RTL events, and dumps them together with the latest
+//! spike steps and the queues of `SpikeRunner` when a check fails.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+
+use spike_rs::runner::SpikeRunner;
+use spike_rs::spike_event::SpikeEvent;
+use tracing::error;
+
+pub struct FailureContext<E: Debug> {
+  /// (event index, event), the latest is at the back
+  events: VecDeque<(u64, E)>,
+  depth: usize,
+}
+
+impl<E: Debug> FailureContext<E> {
+  pub fn new(depth: usize) -> Self {
+    FailureContext { events: VecDeque::with_capacity(depth), depth }
+  }
+
+  pub fn push(&mut self, event_count: u64, event: E) {
+    if self.depth == 0 {
+      return;
+    }
+    if self.events.len() == self.depth {
+      self.events.pop_front();
+    }
+    self.events.push_back((event_count, event));
+  }
+
+  pub fn dump(&self, runner: &SpikeRunner) {
+    error!("==== last {} rtl events ====", self.events.len());
+    for (event_count, event) in &self.events {
+      error!("#{event_count}: {event:?}");
+    }
+
+    error!("==== last {} spike steps ====", runner.step_history.len());
+    for step in &runner.step_history {
+      error!(
+        "spike_cycle={}, pc={:#x}, disasm='{}', bits={:#x}, queue len (commit/vector/scalar/float)={}/{}/{}/{}",
+        step.spike_cycle,
+        step.pc,
+        step.disasm,
+        step.inst_bits,
+        step.commit_queue_len,
+        step.vector_queue_len,
+        step.scalar_queue_len,
+        step.float_queue_len,
+      );
+    }
+
+    dump_queue("commit_queue", &runner.commit_queue);
+    dump_queue("vector_queue", &runner.vector_queue);
+    dump_queue("scalar_queue", &runner.scalar_queue);
+    dump_queue("float_queue", &runner.float_queue);
+  }
+}
+
+// the back of the queue is the oldest event
+fn dump_queue<'a>(name: &str, queue: impl IntoIterator<Item = &'a SpikeEvent>) {
+  let queue: Vec<_> = queue.into_iter().collect();
+  error!("==== {name} ({} entries, oldest first) ====", queue.len());
+  for se in queue.iter().rev() {
+    let vrf_writes = &se.vrf_access_record;
+    let mem_writes = &se.mem_access_record.all_writes;
+    error!(
+      "issue_idx={}, lsu_idx={}, vrf writes executed={}/{}, scoreboard (retired/unretired)={}/{:?}, \
+      mem writes completed={}/{}, rd[{}]={:#x} (written={}, fd={}) ({})",
+      se.issue_idx,
+      se.lsu_idx,
+      vrf_writes.all_writes.values().filter(|w| w.executed).count(),
+      vrf_writes.all_writes.len(),
+      vrf_writes.retired_writes,
+      vrf_writes.unretired_writes,
+      mem_writes.values().map(|w| w.num_completed_writes).sum::<usize>(),
+      mem_writes.values().map(|w| w.writes.len()).sum::<usize>(),
+      se.rd_idx,
+      se.rd_bits,
+      se.is_rd_written,
+      se.is_fd_written,
+      se.describe_insn()
+    );
+  }
+}
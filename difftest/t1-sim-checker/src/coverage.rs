@@ -0,0 +1,160 @@
+//! Vector instruction coverage
+//!
+//! Every vector instruction issued to the RTL is classified by
+//! mnemonic × SEW × LMUL × vl × masked × vstart≠0.
+//! Reports are written as JSON or CSV, JSON reports can be merged across runs.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context as _, bail};
+use serde::{Deserialize, Serialize};
+use spike_rs::spike_event::SpikeEvent;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum VlClass {
+  /// vl = 0
+  Zero,
+  /// 0 < vl < VLMAX
+  Partial,
+  /// vl = VLMAX
+  Full,
+  /// vl > VLMAX of the current vtype
+  OverVlmax,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CoveragePoint {
+  pub mnemonic: String,
+  pub sew: u32,
+  /// "mf8", "mf4", "mf2", "m1", "m2", "m4", "m8" or "reserved"
+  pub lmul: String,
+  pub vl: VlClass,
+  pub masked: bool,
+  pub vstart_nonzero: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CoverageEntry {
+  #[serde(flatten)]
+  point: CoveragePoint,
+  count: u64,
+}
+
+#[derive(Default)]
+pub struct Coverage {
+  points: BTreeMap<CoveragePoint, u64>,
+}
+
+impl CoveragePoint {
+  pub fn from_se(se: &SpikeEvent, vlen: u32) -> Self {
+    let mnemonic = se.disasm.split_whitespace().next().unwrap_or_default().to_string();
+    let sew = 8 << se.vsew();
+
+    // VLMAX = LMUL * VLEN / SEW, in eighths of LMUL to cover fractional LMUL
+    let (lmul, lmul_in_eighths) = match se.vlmul() {
+      0 => ("m1", 8),
+      1 => ("m2", 16),
+      2 => ("m4", 32),
+      3 => ("m8", 64),
+      5 => ("mf8", 1),
+      6 => ("mf4", 2),
+      7 => ("mf2", 4),
+      _ => ("reserved", 0),
+    };
+    let vlmax = lmul_in_eighths * vlen / sew / 8;
+
+    let vl = match se.vl {
+      0 => VlClass::Zero,
+      vl if vl < vlmax => VlClass::Partial,
+      vl if vl == vlmax => VlClass::Full,
+      _ => VlClass::OverVlmax,
+    };
+
+    CoveragePoint {
+      mnemonic,
+      sew,
+      lmul: lmul.to_string(),
+      vl,
+      masked: !se.vm(),
+      vstart_nonzero: se.vstart != 0,
+    }
+  }
+}
+
+impl Coverage {
+  pub fn record(&mut self, se: &SpikeEvent, vlen: u32) {
+    *self.points.entry(CoveragePoint::from_se(se, vlen)).or_default() += 1;
+  }
+
+  pub fn merge(&mut self, other: Coverage) {
+    for (point, count) in other.points {
+      *self.points.entry(point).or_default() += count;
+    }
+  }
+
+  /// Load a JSON report
+  pub fn load(path: &Path) -> anyhow::Result<Self> {
+    let file = File::open(path).with_context(|| format!("in open coverage report {path:?}"))?;
+    let entries: Vec<CoverageEntry> = serde_json::from_reader(BufReader::new(file))
+      .with_context(|| format!("in parsing coverage report {path:?}"))?;
+
+    let mut coverage = Coverage::default();
+    for entry in entries {
+      *coverage.points.entry(entry.point).or_default() += entry.count;
+    }
+    Ok(coverage)
+  }
+
+  /// Write the report, in CSV if the path ends with '.csv', otherwise in JSON
+  pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+    let is_csv = match path.extension().and_then(|ext| ext.to_str()) {
+      Some("csv") => true,
+      Some("json") | None => false,
+      Some(ext) => bail!("unknown coverage report format '{ext}', expected 'json' or 'csv'"),
+    };
+
+    let file = File::create(path).with_context(|| format!("in create coverage report {path:?}"))?;
+    let mut writer = BufWriter::new(file);
+    if is_csv {
+      self.write_csv(&mut writer)?;
+    } else {
+      self.write_json(&mut writer)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+  }
+
+  fn write_json(&self, writer: &mut impl Write) -> anyhow::Result<()> {
+    let entries: Vec<_> = self
+      .points
+      .iter()
+      .map(|(point, &count)| CoverageEntry { point: point.clone(), count })
+      .collect();
+    serde_json::to_writer_pretty(&mut *writer, &entries)?;
+    writeln!(writer)?;
+    Ok(())
+  }
+
+  fn write_csv(&self, writer: &mut impl Write) -> anyhow::Result<()> {
+    writeln!(writer, "mnemonic,sew,lmul,vl,masked,vstart_nonzero,count")?;
+    for (point, count) in &self.points {
+      let vl = serde_json::to_value(point.vl)?;
+      writeln!(
+        writer,
+        "{},{},{},{},{},{},{count}",
+        point.mnemonic,
+        point.sew,
+        point.lmul,
+        vl.as_str().unwrap(),
+        point.masked,
+        point.vstart_nonzero,
+      )?;
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,149 @@
+//! The checking loop of the RTL events of all flavors against spike
+
+use std::fmt::Debug;
+use std::panic::{self, AssertUnwindSafe};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use spike_rs::runner::{SpikeArgs, SpikeRunner};
+use tracing::error;
+
+use crate::DiffOptions;
+use crate::binary::BinaryEvent;
+use crate::context::FailureContext;
+use crate::coverage::Coverage;
+use crate::latency::{LatencyEvent, LatencyTracker};
+use crate::reproducer::{DivergenceTracker, panic_message};
+use crate::util::{EventPipeline, EventReader};
+
+/// An RTL event of a flavor
+pub(crate) trait DiffEvent:
+  for<'a> Deserialize<'a> + BinaryEvent + Debug + Send + 'static
+{
+  /// Check the event against spike
+  fn diff(&self, runner: &mut SpikeRunner) -> anyhow::Result<()>;
+
+  /// The event as seen by the latency tracker, with its cycle
+  fn latency_event(&self) -> (LatencyEvent, u64);
+
+  /// The issue_idx of an issue event
+  fn issued(&self) -> Option<u8>;
+
+  /// The issue_idx of the instruction the event belongs to
+  fn issue_idx(&self) -> Option<u8>;
+
+  fn lsu_idx(&self) -> Option<u8>;
+
+  fn vrf_snapshot(&self) -> Option<&[u8]>;
+}
+
+/// Check the RTL events of `flavor` against spike.
+/// `event_count` is the number of events processed, also when failing
+pub(crate) fn run_diff<E: DiffEvent>(
+  flavor: &'static str,
+  spike_args: &SpikeArgs,
+  options: &DiffOptions,
+  event_count: &mut u64,
+) -> anyhow::Result<()> {
+  let rtl_event_path = spike_args.rtl_event_file.as_ref().unwrap();
+
+  let mut runner = SpikeRunner::new(spike_args, true);
+  runner.step_history_depth = options.context_steps;
+  let reader = EventReader::open(rtl_event_path, flavor, options.follow)?;
+  let mut events = EventPipeline::<E>::spawn(reader, options.event_queue);
+  let mut context = FailureContext::new(options.context_events);
+  let mut coverage = options.coverage.as_ref().map(|_| Coverage::default());
+  let mut latency = if options.latency_csv.is_some() || options.latency_summary.is_some() {
+    Some(LatencyTracker::new(options.latency_csv.as_deref())?)
+  } else {
+    None
+  };
+  let mut tracker = options
+    .reproducer
+    .as_ref()
+    .map(|dir| DivergenceTracker::new(dir, flavor, runner.vlen, options.context_events));
+
+  *event_count = 0;
+  loop {
+    let event = match events.try_next_event() {
+      Some(event) => event?,
+      None => {
+        // step spike ahead while the decoder catches up
+        runner.prefetch(options.lookahead.saturating_sub(runner.prefetched_len()));
+        events.next_event()?
+      }
+    };
+    let Some(event) = event else { break };
+
+    *event_count += 1;
+    // the checks may assert, catch the panic to dump the context
+    match panic::catch_unwind(AssertUnwindSafe(|| event.diff(&mut runner))) {
+      Ok(Ok(())) => {
+        if let (Some(coverage), Some(issued)) = (&mut coverage, event.issued()) {
+          // the issued se is at the front of commit queue
+          if let Some(se) = runner.commit_queue.front().filter(|se| se.issue_idx == issued) {
+            coverage.record(se, runner.vlen);
+          }
+        }
+        if let Some(latency) = &mut latency {
+          let (latency_event, cycle) = event.latency_event();
+          latency.observe(&runner, latency_event, cycle)?;
+        }
+        if let Some(t) = &mut tracker
+          && let Some(divergence) =
+            t.observe(&runner, *event_count, event.issued(), event.vrf_snapshot())
+        {
+          error!("difftest failed at event #{event_count}: {event:?}");
+          context.dump(&runner);
+          tracker.unwrap().report::<E>(runner, spike_args, &divergence)?;
+          return Err(
+            anyhow!(divergence.reason).context(format!("difftest failed at event {event_count}")),
+          );
+        }
+        context.push(*event_count, event);
+      }
+      Ok(Err(e)) => {
+        error!("difftest failed at event #{event_count}: {event:?}");
+        context.dump(&runner);
+        if let Some(tracker) = tracker {
+          let divergence = tracker.check_failure(
+            &runner,
+            *event_count,
+            format!("{e:#}"),
+            event.issue_idx(),
+            event.lsu_idx(),
+          );
+          tracker.report::<E>(runner, spike_args, &divergence)?;
+        }
+        return Err(e.context(format!("difftest failed at event {event_count}")));
+      }
+      Err(panic) => {
+        error!("difftest panicked at event #{event_count}: {event:?}");
+        context.dump(&runner);
+        if let Some(tracker) = tracker {
+          let divergence = tracker.check_failure(
+            &runner,
+            *event_count,
+            panic_message(&panic),
+            event.issue_idx(),
+            event.lsu_idx(),
+          );
+          tracker.report::<E>(runner, spike_args, &divergence)?;
+        }
+        panic::resume_unwind(panic);
+      }
+    }
+  }
+
+  eprintln!("Tototally {event_count} events processed");
+
+  if let (Some(coverage), Some(path)) = (&coverage, &options.coverage) {
+    coverage.save(path)?;
+  }
+
+  if let Some(latency) = latency {
+    latency.finish(options.latency_summary.as_deref())?;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,85 @@
+//! Final architectural state comparison
+//!
+//! With '+t1_dev_final_state=1', the TestBench dumps its VRF/XRF/FRF at exit, kept from
+//! the register write probes. Spike is stepped to its exit instruction, and the dump is
+//! compared with spike's final state byte by byte, which catches writes never reported
+//! by any event.
+
+use anyhow::bail;
+use spike_rs::runner::SpikeRunner;
+use tracing::{error, info};
+
+/// spike steps allowed between the last checked event and the exit
+const MAX_STEPS_TO_EXIT: u64 = 1 << 24;
+
+/// mismatches listed in the error
+const MAX_REPORTED_MISMATCHES: usize = 16;
+
+/// An empty `xrf` or `frf` is not dumped by the RTL, thus not compared
+pub fn check_final_state(
+  runner: &mut SpikeRunner,
+  cycle: u64,
+  vrf: &[u8],
+  xrf: &[u32],
+  frf: &[u32],
+) -> anyhow::Result<()> {
+  let steps = runner.step_to_exit(MAX_STEPS_TO_EXIT)?;
+  if steps > 0 {
+    info!("[{cycle}] FinalState: spike stepped {steps} instructions to exit");
+  }
+
+  let state = runner.arch_state();
+  let vlen_in_bytes = runner.vlen as usize / 8;
+  let mut mismatches = vec![];
+
+  // the hex string of the dump drops leading zeros
+  for (offset, &spike_byte) in state.vrf.iter().enumerate() {
+    let rtl_byte = vrf.get(offset).copied().unwrap_or(0);
+    if rtl_byte != spike_byte {
+      mismatches.push(format!(
+        "v{}[{}] rtl={rtl_byte:#04x} spike={spike_byte:#04x}",
+        offset / vlen_in_bytes,
+        offset % vlen_in_bytes
+      ));
+    }
+  }
+  for (name, rtl_regs, spike_regs) in [("x", xrf, &state.xrf), ("f", frf, &state.frf)] {
+    if rtl_regs.is_empty() {
+      continue;
+    }
+    if rtl_regs.len() != spike_regs.len() {
+      bail!(
+        "[{cycle}] FinalState: {} {name} registers dumped, expect {}",
+        rtl_regs.len(),
+        spike_regs.len()
+      );
+    }
+    for (idx, (rtl_reg, spike_reg)) in rtl_regs.iter().zip(spike_regs).enumerate() {
+      if rtl_reg != spike_reg {
+        mismatches.push(format!(
+          "{name}{idx} rtl={rtl_reg:#010x} spike={spike_reg:#010x}"
+        ));
+      }
+    }
+  }
+
+  if mismatches.is_empty() {
+    info!("[{cycle}] FinalState: matched");
+    return Ok(());
+  }
+
+  for mismatch in &mismatches {
+    error!("[{cycle}] FinalState: {mismatch}");
+  }
+  let listed = mismatches.iter().take(MAX_REPORTED_MISMATCHES).cloned().collect::<Vec<_>>();
+  bail!(
+    "[{cycle}] FinalState: {} mismatches: {}{}",
+    mismatches.len(),
+    listed.join(", "),
+    if mismatches.len() > listed.len() {
+      ", ..."
+    } else {
+      ""
+    }
+  );
+}
@@ -0,0 +1,273 @@
+//! Issue-to-retire latency statistics
+//!
+//! Tracks every issued vector instruction by its issue_idx, and measures
+//! - issue -> first VRF write
+//! - issue -> retire
+//! - LSU enqueue -> last memory write
+//!
+//! Results are aggregated by mnemonic and vector config (SEW, LMUL) into histograms,
+//! and optionally written per instruction as CSV.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Context as _;
+use serde::Serialize;
+use spike_rs::runner::SpikeRunner;
+use spike_rs::spike_event::LSU_IDX_DEFAULT;
+
+use crate::coverage::CoveragePoint;
+
+/// The part of an RTL event relevant to latency tracking
+pub enum LatencyEvent {
+  Issue { idx: u8 },
+  VrfWrite { issue_idx: u8 },
+  LsuEnq,
+  MemoryWrite { lsu_idx: u8 },
+  Other,
+}
+
+struct Inflight {
+  pc: u64,
+  disasm: String,
+  point: CoveragePoint,
+  vl: u32,
+  issue_idx: u8,
+  issue_cycle: u64,
+  first_vrf_write: Option<u64>,
+  lsu_idx: Option<u8>,
+  lsu_enq: Option<u64>,
+  last_memory_write: Option<u64>,
+}
+
+/// Latency histogram with power-of-two buckets:
+/// bucket 0 counts latency 0, bucket k counts latency in [2^(k-1), 2^k)
+#[derive(Serialize, Default)]
+pub struct LatencyStats {
+  count: u64,
+  min: u64,
+  max: u64,
+  mean: f64,
+  histogram: Vec<HistogramBucket>,
+
+  #[serde(skip)]
+  sum: u64,
+  #[serde(skip)]
+  buckets: Vec<u64>,
+}
+
+/// Non-empty bucket of the histogram, serialized in increasing latency
+#[derive(Serialize)]
+struct HistogramBucket {
+  range: String,
+  count: u64,
+}
+
+impl LatencyStats {
+  fn add(&mut self, latency: u64) {
+    self.min = if self.count == 0 {
+      latency
+    } else {
+      self.min.min(latency)
+    };
+    self.max = self.max.max(latency);
+    self.count += 1;
+    self.sum += latency;
+
+    let bucket = (u64::BITS - latency.leading_zeros()) as usize;
+    if self.buckets.len() <= bucket {
+      self.buckets.resize(bucket + 1, 0);
+    }
+    self.buckets[bucket] += 1;
+  }
+
+  fn finish(&mut self) {
+    self.mean = if self.count == 0 {
+      0.0
+    } else {
+      self.sum as f64 / self.count as f64
+    };
+    self.histogram = self
+      .buckets
+      .iter()
+      .enumerate()
+      .filter(|&(_, &n)| n > 0)
+      .map(|(bucket, &n)| {
+        let range = match bucket {
+          0 => "0".to_string(),
+          1 => "1".to_string(),
+          _ => format!("{}-{}", 1u64 << (bucket - 1), (1u64 << bucket) - 1),
+        };
+        HistogramBucket { range, count: n }
+      })
+      .collect();
+  }
+}
+
+#[derive(Serialize, Default)]
+struct LatencyGroup {
+  mnemonic: String,
+  sew: u32,
+  lmul: String,
+  issue_to_first_vrf_write: LatencyStats,
+  issue_to_retire: LatencyStats,
+  lsu_enq_to_last_memory_write: LatencyStats,
+}
+
+pub struct LatencyTracker {
+  inflights: Vec<Inflight>,
+  groups: BTreeMap<(String, u32, String), LatencyGroup>,
+  csv: Option<BufWriter<File>>,
+}
+
+impl LatencyTracker {
+  pub fn new(csv_path: Option<&Path>) -> anyhow::Result<Self> {
+    let csv = match csv_path {
+      Some(path) => {
+        let file = File::create(path).with_context(|| format!("in create {path:?}"))?;
+        let mut csv = BufWriter::new(file);
+        writeln!(
+          csv,
+          "pc,disasm,mnemonic,sew,lmul,vl,issue_cycle,retire_cycle,\
+          issue_to_first_vrf_write,issue_to_retire,lsu_enq_to_last_memory_write"
+        )?;
+        Some(csv)
+      }
+      None => None,
+    };
+
+    Ok(LatencyTracker { inflights: vec![], groups: BTreeMap::new(), csv })
+  }
+
+  /// Called after the event is successfully checked
+  pub fn observe(
+    &mut self,
+    runner: &SpikeRunner,
+    event: LatencyEvent,
+    cycle: u64,
+  ) -> anyhow::Result<()> {
+    match event {
+      LatencyEvent::Issue { idx } => {
+        // the issued se is at the front of commit queue
+        if let Some(se) = runner.commit_queue.front().filter(|se| se.issue_idx == idx) {
+          self.inflights.push(Inflight {
+            pc: se.pc,
+            disasm: se.disasm.clone(),
+            point: CoveragePoint::from_se(se, runner.vlen),
+            vl: se.vl,
+            issue_idx: idx,
+            issue_cycle: cycle,
+            first_vrf_write: None,
+            lsu_idx: None,
+            lsu_enq: None,
+            last_memory_write: None,
+          });
+        }
+      }
+      LatencyEvent::VrfWrite { issue_idx } => {
+        if let Some(inflight) = self.inflights.iter_mut().find(|i| i.issue_idx == issue_idx) {
+          inflight.first_vrf_write.get_or_insert(cycle);
+        }
+      }
+      LatencyEvent::LsuEnq => {
+        for inflight in self.inflights.iter_mut().filter(|i| i.lsu_idx.is_none()) {
+          let se = runner.commit_queue.iter().find(|se| se.issue_idx == inflight.issue_idx);
+          if let Some(se) = se.filter(|se| se.lsu_idx != LSU_IDX_DEFAULT) {
+            inflight.lsu_idx = Some(se.lsu_idx);
+            inflight.lsu_enq = Some(cycle);
+          }
+        }
+      }
+      LatencyEvent::MemoryWrite { lsu_idx } => {
+        if let Some(inflight) = self.inflights.iter_mut().find(|i| i.lsu_idx == Some(lsu_idx)) {
+          inflight.last_memory_write = Some(cycle);
+        }
+      }
+      LatencyEvent::Other => {}
+    }
+
+    // instructions gone from commit queue are retired in this event
+    let mut idx = 0;
+    while idx < self.inflights.len() {
+      let issue_idx = self.inflights[idx].issue_idx;
+      if runner.commit_queue.iter().any(|se| se.issue_idx == issue_idx) {
+        idx += 1;
+      } else {
+        let inflight = self.inflights.remove(idx);
+        self.retire(inflight, cycle)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  fn retire(&mut self, inflight: Inflight, cycle: u64) -> anyhow::Result<()> {
+    let point = &inflight.point;
+    let first_vrf_write = inflight.first_vrf_write.map(|c| c - inflight.issue_cycle);
+    let retire = cycle - inflight.issue_cycle;
+    let memory_write =
+      inflight.lsu_enq.zip(inflight.last_memory_write).map(|(enq, last)| last - enq);
+
+    let group = self
+      .groups
+      .entry((point.mnemonic.clone(), point.sew, point.lmul.clone()))
+      .or_insert_with(|| LatencyGroup {
+        mnemonic: point.mnemonic.clone(),
+        sew: point.sew,
+        lmul: point.lmul.clone(),
+        ..Default::default()
+      });
+    if let Some(latency) = first_vrf_write {
+      group.issue_to_first_vrf_write.add(latency);
+    }
+    group.issue_to_retire.add(retire);
+    if let Some(latency) = memory_write {
+      group.lsu_enq_to_last_memory_write.add(latency);
+    }
+
+    if let Some(csv) = &mut self.csv {
+      let optional = |latency: Option<u64>| latency.map(|l| l.to_string()).unwrap_or_default();
+      writeln!(
+        csv,
+        "{:#x},\"{}\",{},{},{},{},{},{cycle},{},{retire},{}",
+        inflight.pc,
+        inflight.disasm.replace('"', "\"\""),
+        point.mnemonic,
+        point.sew,
+        point.lmul,
+        inflight.vl,
+        inflight.issue_cycle,
+        optional(first_vrf_write),
+        optional(memory_write),
+      )?;
+    }
+
+    Ok(())
+  }
+
+  /// Flush the per-instruction CSV, and write the aggregated histograms as JSON
+  pub fn finish(mut self, summary_path: Option<&Path>) -> anyhow::Result<()> {
+    if let Some(csv) = &mut self.csv {
+      csv.flush()?;
+    }
+
+    if let Some(path) = summary_path {
+      let mut groups: Vec<_> = self.groups.into_values().collect();
+      for group in &mut groups {
+        group.issue_to_first_vrf_write.finish();
+        group.issue_to_retire.finish();
+        group.lsu_enq_to_last_memory_write.finish();
+      }
+
+      let file = File::create(path).with_context(|| format!("in create {path:?}"))?;
+      let mut writer = BufWriter::new(file);
+      serde_json::to_writer_pretty(&mut writer, &groups)?;
+      writeln!(writer)?;
+      writer.flush()?;
+    }
+
+    Ok(())
+  }
+}
@@ -1,63 +1,241 @@
-use std::{fs::read_to_string, path::PathBuf};
+use std::{
+  fs::read_to_string,
+  io::IsTerminal,
+  panic::{self, AssertUnwindSafe},
+  path::PathBuf,
+  time::Duration,
+};
 
 use anyhow::{Context, bail};
-use clap::Parser;
-use serde::Deserialize;
+use clap::{Args, Parser, Subcommand};
+use coverage::Coverage;
+use serde::{Deserialize, Serialize};
+use sim_schema::SimResult;
 use spike_rs::runner::SpikeArgs;
 use tracing::Level;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
+mod batch;
+pub(crate) mod binary;
+pub(crate) mod context;
+pub(crate) mod coverage;
+pub(crate) mod diff;
+pub(crate) mod final_state;
+pub(crate) mod latency;
+pub(crate) mod reproducer;
+mod run;
 mod t1emu;
 mod t1rocketemu;
 pub(crate) mod util;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct SimCheckerArgs {
-  /// Path to sim result json
+  #[command(subcommand)]
+  pub command: Option<Command>,
+
+  #[command(flatten)]
+  pub config: SpikeConfigArgs,
+
+  /// Path to the rtl event log file, '-' for stdin
+  /// (json lines or binary events, optionally compressed by gzip or zstd)
+  #[arg(long, required = true)]
+  pub rtl_event_file: Option<PathBuf>,
+
+  /// Flavor of the simulation: t1emu, t1rocketemu
+  /// (override that in sim result json, required without --sim-result)
   #[arg(long)]
-  pub sim_result: PathBuf,
+  pub flavor: Option<String>,
+
+  /// Keep reading the rtl event file while it is being written,
+  /// stop when no new event arrives within --follow-timeout
+  #[arg(long)]
+  pub follow: bool,
+
+  /// Idle timeout in seconds for --follow
+  #[arg(long, default_value_t = 60)]
+  pub follow_timeout: u64,
+
+  /// Number of latest rtl events to dump on failure
+  #[arg(long, default_value_t = 32)]
+  pub context_events: usize,
+
+  /// Number of latest spike steps to dump on failure
+  #[arg(long, default_value_t = 32)]
+  pub context_steps: usize,
 
-  /// Path to the rtl event log file
+  /// Path to write the vector instruction coverage report
+  /// (CSV if ends with '.csv', otherwise JSON)
   #[arg(long)]
-  pub rtl_event_file: PathBuf,
+  pub coverage: Option<PathBuf>,
+
+  /// Path to write the per-instruction latency CSV
+  #[arg(long)]
+  pub latency_csv: Option<PathBuf>,
+
+  /// Path to write the latency histograms grouped by mnemonic, SEW and LMUL (JSON)
+  #[arg(long)]
+  pub latency_summary: Option<PathBuf>,
+
+  /// Directory to write a reproducer bundle of the first divergence, also enables comparing
+  /// the VRF snapshots in rtl events against spike (emitted with +t1_dev_vrf_snapshot=1)
+  #[arg(long)]
+  pub reproducer: Option<PathBuf>,
+
+  /// Capacity of the queue between the event decoder thread and the checker
+  #[arg(long, default_value_t = 4096)]
+  pub event_queue: usize,
+
+  /// Max number of spike steps executed ahead while waiting for decoded events
+  /// (0 to disable)
+  #[arg(long, default_value_t = 1024)]
+  pub lookahead: usize,
+
+  /// Path to write the number of processed events and whether the check passed (JSON),
+  /// also written when the check fails
+  #[arg(long)]
+  pub report_json: Option<PathBuf>,
+
+  /// Log level: trace, debug, info, warn, error
+  #[arg(long, default_value = "info")]
+  pub log_level: String,
+}
+
+/// Written by `--report-json`, read by the batch subcommand
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CheckReport {
+  pub events: u64,
+  pub passed: bool,
+}
+
+/// Spike configuration, taken from sim result json and overridden by the command line
+#[derive(Args, Debug)]
+pub struct SpikeConfigArgs {
+  /// Path to sim result json
+  /// (if absent, metadata is taken from --elf-file and the overrides)
+  #[arg(long)]
+  pub sim_result: Option<PathBuf>,
 
   /// Path to the ELF file
   /// (override that in sim result json)
   #[arg(long)]
   pub elf_file: Option<PathBuf>,
 
-  /// Override VLEN (debug only)
+  /// Override VLEN (required without --sim-result)
   #[arg(long)]
   pub vlen_override: Option<u32>,
 
-  /// Override DLEN (debug only)
+  /// Override DLEN (required without --sim-result)
   #[arg(long)]
   pub dlen_override: Option<u32>,
 
-  /// Override LANE WIDTH (debug only)
+  /// Override LANE WIDTH (required without --sim-result)
   #[arg(long)]
   pub lane_width_override: Option<u32>,
 
-  /// Override ISA (debug only)
+  /// Override ISA (required without --sim-result)
   #[arg(long)]
   pub isa_override: Option<String>,
+}
 
-  /// Log level: trace, debug, info, warn, error
-  #[arg(long, default_value = "info")]
-  pub log_level: String,
+#[derive(Subcommand, Debug)]
+pub enum Command {
+  /// Run the ELF in spike only, and print instruction statistics
+  Run {
+    #[command(flatten)]
+    config: SpikeConfigArgs,
+
+    /// Stop after executing the given number of instructions
+    #[arg(long)]
+    max_insns: Option<u64>,
+
+    /// Stop before executing the instruction at the symbol
+    #[arg(long)]
+    stop_at: Option<String>,
+
+    /// Path to write the executed instructions
+    #[arg(long)]
+    trace: Option<PathBuf>,
+  },
+
+  /// Convert a json lines rtl event file into the compact binary encoding
+  Convert {
+    /// Flavor of the event file: t1emu, t1rocketemu
+    #[arg(long)]
+    flavor: String,
+
+    /// Path to the json lines event file (optionally compressed)
+    #[arg(long)]
+    input: PathBuf,
+
+    /// Path to the binary event file,
+    /// compressed if ends with '.gz', '.zst' or '.zstd'
+    #[arg(long)]
+    output: PathBuf,
+  },
+
+  /// Check many cases in parallel, and write a summary table
+  Batch {
+    /// A directory searched recursively for 'sim_result.json' with 'rtl-event.*' next to it,
+    /// or a JSON manifest: [{ "name": .., "sim_result": .., "rtl_event_file": .. }]
+    input: PathBuf,
+
+    /// Number of cases checked in parallel (default: number of CPUs)
+    #[arg(long, short)]
+    jobs: Option<usize>,
+
+    /// Path to write the summary in JSON
+    #[arg(long)]
+    summary_json: Option<PathBuf>,
+
+    /// Path to write the summary in Markdown
+    #[arg(long)]
+    summary_markdown: Option<PathBuf>,
+
+    /// Directory to keep the log of every case, named `<index>-<case>.log`
+    #[arg(long)]
+    log_dir: Option<PathBuf>,
+
+    /// Extra arguments passed to the check of every case
+    #[arg(last = true)]
+    checker_args: Vec<String>,
+  },
+
+  /// Merge JSON coverage reports of several runs
+  MergeCoverage {
+    /// Path to the merged report (CSV if ends with '.csv', otherwise JSON)
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Paths to the JSON coverage reports
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+  },
 }
 
-// contains only fields used by t1-sim-checker
-#[derive(Deserialize, Debug)]
-struct SimResult {
-  flavor: String,
-  meta_vlen: u32,
-  meta_dlen: u32,
-  meta_lane_width: u32,
-  meta_isa: String,
-  meta_elf_file: Option<PathBuf>,
-  success: bool,
+/// Options of a difftest run, other than the spike configuration
+pub(crate) struct DiffOptions {
+  /// wait for new events at end of file, with the given idle timeout
+  pub follow: Option<Duration>,
+
+  /// size of the failure context window
+  pub context_events: usize,
+  pub context_steps: usize,
+
+  /// path of the coverage report, if enabled
+  pub coverage: Option<PathBuf>,
+
+  /// paths of the latency reports, tracking is enabled if any is given
+  pub latency_csv: Option<PathBuf>,
+  pub latency_summary: Option<PathBuf>,
+
+  /// directory of the reproducer bundle, if enabled
+  pub reproducer: Option<PathBuf>,
+
+  /// pipelining of event decoding and spike stepping
+  pub event_queue: usize,
+  pub lookahead: usize,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -65,49 +243,152 @@ fn main() -> anyhow::Result<()> {
 
   init_logger(&args.log_level);
 
-  let sim_result = read_to_string(&args.sim_result).context("in open sim result json file")?;
-  let sim_result: SimResult =
-    serde_json::from_str(&sim_result).context("in parsing sim result file")?;
+  if let Some(command) = &args.command {
+    return match command {
+      Command::Convert { flavor, input, output } => match flavor.as_str() {
+        "t1emu" => t1emu::convert(input, output),
+        "t1rocketemu" => t1rocketemu::convert(input, output),
+        _ => bail!("unknown flavor '{flavor}', expected 't1emu' or 't1rocketemu'"),
+      },
+      Command::Run { config, max_insns, stop_at, trace } => {
+        let sim_result = config.load_sim_result()?;
+        let spike_args = config.spike_args(&sim_result, None)?;
+        let options = run::RunOptions {
+          max_insns: *max_insns,
+          stop_at: stop_at.as_deref(),
+          trace: trace.as_deref(),
+        };
+        run::run_spike(&spike_args, &options)
+      }
+      Command::Batch {
+        input,
+        jobs,
+        summary_json,
+        summary_markdown,
+        log_dir,
+        checker_args,
+      } => {
+        let jobs = match jobs {
+          Some(jobs) => *jobs,
+          None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+        batch::run_batch(&batch::BatchOptions {
+          input,
+          jobs,
+          summary_json: summary_json.as_deref(),
+          summary_markdown: summary_markdown.as_deref(),
+          log_dir: log_dir.as_deref(),
+          checker_args,
+        })
+      }
+      Command::MergeCoverage { output, inputs } => {
+        let mut merged = Coverage::default();
+        for input in inputs {
+          merged.merge(Coverage::load(input)?);
+        }
+        merged.save(output)
+      }
+    };
+  }
+
+  let rtl_event_file = args.rtl_event_file.as_ref().unwrap();
 
+  if args.config.sim_result.is_none() && args.flavor.is_none() {
+    bail!("'--flavor' is required without '--sim-result'");
+  }
+
+  let sim_result = args.config.load_sim_result()?;
   if !sim_result.success {
     bail!("online run is unsuccessful");
   }
 
-  let vlen = args.vlen_override.unwrap_or(sim_result.meta_vlen);
-  let dlen = args.dlen_override.unwrap_or(sim_result.meta_dlen);
-  let lane_width = args.lane_width_override.unwrap_or(sim_result.meta_lane_width);
-  let isa = args.isa_override.as_ref().unwrap_or(&sim_result.meta_isa);
-  let elf_file = if let Some(elf_file) = &args.elf_file {
-    elf_file
-  } else if let Some(elf_file) = &sim_result.meta_elf_file {
-    elf_file
-  } else {
-    bail!("neither cmd args nor sim_result.json contains elf path");
+  let flavor = args.flavor.as_ref().unwrap_or(&sim_result.flavor);
+  let spike_args = args.config.spike_args(&sim_result, Some(rtl_event_file.clone()))?;
+
+  let options = DiffOptions {
+    follow: args.follow.then(|| Duration::from_secs(args.follow_timeout)),
+    context_events: args.context_events,
+    context_steps: args.context_steps,
+    coverage: args.coverage.clone(),
+    latency_csv: args.latency_csv.clone(),
+    latency_summary: args.latency_summary.clone(),
+    reproducer: args.reproducer.clone(),
+    event_queue: args.event_queue,
+    lookahead: args.lookahead,
   };
 
-  let spike_args = SpikeArgs {
-    elf_file: elf_file.clone(),
-    rtl_event_file: Some(args.rtl_event_file.clone()),
-    vlen,
-    dlen,
-    lane_width,
-    set: isa.clone(),
+  let run_diff = match flavor.as_str() {
+    "t1emu" => t1emu::run_diff,
+    "t1rocketemu" => t1rocketemu::run_diff,
+    _ => bail!("unknown flavor '{flavor}', expected 't1emu' or 't1rocketemu'"),
   };
+  let mut event_count = 0;
+  let result = panic::catch_unwind(AssertUnwindSafe(|| {
+    run_diff(&spike_args, &options, &mut event_count)
+  }));
+
+  if let Some(path) = &args.report_json {
+    let report = CheckReport {
+      events: event_count,
+      passed: matches!(result, Ok(Ok(()))),
+    };
+    std::fs::write(path, serde_json::to_string(&report)?)
+      .with_context(|| format!("in write {path:?}"))?;
+  }
+
+  match result {
+    Ok(result) => result,
+    Err(panic) => panic::resume_unwind(panic),
+  }
+}
 
-  match sim_result.flavor.as_str() {
-    "t1emu" => {
-      t1emu::run_diff(&spike_args)?;
+impl SpikeConfigArgs {
+  /// Read the sim result json, or take the metadata from the command line if it is absent
+  fn load_sim_result(&self) -> anyhow::Result<SimResult> {
+    fn required<T: Clone>(value: &Option<T>, name: &str) -> anyhow::Result<T> {
+      value.clone().with_context(|| format!("'--{name}' is required without '--sim-result'"))
     }
-    "t1rocketemu" => {
-      t1rocketemu::run_diff(&spike_args)?;
+
+    if let Some(sim_result_path) = &self.sim_result {
+      let sim_result = read_to_string(sim_result_path).context("in open sim result json file")?;
+      return SimResult::from_json(&sim_result).context("in parsing sim result file");
     }
-    _ => bail!(
-      "unknown flavor '{}', expected 't1emu' or 't1rocketemu'",
-      sim_result.flavor
-    ),
+
+    Ok(SimResult {
+      // flavor is given by --flavor when needed
+      flavor: String::new(),
+      meta_vlen: required(&self.vlen_override, "vlen-override")?,
+      meta_dlen: required(&self.dlen_override, "dlen-override")?,
+      meta_lane_width: required(&self.lane_width_override, "lane-width-override")?,
+      meta_isa: required(&self.isa_override, "isa-override")?,
+      meta_elf_file: self.elf_file.clone(),
+      success: true,
+      ..Default::default()
+    })
   }
 
-  Ok(())
+  fn spike_args(
+    &self,
+    sim_result: &SimResult,
+    rtl_event_file: Option<PathBuf>,
+  ) -> anyhow::Result<SpikeArgs> {
+    let elf_file = if let Some(elf_file) = &self.elf_file {
+      elf_file
+    } else if let Some(elf_file) = &sim_result.meta_elf_file {
+      elf_file
+    } else {
+      bail!("neither cmd args nor sim_result.json contains elf path");
+    };
+
+    Ok(SpikeArgs {
+      elf_file: elf_file.clone(),
+      rtl_event_file,
+      vlen: self.vlen_override.unwrap_or(sim_result.meta_vlen),
+      dlen: self.dlen_override.unwrap_or(sim_result.meta_dlen),
+      lane_width: self.lane_width_override.unwrap_or(sim_result.meta_lane_width),
+      set: self.isa_override.as_ref().unwrap_or(&sim_result.meta_isa).clone(),
+    })
+  }
 }
 
 fn init_logger(log_level: &str) {
@@ -117,7 +398,7 @@ fn init_logger(log_level: &str) {
     .with_max_level(log_level)
     .without_time()
     .with_target(false)
-    .with_ansi(true)
+    .with_ansi(std::io::stderr().is_terminal())
     .compact()
     .finish();
   tracing::subscriber::set_global_default(global_logger)
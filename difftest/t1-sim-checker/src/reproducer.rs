@@ -0,0 +1,316 @@
+//! First-divergence tracking and reproducer bundles
+//!
+//! With '--reproducer', the checker keeps an image of spike's VRF as of the retired vector
+//! instructions, and compares it with the VRF snapshots the TestBench emits each time the vector
+//! unit becomes idle, if the emulator runs with '+t1_dev_vrf_snapshot=1'. The TestBench builds
+//! the snapshot from the VRF write probes, so this catches wrong VRF bytes that are never
+//! reported by a VrfWrite event, e.g. overwritten before being checked, but not corruption
+//! inside the SRAM.
+//!
+//! On the first divergence, a snapshot mismatch or a failed check, a bundle is written:
+//! - the ELF
+//! - divergence.json: the reason, the diverging instruction and the sim config
+//! - spike-state.json: spike's architectural state right before the instruction
+//! - rtl-event.bin, rtl-event.txt: RTL events from the issue of the instruction to the failure
+
+use std::any::Any;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use serde::Deserialize;
+use serde_json::json;
+use spike_rs::runner::{ArchState, SpikeArgs, SpikeRunner};
+use spike_rs::spike_event::SpikeEvent;
+use tracing::{error, info, warn};
+
+use crate::binary::{BinaryEvent, BinaryWriter};
+use crate::util::EventReader;
+
+struct Tracked {
+  /// index of the Issue event
+  issue_event: u64,
+  se: SpikeEvent,
+}
+
+pub struct Divergence {
+  pub event_index: u64,
+  pub reason: String,
+  /// the diverging instruction, and the index of its Issue event if known
+  insn: Option<(Option<u64>, SpikeEvent)>,
+}
+
+pub struct DivergenceTracker {
+  dir: PathBuf,
+  flavor: &'static str,
+  context_events: usize,
+
+  vlen_in_bytes: usize,
+  /// spike's VRF after all retired instructions
+  vrf: Vec<u8>,
+  inflight: Vec<Tracked>,
+  /// retired since the last VRF snapshot, the latest is at the back
+  retired: Vec<Tracked>,
+}
+
+impl DivergenceTracker {
+  pub fn new(dir: &Path, flavor: &'static str, vlen: u32, context_events: usize) -> Self {
+    let vlen_in_bytes = vlen as usize / 8;
+    DivergenceTracker {
+      dir: dir.to_owned(),
+      flavor,
+      context_events,
+      vlen_in_bytes,
+      vrf: vec![0; vlen_in_bytes * 32],
+      inflight: vec![],
+      retired: vec![],
+    }
+  }
+
+  /// Called after the event is successfully checked,
+  /// returns the divergence if the event is a mismatched VRF snapshot
+  pub fn observe(
+    &mut self,
+    runner: &SpikeRunner,
+    event_index: u64,
+    issued: Option<u8>,
+    snapshot: Option<&[u8]>,
+  ) -> Option<Divergence> {
+    // the issued se is at the front of commit queue
+    if let Some(idx) = issued
+      && let Some(se) = runner.commit_queue.front().filter(|se| se.issue_idx == idx)
+    {
+      self.inflight.push(Tracked { issue_event: event_index, se: se.clone() });
+    }
+
+    // instructions gone from commit queue are retired, apply their VRF writes
+    let mut idx = 0;
+    while idx < self.inflight.len() {
+      let issue_idx = self.inflight[idx].se.issue_idx;
+      if runner.commit_queue.iter().any(|se| se.issue_idx == issue_idx) {
+        idx += 1;
+      } else {
+        let retired = self.inflight.remove(idx);
+        for (&offset, write) in &retired.se.vrf_access_record.all_writes {
+          self.vrf[offset] = write.byte;
+        }
+        self.retired.push(retired);
+      }
+    }
+
+    let snapshot = snapshot?;
+    let divergence = self.compare_snapshot(event_index, snapshot);
+    self.retired.clear();
+    divergence
+  }
+
+  fn compare_snapshot(&self, event_index: u64, snapshot: &[u8]) -> Option<Divergence> {
+    // the hex string of the snapshot drops leading zeros
+    let rtl_byte = |offset: usize| snapshot.get(offset).copied().unwrap_or(0);
+    let mismatches: Vec<usize> =
+      (0..self.vrf.len()).filter(|&offset| rtl_byte(offset) != self.vrf[offset]).collect();
+    let &first = mismatches.first()?;
+
+    // the latest instruction writing the byte decides its value
+    let culprit = self
+      .retired
+      .iter()
+      .rev()
+      .find(|t| t.se.vrf_access_record.all_writes.contains_key(&first))
+      .or(self.retired.first());
+
+    Some(Divergence {
+      event_index,
+      reason: format!(
+        "vrf snapshot mismatch on {} bytes, first at vrf_idx={first} (v{}[{}]): rtl={:#04x}, spike={:#04x}",
+        mismatches.len(),
+        first / self.vlen_in_bytes,
+        first % self.vlen_in_bytes,
+        rtl_byte(first),
+        self.vrf[first],
+      ),
+      insn: culprit.map(|t| (Some(t.issue_event), t.se.clone())),
+    })
+  }
+
+  /// The divergence of a failed check, blamed on the instruction the event belongs to,
+  /// or the oldest instruction in flight
+  pub fn check_failure(
+    &self,
+    runner: &SpikeRunner,
+    event_index: u64,
+    reason: String,
+    issue_idx: Option<u8>,
+    lsu_idx: Option<u8>,
+  ) -> Divergence {
+    let se = runner
+      .commit_queue
+      .iter()
+      .find(|se| Some(se.issue_idx) == issue_idx || Some(se.lsu_idx) == lsu_idx)
+      .or(runner.commit_queue.back());
+    let insn = se.map(|se| {
+      let tracked = self.inflight.iter().find(|t| t.se.issue_idx == se.issue_idx);
+      (tracked.map(|t| t.issue_event), se.clone())
+    });
+
+    Divergence { event_index, reason, insn }
+  }
+
+  /// Write the reproducer bundle. The runner is dropped first,
+  /// since spike is re-run from the start to capture the state before the instruction.
+  pub fn report<EventType>(
+    self,
+    runner: SpikeRunner,
+    spike_args: &SpikeArgs,
+    divergence: &Divergence,
+  ) -> anyhow::Result<()>
+  where
+    EventType: for<'a> Deserialize<'a> + BinaryEvent + Debug,
+  {
+    drop(runner);
+
+    let dir = &self.dir;
+    fs::create_dir_all(dir).with_context(|| format!("in create {dir:?}"))?;
+    error!(
+      "first divergence at event {}: {}",
+      divergence.event_index, divergence.reason
+    );
+
+    let elf_name = spike_args.elf_file.file_name().context("elf file has no file name")?;
+    fs::copy(&spike_args.elf_file, dir.join(elf_name))
+      .with_context(|| format!("in copy {:?}", spike_args.elf_file))?;
+
+    let instruction = divergence.insn.as_ref().map(|(issue_event, se)| {
+      json!({
+        "pc": format!("{:#x}", se.pc),
+        "inst_bits": format!("{:#010x}", se.inst_bits),
+        "disasm": se.disasm,
+        "issue_idx": se.issue_idx,
+        "issue_event": issue_event,
+        "spike_cycle": se.spike_cycle,
+      })
+    });
+    write_json(
+      &dir.join("divergence.json"),
+      &json!({
+        "reason": divergence.reason,
+        "event_index": divergence.event_index,
+        "instruction": instruction,
+        "flavor": self.flavor,
+        "elf_file": elf_name.to_string_lossy(),
+        "vlen": spike_args.vlen,
+        "dlen": spike_args.dlen,
+        "lane_width": spike_args.lane_width,
+        "isa": spike_args.set,
+      }),
+    )?;
+
+    if let Some((_, se)) = &divergence.insn {
+      let mut runner = SpikeRunner::new(spike_args, false);
+      for _ in 0..se.spike_cycle {
+        runner.spike_step();
+      }
+      let state = runner.arch_state();
+      if state.pc != se.pc {
+        warn!(
+          "re-run spike reaches pc={:#x}, instead of pc={:#x}",
+          state.pc, se.pc
+        );
+      }
+      write_json(
+        &dir.join("spike-state.json"),
+        &state_to_json(&state, self.vlen_in_bytes),
+      )?;
+    }
+
+    let start = match &divergence.insn {
+      Some((Some(issue_event), _)) => *issue_event,
+      _ => divergence.event_index.saturating_sub(self.context_events as u64).max(1),
+    };
+    self.write_events::<EventType>(spike_args, start, divergence.event_index)?;
+
+    info!("reproducer written to {dir:?}");
+    Ok(())
+  }
+
+  // events in [start, end], indexed from 1
+  fn write_events<EventType>(
+    &self,
+    spike_args: &SpikeArgs,
+    start: u64,
+    end: u64,
+  ) -> anyhow::Result<()>
+  where
+    EventType: for<'a> Deserialize<'a> + BinaryEvent + Debug,
+  {
+    let rtl_event_path = spike_args.rtl_event_file.as_ref().unwrap();
+    if rtl_event_path == Path::new("-") {
+      warn!("rtl events are read from stdin, skip writing them to the reproducer");
+      return Ok(());
+    }
+
+    let mut reader = EventReader::open(rtl_event_path, self.flavor, None)?;
+    let binary = File::create(self.dir.join("rtl-event.bin"))?;
+    let mut binary = BinaryWriter::new(BufWriter::new(binary), self.flavor)?;
+    let mut text = BufWriter::new(File::create(self.dir.join("rtl-event.txt"))?);
+
+    let mut event_index = 0;
+    while let Some(event) = reader.next_event::<EventType>()? {
+      event_index += 1;
+      if event_index > end {
+        break;
+      }
+      if event_index >= start {
+        binary.write_event(&event)?;
+        writeln!(text, "#{event_index}: {event:?}")?;
+      }
+    }
+
+    binary.into_inner().flush()?;
+    text.flush()?;
+    Ok(())
+  }
+}
+
+pub fn panic_message(panic: &Box<dyn Any + Send>) -> String {
+  if let Some(message) = panic.downcast_ref::<&str>() {
+    format!("panicked: {message}")
+  } else if let Some(message) = panic.downcast_ref::<String>() {
+    format!("panicked: {message}")
+  } else {
+    "panicked".to_string()
+  }
+}
+
+fn state_to_json(state: &ArchState, vlen_in_bytes: usize) -> serde_json::Value {
+  let hex = |regs: &[u32]| regs.iter().map(|reg| format!("{reg:#010x}")).collect::<Vec<_>>();
+  // each vreg as a hex number, the highest byte first
+  let vrf: Vec<String> = state
+    .vrf
+    .chunks(vlen_in_bytes)
+    .map(|vreg| vreg.iter().rev().map(|byte| format!("{byte:02x}")).collect())
+    .collect();
+
+  json!({
+    "pc": format!("{:#x}", state.pc),
+    "xrf": hex(&state.xrf),
+    "frf": hex(&state.frf),
+    "vrf": vrf,
+    "vtype": format!("{:#x}", state.vtype),
+    "vl": state.vl,
+    "vstart": state.vstart,
+    "vxrm": state.vxrm,
+    "vxsat": state.vxsat,
+  })
+}
+
+fn write_json(path: &Path, value: &serde_json::Value) -> anyhow::Result<()> {
+  let file = File::create(path).with_context(|| format!("in create {path:?}"))?;
+  let mut writer = BufWriter::new(file);
+  serde_json::to_writer_pretty(&mut writer, value)?;
+  writeln!(writer)?;
+  writer.flush()?;
+  Ok(())
+}
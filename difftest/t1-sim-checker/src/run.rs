@@ -0,0 +1,154 @@
+//! Run an ELF in spike only, without RTL events
+//!
+//! Used to triage whether a failure comes from the test itself before looking at RTL logs.
+
+use std::fs::{File, read};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context as _, bail};
+use spike_rs::runner::{SpikeArgs, SpikeRunner};
+use spike_rs::spike_event::SpikeEvent;
+use tracing::info;
+use xmas_elf::ElfFile;
+use xmas_elf::sections::SectionData;
+use xmas_elf::symbol_table::Entry;
+
+pub struct RunOptions<'a> {
+  /// stop after executing the given number of instructions
+  pub max_insns: Option<u64>,
+
+  /// stop before executing the instruction at the symbol
+  pub stop_at: Option<&'a str>,
+
+  /// write every executed instruction to the file
+  pub trace: Option<&'a Path>,
+}
+
+#[derive(Debug)]
+enum ExitStatus {
+  /// the program wrote the exit magic to the sim control register
+  Exited,
+  MaxInsns,
+  StopAt(String),
+}
+
+#[derive(Default)]
+struct RunStats {
+  total: u64,
+  vector_arith: u64,
+  vector_load: u64,
+  vector_store: u64,
+  vsetvl: u64,
+  scalar_load: u64,
+  scalar_store: u64,
+  scalar_other: u64,
+}
+
+impl RunStats {
+  fn record(&mut self, se: &SpikeEvent) {
+    self.total += 1;
+    let count = if se.is_vload() {
+      &mut self.vector_load
+    } else if se.is_vstore() {
+      &mut self.vector_store
+    } else if se.is_vsetvl() {
+      &mut self.vsetvl
+    } else if se.is_v() {
+      &mut self.vector_arith
+    } else if se.is_load() {
+      &mut self.scalar_load
+    } else if se.is_store() {
+      &mut self.scalar_store
+    } else {
+      &mut self.scalar_other
+    };
+    *count += 1;
+  }
+
+  fn vector(&self) -> u64 {
+    self.vector_arith + self.vector_load + self.vector_store
+  }
+}
+
+pub fn run_spike(spike_args: &SpikeArgs, options: &RunOptions) -> anyhow::Result<()> {
+  let stop_addr = match options.stop_at {
+    Some(symbol) => Some(find_symbol(&spike_args.elf_file, symbol)?),
+    None => None,
+  };
+  let mut trace = match options.trace {
+    Some(path) => {
+      let file = File::create(path).with_context(|| format!("in create trace file {path:?}"))?;
+      Some(BufWriter::new(file))
+    }
+    None => None,
+  };
+
+  let mut runner = SpikeRunner::new(spike_args, false);
+  let mut stats = RunStats::default();
+
+  let status = loop {
+    if options.max_insns.is_some_and(|max| stats.total >= max) {
+      break ExitStatus::MaxInsns;
+    }
+    if stop_addr.is_some_and(|addr| addr == runner.pc()) {
+      break ExitStatus::StopAt(options.stop_at.unwrap().to_string());
+    }
+
+    let se = runner.spike_step();
+    stats.record(&se);
+    if let Some(trace) = &mut trace {
+      writeln!(trace, "{:#010x} {:08x} {}", se.pc, se.inst_bits, se.disasm)?;
+    }
+    if stats.total % 1000000 == 0 {
+      info!("count = {}", stats.total);
+    }
+
+    if se.is_exit() {
+      break ExitStatus::Exited;
+    }
+  };
+
+  if let Some(trace) = &mut trace {
+    trace.flush()?;
+  }
+
+  eprintln!("Totally {} instructions executed", stats.total);
+  eprintln!("  vector:        {}", stats.vector());
+  eprintln!("    arithmetic:  {}", stats.vector_arith);
+  eprintln!("    load:        {}", stats.vector_load);
+  eprintln!("    store:       {}", stats.vector_store);
+  eprintln!("  vsetvl:        {}", stats.vsetvl);
+  eprintln!("  scalar load:   {}", stats.scalar_load);
+  eprintln!("  scalar store:  {}", stats.scalar_store);
+  eprintln!("  scalar other:  {}", stats.scalar_other);
+  match status {
+    ExitStatus::Exited => eprintln!("Exit status: exited at pc={:#x}", runner.pc()),
+    ExitStatus::MaxInsns => eprintln!("Exit status: stopped after max instructions"),
+    ExitStatus::StopAt(symbol) => {
+      eprintln!("Exit status: stopped at '{symbol}' (pc={:#x})", runner.pc())
+    }
+  }
+
+  Ok(())
+}
+
+fn find_symbol(elf_path: &Path, symbol: &str) -> anyhow::Result<u64> {
+  let buffer = read(elf_path).with_context(|| format!("in open elf file {elf_path:?}"))?;
+  let elf_file = ElfFile::new(&buffer).map_err(anyhow::Error::msg)?;
+
+  let Some(symtab) = elf_file.find_section_by_name(".symtab") else {
+    bail!("elf file {elf_path:?} has no symbol table");
+  };
+  let SectionData::SymbolTable32(entries) =
+    symtab.get_data(&elf_file).map_err(anyhow::Error::msg)?
+  else {
+    bail!("elf file {elf_path:?} has no 32-bit symbol table");
+  };
+
+  entries
+    .iter()
+    .find(|entry| entry.get_name(&elf_file) == Ok(symbol))
+    .map(|entry| entry.value())
+    .with_context(|| format!("symbol '{symbol}' not found in {elf_path:?}"))
+}
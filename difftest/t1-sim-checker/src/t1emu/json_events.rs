@@ -1,42 +1,17 @@
-use num_bigint::BigUint;
-use serde::{Deserialize, Deserializer};
+use std::io::{self, Read, Write};
+
+use serde::Deserialize;
+use sim_schema::event::*;
 use spike_rs::runner::SpikeRunner;
 use spike_rs::spike_event::LSU_IDX_DEFAULT;
 use tracing::{debug, info};
 
-fn str_to_vec_u8<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
-where
-  D: Deserializer<'de>,
-{
-  let s: &str = Deserialize::deserialize(deserializer)?;
-  let bigint = BigUint::parse_bytes(s.trim_start().as_bytes(), 16)
-    .ok_or_else(|| serde::de::Error::custom("Failed to parse BigUint from hex string"))?;
-  Ok(bigint.to_bytes_le())
-}
-
-fn str_to_vec_bool<'de, D>(deserializer: D) -> Result<Vec<bool>, D::Error>
-where
-  D: Deserializer<'de>,
-{
-  let s: &str = Deserialize::deserialize(deserializer)?;
-  let bigint = BigUint::parse_bytes(s.trim_start().as_bytes(), 16)
-    .ok_or_else(|| serde::de::Error::custom("Failed to parse BigUint from hex string"))?;
-  let bytes = bigint.to_bytes_le();
-  let bools = bytes.iter().flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1u8 == 1u8)).collect();
-
-  Ok(bools)
-}
-
-fn str_to_u32<'de, D>(deserializer: D) -> Result<u32, D::Error>
-where
-  D: Deserializer<'de>,
-{
-  let s: &str = Deserialize::deserialize(deserializer)?;
-  let value =
-    u32::from_str_radix(s.trim_start_matches(' '), 16).map_err(serde::de::Error::custom)?;
-
-  Ok(value)
-}
+use crate::binary::{
+  BinaryEvent, BinaryReader, BinaryWriter, TAG_CHECK_RD, TAG_FINAL_STATE, TAG_ISSUE, TAG_LSU_ENQ,
+  TAG_MEMORY_WRITE, TAG_VRF_SCOREBOARD, TAG_VRF_SNAPSHOT, TAG_VRF_WRITE, unknown_tag,
+};
+use crate::diff::DiffEvent;
+use crate::latency::LatencyEvent;
 
 fn mask_display(mask: &Vec<bool>) -> String {
   mask.into_iter().map(|&b| if b { '1' } else { '0' }).collect()
@@ -49,82 +24,175 @@ fn data_display(data: &Vec<u8>) -> String {
 #[derive(Deserialize, Debug)]
 #[serde(tag = "event")]
 pub(crate) enum JsonEvents {
-  Issue {
-    idx: u8,
-    cycle: u64,
-  },
-  LsuEnq {
-    enq: u32,
-    cycle: u64,
-  },
-  VrfWrite {
-    issue_idx: u8,
-    vrf_idx: usize,
-    #[serde(deserialize_with = "str_to_vec_bool", default)]
-    mask: Vec<bool>,
-    #[serde(deserialize_with = "str_to_vec_u8", default)]
-    data: Vec<u8>,
-    cycle: u64,
-  },
-  MemoryWrite {
-    #[serde(deserialize_with = "str_to_vec_bool", default)]
-    mask: Vec<bool>,
-    #[serde(deserialize_with = "str_to_vec_u8", default)]
-    data: Vec<u8>,
-    lsu_idx: u8,
-    #[serde(deserialize_with = "str_to_u32", default)]
-    address: u32,
-    cycle: u64,
-  },
-  CheckRd {
-    #[serde(deserialize_with = "str_to_u32", default)]
-    data: u32,
-    issue_idx: u8,
-    cycle: u64,
-  },
-  VrfScoreboard {
-    count: u32,
-    issue_idx: u8,
-    cycle: u64,
-  },
+  Issue(IssueEvent),
+  LsuEnq(LsuEnqEvent),
+  VrfWrite(VrfWriteEvent),
+  MemoryWrite(MemoryWriteEvent),
+  CheckRd(CheckRdEvent),
+  VrfScoreboard(VrfScoreboardEvent),
+  /// Whole VRF emitted at a fence, checked with '--reproducer'
+  VrfSnapshot(VrfSnapshotEvent),
+  /// Register files dumped at exit, compared with spike's final state
+  FinalState(FinalStateEvent),
 }
 
-pub struct IssueEvent {
-  pub idx: u8,
-  pub cycle: u64,
-}
+impl DiffEvent for JsonEvents {
+  fn diff(&self, runner: &mut SpikeRunner) -> anyhow::Result<()> {
+    super::difftest::diff(runner, self)
+  }
 
-pub struct LsuEnqEvent {
-  pub enq: u32,
-  pub cycle: u64,
-}
+  fn latency_event(&self) -> (LatencyEvent, u64) {
+    match self {
+      JsonEvents::Issue(IssueEvent { idx, cycle }) => (LatencyEvent::Issue { idx: *idx }, *cycle),
+      JsonEvents::LsuEnq(LsuEnqEvent { cycle, .. }) => (LatencyEvent::LsuEnq, *cycle),
+      JsonEvents::VrfWrite(VrfWriteEvent { issue_idx, cycle, .. }) => {
+        (LatencyEvent::VrfWrite { issue_idx: *issue_idx }, *cycle)
+      }
+      JsonEvents::MemoryWrite(MemoryWriteEvent { lsu_idx, cycle, .. }) => {
+        (LatencyEvent::MemoryWrite { lsu_idx: *lsu_idx }, *cycle)
+      }
+      JsonEvents::CheckRd(CheckRdEvent { cycle, .. }) => (LatencyEvent::Other, *cycle),
+      JsonEvents::VrfScoreboard(VrfScoreboardEvent { cycle, .. }) => (LatencyEvent::Other, *cycle),
+      JsonEvents::VrfSnapshot(VrfSnapshotEvent { cycle, .. }) => (LatencyEvent::Other, *cycle),
+      JsonEvents::FinalState(FinalStateEvent { cycle, .. }) => (LatencyEvent::Other, *cycle),
+    }
+  }
 
-pub struct VrfWriteEvent {
-  pub issue_idx: u8,
-  pub vrf_idx: usize,
-  pub mask: Vec<bool>,
-  pub data: Vec<u8>,
-  pub cycle: u64,
-}
+  fn issued(&self) -> Option<u8> {
+    match self {
+      JsonEvents::Issue(IssueEvent { idx, .. }) => Some(*idx),
+      _ => None,
+    }
+  }
 
-pub struct MemoryWriteEvent {
-  pub mask: Vec<bool>,
-  pub data: Vec<u8>,
-  pub lsu_idx: u8,
-  pub address: u32,
-  pub cycle: u64,
-}
+  fn issue_idx(&self) -> Option<u8> {
+    match self {
+      JsonEvents::Issue(IssueEvent { idx, .. }) => Some(*idx),
+      JsonEvents::VrfWrite(VrfWriteEvent { issue_idx, .. })
+      | JsonEvents::CheckRd(CheckRdEvent { issue_idx, .. })
+      | JsonEvents::VrfScoreboard(VrfScoreboardEvent { issue_idx, .. }) => Some(*issue_idx),
+      _ => None,
+    }
+  }
 
-pub struct VrfScoreboardEvent {
-  pub count: u32,
-  pub issue_idx: u8,
-  pub cycle: u64,
+  fn lsu_idx(&self) -> Option<u8> {
+    match self {
+      JsonEvents::MemoryWrite(MemoryWriteEvent { lsu_idx, .. }) => Some(*lsu_idx),
+      _ => None,
+    }
+  }
+
+  fn vrf_snapshot(&self) -> Option<&[u8]> {
+    match self {
+      JsonEvents::VrfSnapshot(VrfSnapshotEvent { data, .. }) => Some(data),
+      _ => None,
+    }
+  }
 }
 
-pub struct CheckRdEvent {
-  pub data: u32,
-  pub issue_idx: u8,
-  pub cycle: u64,
+impl BinaryEvent for JsonEvents {
+  fn encode<W: Write>(&self, w: &mut BinaryWriter<W>) -> io::Result<()> {
+    match self {
+      JsonEvents::Issue(IssueEvent { idx, cycle }) => {
+        w.put_u8(TAG_ISSUE)?;
+        w.put_u8(*idx)?;
+        w.put_u64(*cycle)
+      }
+      JsonEvents::LsuEnq(LsuEnqEvent { enq, cycle }) => {
+        w.put_u8(TAG_LSU_ENQ)?;
+        w.put_u32(*enq)?;
+        w.put_u64(*cycle)
+      }
+      JsonEvents::VrfWrite(VrfWriteEvent { issue_idx, vrf_idx, mask, data, cycle }) => {
+        w.put_u8(TAG_VRF_WRITE)?;
+        w.put_u8(*issue_idx)?;
+        w.put_u64(*vrf_idx as u64)?;
+        w.put_mask(mask)?;
+        w.put_bytes(data)?;
+        w.put_u64(*cycle)
+      }
+      JsonEvents::MemoryWrite(MemoryWriteEvent { mask, data, lsu_idx, address, cycle }) => {
+        w.put_u8(TAG_MEMORY_WRITE)?;
+        w.put_mask(mask)?;
+        w.put_bytes(data)?;
+        w.put_u8(*lsu_idx)?;
+        w.put_u32(*address)?;
+        w.put_u64(*cycle)
+      }
+      JsonEvents::CheckRd(CheckRdEvent { data, issue_idx, cycle }) => {
+        w.put_u8(TAG_CHECK_RD)?;
+        w.put_u32(*data)?;
+        w.put_u8(*issue_idx)?;
+        w.put_u64(*cycle)
+      }
+      JsonEvents::VrfScoreboard(VrfScoreboardEvent { count, issue_idx, cycle }) => {
+        w.put_u8(TAG_VRF_SCOREBOARD)?;
+        w.put_u32(*count)?;
+        w.put_u8(*issue_idx)?;
+        w.put_u64(*cycle)
+      }
+      JsonEvents::VrfSnapshot(VrfSnapshotEvent { data, cycle }) => {
+        w.put_u8(TAG_VRF_SNAPSHOT)?;
+        w.put_bytes(data)?;
+        w.put_u64(*cycle)
+      }
+      JsonEvents::FinalState(FinalStateEvent { vrf, xrf, frf, cycle }) => {
+        w.put_u8(TAG_FINAL_STATE)?;
+        w.put_bytes(vrf)?;
+        for regs in [xrf, frf] {
+          w.put_u32(regs.len() as u32)?;
+          regs.iter().try_for_each(|&reg| w.put_u32(reg))?;
+        }
+        w.put_u64(*cycle)
+      }
+    }
+  }
+
+  fn decode<R: Read>(tag: u8, r: &mut BinaryReader<R>) -> anyhow::Result<Self> {
+    let event = match tag {
+      TAG_ISSUE => JsonEvents::Issue(IssueEvent { idx: r.get_u8()?, cycle: r.get_u64()? }),
+      TAG_LSU_ENQ => JsonEvents::LsuEnq(LsuEnqEvent { enq: r.get_u32()?, cycle: r.get_u64()? }),
+      TAG_VRF_WRITE => JsonEvents::VrfWrite(VrfWriteEvent {
+        issue_idx: r.get_u8()?,
+        vrf_idx: r.get_u64()? as usize,
+        mask: r.get_mask()?,
+        data: r.get_bytes()?,
+        cycle: r.get_u64()?,
+      }),
+      TAG_MEMORY_WRITE => JsonEvents::MemoryWrite(MemoryWriteEvent {
+        mask: r.get_mask()?,
+        data: r.get_bytes()?,
+        lsu_idx: r.get_u8()?,
+        address: r.get_u32()?,
+        cycle: r.get_u64()?,
+      }),
+      TAG_CHECK_RD => JsonEvents::CheckRd(CheckRdEvent {
+        data: r.get_u32()?,
+        issue_idx: r.get_u8()?,
+        cycle: r.get_u64()?,
+      }),
+      TAG_VRF_SCOREBOARD => JsonEvents::VrfScoreboard(VrfScoreboardEvent {
+        count: r.get_u32()?,
+        issue_idx: r.get_u8()?,
+        cycle: r.get_u64()?,
+      }),
+      TAG_VRF_SNAPSHOT => {
+        JsonEvents::VrfSnapshot(VrfSnapshotEvent { data: r.get_bytes()?, cycle: r.get_u64()? })
+      }
+      TAG_FINAL_STATE => {
+        let vrf = r.get_bytes()?;
+        let mut get_regs = || -> anyhow::Result<Vec<u32>> {
+          let len = r.get_u32()?;
+          (0..len).map(|_| r.get_u32()).collect()
+        };
+        let xrf = get_regs()?;
+        let frf = get_regs()?;
+        JsonEvents::FinalState(FinalStateEvent { vrf, xrf, frf, cycle: r.get_u64()? })
+      }
+      _ => return unknown_tag(tag),
+    };
+    Ok(event)
+  }
 }
 
 pub(crate) trait JsonEventRunner {
@@ -1,49 +1,31 @@
 mod difftest;
 mod json_events;
+#[cfg(test)]
+mod tests;
 
-use std::{fs::File, io::BufReader};
-
-use anyhow::Context as _;
-use tracing::info;
-
-use spike_rs::runner::*;
-
-use crate::util::JsonReader;
-
-fn run_spike(args: &SpikeArgs) -> anyhow::Result<()> {
-  let mut count: u64 = 0;
-
-  let spike = SpikeRunner::new(args, true);
-  loop {
-    count += 1;
-    if count % 1000000 == 0 {
-      info!("count = {}", count);
-    }
-    match spike.exec() {
-      Ok(_) => {}
-      Err(_) => {
-        info!("total v instrucions count = {}", count);
-        info!("Simulation quit graceful");
-        return Ok(());
-      }
-    };
-  }
-}
+use std::path::Path;
+
+use spike_rs::runner::SpikeArgs;
 
-pub fn run_diff(spike_args: &SpikeArgs) -> anyhow::Result<()> {
-  let rtl_event_path = spike_args.rtl_event_file.as_ref().unwrap();
-  let json_file = File::open(rtl_event_path).context("in open rtl event file")?;
+use crate::DiffOptions;
+use crate::diff;
+use crate::util::convert_to_binary;
 
-  let mut runner = SpikeRunner::new(&spike_args, true);
-  let mut reader = JsonReader::new(BufReader::new(json_file));
+use json_events::JsonEvents;
+
+/// `event_count` is the number of events processed, also when failing
+pub fn run_diff(
+  spike_args: &SpikeArgs,
+  options: &DiffOptions,
+  event_count: &mut u64,
+) -> anyhow::Result<()> {
+  diff::run_diff::<JsonEvents>("t1emu", spike_args, options, event_count)
+}
 
-  let mut event_count = 0;
-  while let Some(event) = reader.next_event()? {
-    event_count += 1;
-    difftest::diff(&mut runner, &event)?;
-  }
+pub fn convert(input: &Path, output: &Path) -> anyhow::Result<()> {
+  let event_count = convert_to_binary::<JsonEvents>(input, output, "t1emu")?;
 
-  eprintln!("Tototally {event_count} events processed");
+  eprintln!("Totally {event_count} events converted");
 
   Ok(())
 }
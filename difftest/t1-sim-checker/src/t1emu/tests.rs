@@ -0,0 +1,315 @@
+//! Checks of synthetic event logs against scripted spike events
+
+use std::panic::{self, AssertUnwindSafe};
+
+use anyhow::anyhow;
+use spike_rs::model::ScriptedModel;
+use spike_rs::runner::SpikeRunner;
+use spike_rs::spike_event::{ISSUE_IDX_DEFAULT, SpikeEvent};
+
+use super::difftest;
+use super::json_events::JsonEvents;
+use crate::reproducer::panic_message;
+use crate::util::JsonReader;
+
+const VLEN: u32 = 128;
+const DLEN: u32 = 256;
+
+// offset of v1 in the vrf
+const V1: usize = VLEN as usize / 8;
+
+fn vadd(pc: u64) -> SpikeEvent {
+  // vadd.vv v1, v2, v3
+  SpikeEvent::scripted(pc, 0x022180d7, "vadd.vv v1, v2, v3")
+}
+
+fn vse32(pc: u64) -> SpikeEvent {
+  // vse32.v v1, (a0)
+  SpikeEvent::scripted(pc, 0x020560a7, "vse32.v v1, (a0)")
+}
+
+fn addi(pc: u64) -> SpikeEvent {
+  // addi a0, a0, 1
+  SpikeEvent::scripted(pc, 0x00150513, "addi a0, a0, 1")
+}
+
+fn exit(pc: u64) -> SpikeEvent {
+  // sw a1, 0(a0)
+  SpikeEvent::scripted(pc, 0x00b52023, "sw a1, 0(a0)").with_exit()
+}
+
+fn runner(events: Vec<SpikeEvent>) -> SpikeRunner {
+  SpikeRunner::with_model(Box::new(ScriptedModel::new(events, VLEN)), VLEN, DLEN)
+}
+
+/// Check the events of a log in order, a failed assertion is returned as an error
+fn check_log(runner: &mut SpikeRunner, log: &str) -> anyhow::Result<()> {
+  let log: String = log.lines().map(|line| format!("{}\n", line.trim())).collect();
+  let mut reader = JsonReader::new(log.as_bytes(), "t1emu");
+  while let Some(event) = reader.next_event::<JsonEvents>()? {
+    panic::catch_unwind(AssertUnwindSafe(|| difftest::diff(runner, &event)))
+      .map_err(|panic| anyhow!(panic_message(&panic)))??;
+  }
+  Ok(())
+}
+
+fn issue_idxs(runner: &SpikeRunner) -> Vec<u8> {
+  runner.commit_queue.iter().map(|se| se.issue_idx).collect()
+}
+
+#[test]
+fn issue_skips_scalar_instructions() {
+  let mut runner = runner(vec![addi(0x1000), vadd(0x1004), addi(0x1008), vadd(0x100c)]);
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"Issue","idx":1,"cycle":2}
+    "#,
+  )
+  .unwrap();
+
+  // the earliest issued is at the back
+  let pcs: Vec<u64> = runner.commit_queue.iter().map(|se| se.pc).collect();
+  assert_eq!(pcs, [0x100c, 0x1004]);
+  assert_eq!(issue_idxs(&runner), [1, 0]);
+}
+
+#[test]
+fn retire_out_of_issue_order() {
+  let mut runner = runner(vec![
+    vadd(0x1000).with_vrf_write(V1, 0x11, true),
+    vadd(0x1004).with_vrf_write(2 * V1, 0x22, true),
+  ]);
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"Issue","idx":1,"cycle":2}
+    {"event":"VrfScoreboard","count":1,"issue_idx":1,"cycle":3}
+    {"event":"VrfWrite","issue_idx":1,"vrf_idx":32,"mask":"1","data":"22","cycle":4}
+    "#,
+  )
+  .unwrap();
+  assert_eq!(issue_idxs(&runner), [0]);
+
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"VrfWrite","issue_idx":0,"vrf_idx":16,"mask":"1","data":"11","cycle":5}
+    {"event":"VrfScoreboard","count":1,"issue_idx":0,"cycle":6}
+    "#,
+  )
+  .unwrap();
+  assert!(runner.commit_queue.is_empty());
+}
+
+#[test]
+fn retire_with_unwritten_vrf_byte_fails() {
+  let mut runner = runner(vec![vadd(0x1000).with_vrf_write(V1, 0x11, true)]);
+  let error = check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"VrfScoreboard","count":0,"issue_idx":0,"cycle":2}
+    "#,
+  )
+  .unwrap_err();
+  assert!(
+    error.to_string().contains("changed but not executed"),
+    "{error}"
+  );
+}
+
+#[test]
+fn retire_with_unchanged_vrf_byte() {
+  // spike writes the byte with its original value, the rtl may skip it
+  let mut runner = runner(vec![vadd(0x1000).with_vrf_write(V1, 0, false)]);
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"VrfScoreboard","count":0,"issue_idx":0,"cycle":2}
+    "#,
+  )
+  .unwrap();
+  assert!(runner.commit_queue.is_empty());
+}
+
+#[test]
+fn scoreboard_counts_writes_before_and_after() {
+  let mut runner = runner(vec![
+    vadd(0x1000).with_vrf_write(V1, 0x11, true).with_vrf_write(V1 + 1, 0x12, true),
+  ]);
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"VrfWrite","issue_idx":0,"vrf_idx":16,"mask":"1","data":"11","cycle":2}
+    {"event":"VrfScoreboard","count":2,"issue_idx":0,"cycle":3}
+    "#,
+  )
+  .unwrap();
+  let se = runner.commit_queue.front().unwrap();
+  assert_eq!(se.vrf_access_record.retired_writes, 1);
+  assert_eq!(se.vrf_access_record.unretired_writes, Some(1));
+
+  check_log(
+    &mut runner,
+    r#"{"event":"VrfWrite","issue_idx":0,"vrf_idx":17,"mask":"1","data":"12","cycle":4}"#,
+  )
+  .unwrap();
+  assert!(runner.commit_queue.is_empty());
+}
+
+#[test]
+fn scoreboard_count_less_than_retired_fails() {
+  let mut runner = runner(vec![
+    vadd(0x1000).with_vrf_write(V1, 0x11, true).with_vrf_write(V1 + 1, 0x12, true),
+  ]);
+  let error = check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"VrfWrite","issue_idx":0,"vrf_idx":16,"mask":"1","data":"11","cycle":2}
+    {"event":"VrfWrite","issue_idx":0,"vrf_idx":17,"mask":"1","data":"12","cycle":3}
+    {"event":"VrfScoreboard","count":1,"issue_idx":0,"cycle":4}
+    "#,
+  )
+  .unwrap_err();
+  assert!(
+    error.to_string().contains("should be less than count"),
+    "{error}"
+  );
+}
+
+#[test]
+fn vrf_write_mismatch_fails() {
+  let mut runner = runner(vec![vadd(0x1000).with_vrf_write(V1, 0x11, true)]);
+  let error = check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"VrfWrite","issue_idx":0,"vrf_idx":16,"mask":"1","data":"10","cycle":2}
+    "#,
+  )
+  .unwrap_err();
+  assert!(error.to_string().contains("byte incorrect"), "{error}");
+}
+
+#[test]
+fn lsu_idx_allocated_to_oldest_unallocated() {
+  let mut runner = runner(vec![
+    vse32(0x1000).with_mem_write(0x2000, 0xaa),
+    vse32(0x1004).with_mem_write(0x2004, 0xbb),
+  ]);
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"Issue","idx":1,"cycle":2}
+    {"event":"LsuEnq","enq":4,"cycle":3}
+    {"event":"LsuEnq","enq":32,"cycle":4}
+    "#,
+  )
+  .unwrap();
+  let lsu_idxs: Vec<u8> = runner.commit_queue.iter().map(|se| se.lsu_idx).collect();
+  assert_eq!(lsu_idxs, [5, 2]);
+
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"MemoryWrite","lsu_idx":5,"mask":"1","data":"bb","address":"2004","cycle":5}
+    {"event":"MemoryWrite","lsu_idx":2,"mask":"1","data":"aa","address":"2000","cycle":6}
+    {"event":"VrfScoreboard","count":0,"issue_idx":0,"cycle":7}
+    {"event":"VrfScoreboard","count":0,"issue_idx":1,"cycle":8}
+    "#,
+  )
+  .unwrap();
+  assert!(runner.commit_queue.is_empty());
+}
+
+#[test]
+fn memory_write_before_lsu_enq_fails() {
+  let mut runner = runner(vec![vse32(0x1000).with_mem_write(0x2000, 0xaa)]);
+  let error = check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"MemoryWrite","lsu_idx":2,"mask":"1","data":"aa","address":"2000","cycle":2}
+    "#,
+  )
+  .unwrap_err();
+  assert!(error.to_string().contains("lsu_idx=2"), "{error}");
+}
+
+#[test]
+fn retire_before_memory_write_fails() {
+  let mut runner = runner(vec![vse32(0x1000).with_mem_write(0x2000, 0xaa)]);
+  let error = check_log(
+    &mut runner,
+    r#"
+    {"event":"Issue","idx":0,"cycle":1}
+    {"event":"LsuEnq","enq":1,"cycle":2}
+    {"event":"VrfScoreboard","count":0,"issue_idx":0,"cycle":3}
+    "#,
+  )
+  .unwrap_err();
+  assert!(
+    error.to_string().contains("expect to write mem 0x2000"),
+    "{error}"
+  );
+}
+
+#[test]
+fn fence_waits_for_earlier_instructions() {
+  let mut runner = runner(vec![vadd(0x1000).with_vrf_write(V1, 0, false)]);
+  check_log(&mut runner, r#"{"event":"Issue","idx":0,"cycle":1}"#).unwrap();
+
+  // the exit is queued as an unissued fence
+  runner.commit_queue.push_front(exit(0x1004));
+  check_log(&mut runner, r#"{"event":"Issue","idx":1,"cycle":2}"#).unwrap();
+  assert_eq!(issue_idxs(&runner), [ISSUE_IDX_DEFAULT, 0]);
+
+  check_log(
+    &mut runner,
+    r#"{"event":"VrfScoreboard","count":0,"issue_idx":0,"cycle":3}"#,
+  )
+  .unwrap();
+  assert_eq!(runner.commit_queue.len(), 1);
+
+  // cleared before checking the next event
+  check_log(
+    &mut runner,
+    r#"{"event":"VrfSnapshot","data":"0","cycle":4}"#,
+  )
+  .unwrap();
+  assert!(runner.commit_queue.is_empty());
+}
+
+#[test]
+fn final_state_compared_after_exit() {
+  let script = || vec![vadd(0x1000).with_vrf_write(V1, 0x11, true), exit(0x1004)];
+  let log = |v1: &str| {
+    format!(
+      r#"
+      {{"event":"Issue","idx":0,"cycle":1}}
+      {{"event":"VrfWrite","issue_idx":0,"vrf_idx":16,"mask":"1","data":"11","cycle":2}}
+      {{"event":"VrfScoreboard","count":1,"issue_idx":0,"cycle":3}}
+      {{"event":"FinalState","vrf":"{v1}{}","cycle":4}}
+      "#,
+      "00".repeat(V1)
+    )
+  };
+
+  let mut matched = runner(script());
+  check_log(&mut matched, &log("11")).unwrap();
+  assert!(matched.exited);
+
+  let mut mismatched = runner(script());
+  let error = check_log(&mut mismatched, &log("12")).unwrap_err();
+  assert!(
+    error.to_string().contains("v1[0] rtl=0x12 spike=0x11"),
+    "{error}"
+  );
+}
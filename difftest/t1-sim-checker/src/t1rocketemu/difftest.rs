@@ -1,66 +1,61 @@
+use sim_schema::event::FinalStateEvent;
 use spike_rs::runner::SpikeRunner;
 
 use super::json_events::*;
+use crate::final_state::check_final_state;
 
 pub fn diff(runner: &mut SpikeRunner, event: &JsonEvents) -> anyhow::Result<()> {
   runner.check_and_clear_fence();
 
   match event {
-    JsonEvents::RegWrite { idx, data, cycle } => {
-      runner.cycle = *cycle;
-      runner.peek_reg_write(&RegWriteEvent { idx: *idx, data: *data, cycle: *cycle })
+    JsonEvents::RegWrite(reg_write) => {
+      runner.cycle = reg_write.cycle;
+      runner.peek_reg_write(reg_write)
     }
-    JsonEvents::RegWriteWait { idx, cycle } => {
-      runner.cycle = *cycle;
-      runner.peek_reg_write_wait(&RegWriteWaitEvent { idx: *idx, cycle: *cycle })
+    JsonEvents::RegWriteWait(reg_write_wait) => {
+      runner.cycle = reg_write_wait.cycle;
+      runner.peek_reg_write_wait(reg_write_wait)
     }
-    JsonEvents::FregWrite { idx, data, cycle } => {
-      runner.cycle = *cycle;
-      runner.peek_freg_write(&RegWriteEvent { idx: *idx, data: *data, cycle: *cycle })
+    JsonEvents::FregWrite(freg_write) => {
+      runner.cycle = freg_write.cycle;
+      runner.peek_freg_write(freg_write)
     }
-    JsonEvents::FregWriteWait { idx, cycle } => {
-      runner.cycle = *cycle;
-      runner.peek_freg_write_wait(&RegWriteWaitEvent { idx: *idx, cycle: *cycle })
+    JsonEvents::FregWriteWait(freg_write_wait) => {
+      runner.cycle = freg_write_wait.cycle;
+      runner.peek_freg_write_wait(freg_write_wait)
     }
-    JsonEvents::Issue { idx, cycle } => {
-      runner.cycle = *cycle;
-      runner.peek_issue(&IssueEvent { idx: *idx, cycle: *cycle })
+    JsonEvents::Issue(issue) => {
+      runner.cycle = issue.cycle;
+      runner.peek_issue(issue)
     }
-    JsonEvents::MemoryWrite { mask, data, lsu_idx, address, cycle } => {
-      runner.cycle = *cycle;
-      runner.peek_memory_write(&MemoryWriteEvent {
-        mask: mask.clone(),
-        data: data.clone(),
-        lsu_idx: *lsu_idx,
-        address: *address,
-        cycle: *cycle,
-      })
+    JsonEvents::MemoryWrite(memory_write) => {
+      runner.cycle = memory_write.cycle;
+      runner.peek_memory_write(memory_write)
     }
-    JsonEvents::LsuEnq { enq, cycle } => {
-      runner.cycle = *cycle;
-      runner.update_lsu_idx(&LsuEnqEvent { enq: *enq, cycle: *cycle })
+    JsonEvents::LsuEnq(lsu_enq) => {
+      runner.cycle = lsu_enq.cycle;
+      runner.update_lsu_idx(lsu_enq)
     }
-    JsonEvents::VrfWrite { issue_idx, vrf_idx, mask, data, cycle } => {
-      runner.cycle = *cycle;
-      runner.peek_vrf_write(&VrfWriteEvent {
-        issue_idx: *issue_idx,
-        vrf_idx: *vrf_idx,
-        mask: mask.clone(),
-        data: data.clone(),
-        cycle: *cycle,
-      })
+    JsonEvents::VrfWrite(vrf_write) => {
+      runner.cycle = vrf_write.cycle;
+      runner.peek_vrf_write(vrf_write)
     }
-    JsonEvents::CheckRd { data, issue_idx, cycle } => {
-      runner.cycle = *cycle;
-      runner.check_rd(&CheckRdEvent { data: *data, issue_idx: *issue_idx, cycle: *cycle })
+    JsonEvents::CheckRd(check_rd) => {
+      runner.cycle = check_rd.cycle;
+      runner.check_rd(check_rd)
+    }
+    JsonEvents::VrfScoreboard(vrf_scoreboard) => {
+      runner.cycle = vrf_scoreboard.cycle;
+      runner.vrf_scoreboard(vrf_scoreboard)
+    }
+    JsonEvents::VrfSnapshot(vrf_snapshot) => {
+      // compared against retired instructions by the reproducer tracker
+      runner.cycle = vrf_snapshot.cycle;
+      Ok(())
     }
-    JsonEvents::VrfScoreboard { count, issue_idx, cycle } => {
+    JsonEvents::FinalState(FinalStateEvent { vrf, xrf, frf, cycle }) => {
       runner.cycle = *cycle;
-      runner.vrf_scoreboard(&VrfScoreboardEvent {
-        count: *count,
-        issue_idx: *issue_idx,
-        cycle: *cycle,
-      })
+      check_final_state(runner, *cycle, vrf, xrf, frf)
     }
   }
 }
@@ -0,0 +1,166 @@
+//! Checks of synthetic event logs against scripted spike events
+
+use std::panic::{self, AssertUnwindSafe};
+
+use anyhow::anyhow;
+use spike_rs::model::ScriptedModel;
+use spike_rs::runner::SpikeRunner;
+use spike_rs::spike_event::SpikeEvent;
+
+use super::difftest;
+use super::json_events::JsonEvents;
+use crate::reproducer::panic_message;
+use crate::util::JsonReader;
+
+const VLEN: u32 = 128;
+const DLEN: u32 = 256;
+
+// offset of v1 in the vrf
+const V1: usize = VLEN as usize / 8;
+
+fn vadd(pc: u64) -> SpikeEvent {
+  // vadd.vv v1, v2, v3
+  SpikeEvent::scripted(pc, 0x022180d7, "vadd.vv v1, v2, v3")
+}
+
+fn addi(pc: u64, rd: u32, bits: u32) -> SpikeEvent {
+  // addi rd, rd, 1
+  let inst_bits = 0x00100013 | (rd << 15) | (rd << 7);
+  SpikeEvent::scripted(pc, inst_bits, "addi").with_rd_write(rd, bits)
+}
+
+fn fadd(pc: u64, rd: u32, bits: u32) -> SpikeEvent {
+  // fadd.s rd, rd, rd
+  let inst_bits = 0x00000053 | (rd << 20) | (rd << 15) | (rd << 7);
+  SpikeEvent::scripted(pc, inst_bits, "fadd.s").with_fd_write(rd, bits)
+}
+
+fn runner(events: Vec<SpikeEvent>) -> SpikeRunner {
+  SpikeRunner::with_model(Box::new(ScriptedModel::new(events, VLEN)), VLEN, DLEN)
+}
+
+/// Check the events of a log in order, a failed assertion is returned as an error
+fn check_log(runner: &mut SpikeRunner, log: &str) -> anyhow::Result<()> {
+  let log: String = log.lines().map(|line| format!("{}\n", line.trim())).collect();
+  let mut reader = JsonReader::new(log.as_bytes(), "t1rocketemu");
+  while let Some(event) = reader.next_event::<JsonEvents>()? {
+    panic::catch_unwind(AssertUnwindSafe(|| difftest::diff(runner, &event)))
+      .map_err(|panic| anyhow!(panic_message(&panic)))??;
+  }
+  Ok(())
+}
+
+#[test]
+fn reg_writes_in_program_order() {
+  let mut ordered = runner(vec![addi(0x1000, 1, 5), addi(0x1004, 2, 7)]);
+  check_log(
+    &mut ordered,
+    r#"
+    {"event":"RegWrite","idx":1,"data":"5","cycle":1}
+    {"event":"RegWrite","idx":2,"data":"7","cycle":2}
+    "#,
+  )
+  .unwrap();
+
+  let mut reordered = runner(vec![addi(0x1000, 1, 5), addi(0x1004, 2, 7)]);
+  let error = check_log(
+    &mut reordered,
+    r#"{"event":"RegWrite","idx":2,"data":"7","cycle":1}"#,
+  )
+  .unwrap_err();
+  assert!(error.to_string().contains("rtl idx=2; se idx=1"), "{error}");
+}
+
+#[test]
+fn reg_write_wait_checked_by_later_write() {
+  let mut runner = runner(vec![addi(0x1000, 1, 5), addi(0x1004, 2, 7)]);
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"RegWriteWait","idx":1,"cycle":1}
+    {"event":"RegWrite","idx":2,"data":"7","cycle":2}
+    "#,
+  )
+  .unwrap();
+  assert_eq!(runner.rf_board[1], Some(5));
+
+  let error = check_log(
+    &mut runner,
+    r#"{"event":"RegWrite","idx":1,"data":"6","cycle":3}"#,
+  )
+  .unwrap_err();
+  assert!(error.to_string().contains("board data=0x5"), "{error}");
+}
+
+#[test]
+fn issue_and_reg_writes_interleave() {
+  // vector and float instructions stepped over are queued for their own events
+  let mut runner = runner(vec![
+    fadd(0x1000, 3, 0x3f800000),
+    vadd(0x1004).with_vrf_write(V1, 0x11, true),
+    addi(0x1008, 1, 5),
+  ]);
+  check_log(
+    &mut runner,
+    r#"
+    {"event":"RegWrite","idx":1,"data":"5","cycle":1}
+    {"event":"Issue","idx":0,"cycle":2}
+    {"event":"FregWrite","idx":3,"data":"3f800000","cycle":3}
+    {"event":"VrfWrite","issue_idx":0,"vrf_idx":16,"mask":"1","data":"11","cycle":4}
+    {"event":"VrfScoreboard","count":1,"issue_idx":0,"cycle":5}
+    "#,
+  )
+  .unwrap();
+  assert!(runner.commit_queue.is_empty());
+  assert!(runner.vector_queue.is_empty());
+  assert!(runner.float_queue.is_empty());
+}
+
+#[test]
+fn final_state_compares_scalar_registers() {
+  let script = || {
+    vec![
+      addi(0x1000, 1, 5),
+      fadd(0x1004, 3, 0x3f800000),
+      // sw a1, 0(a0)
+      SpikeEvent::scripted(0x1008, 0x00b52023, "sw a1, 0(a0)").with_exit(),
+    ]
+  };
+  // in the format of the TestBench, every register as a zero padded hex string
+  let final_state = |x1: u32| {
+    let regs = |values: &[(usize, u32)]| {
+      let mut regs = [0u32; 32];
+      for &(idx, value) in values {
+        regs[idx] = value;
+      }
+      regs.iter().map(|reg| format!(r#""{reg:08x}""#)).collect::<Vec<_>>().join(",")
+    };
+    format!(
+      r#"{{"event":"FinalState","vrf":"{}","xrf":[{}],"frf":[{}],"cycle":4}}"#,
+      "00".repeat(V1 * 32),
+      regs(&[(1, x1)]),
+      regs(&[(3, 0x3f800000)]),
+    )
+  };
+  let log = |x1: u32| {
+    format!(
+      r#"
+      {{"event":"RegWrite","idx":1,"data":"5","cycle":1}}
+      {{"event":"FregWrite","idx":3,"data":"3f800000","cycle":2}}
+      {}
+      "#,
+      final_state(x1)
+    )
+  };
+
+  let mut matched = runner(script());
+  check_log(&mut matched, &log(5)).unwrap();
+  assert!(matched.exited);
+
+  let mut mismatched = runner(script());
+  let error = check_log(&mut mismatched, &log(6)).unwrap_err();
+  assert!(
+    error.to_string().contains("x1 rtl=0x00000006 spike=0x00000005"),
+    "{error}"
+  );
+}
@@ -1,16 +1,33 @@
-use std::io::BufRead;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::panic;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, RecvError, TryRecvError, sync_channel};
+use std::thread::{self, JoinHandle, sleep};
+use std::time::{Duration, Instant};
 
-use anyhow::Context as _;
+use anyhow::{Context as _, bail};
 use serde::Deserialize;
+use sim_schema::event::EventLogHeader;
+
+use crate::binary::{self, BinaryEvent, BinaryReader};
 
 pub struct JsonReader<R: BufRead> {
   row: usize,
   reader: R,
+  flavor: String,
+  /// schema version given by the header line, logs without it are version 1
+  version: u32,
 }
 
 impl<R: BufRead> JsonReader<R> {
-  pub fn new(reader: R) -> Self {
-    JsonReader { row: 0, reader }
+  pub fn new(reader: R, flavor: &str) -> Self {
+    JsonReader {
+      row: 0,
+      reader,
+      flavor: flavor.to_string(),
+      version: 1,
+    }
   }
 
   pub fn next_event<EventType>(&mut self) -> anyhow::Result<Option<EventType>>
@@ -29,10 +46,264 @@ impl<R: BufRead> JsonReader<R> {
         continue;
       }
 
-      let event: EventType = serde_json::from_str(&line)
-        .with_context(|| format!("json parsing error at row {}", self.row))?;
+      if EventLogHeader::is_header(&line) {
+        let header: EventLogHeader = serde_json::from_str(&line)
+          .with_context(|| format!("invalid event log header at row {}", self.row))?;
+        header.check(&self.flavor)?;
+        self.version = header.version;
+        continue;
+      }
+
+      let event: EventType = serde_json::from_str(&line).with_context(|| {
+        format!(
+          "json parsing error at row {} (event log schema version {})",
+          self.row, self.version
+        )
+      })?;
 
       return Ok(Some(event));
     }
   }
 }
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+fn is_compressed(head: &[u8]) -> bool {
+  head.starts_with(GZIP_MAGIC) || head.starts_with(ZSTD_MAGIC)
+}
+
+/// Open a (possibly compressed) file for reading,
+/// compression is detected by magic number
+pub fn open_decompressed(path: &Path) -> anyhow::Result<Box<dyn BufRead + Send>> {
+  let file = File::open(path).with_context(|| format!("in open {path:?}"))?;
+  decompress(Box::new(BufReader::new(file)))
+}
+
+fn decompress(mut reader: Box<dyn BufRead + Send>) -> anyhow::Result<Box<dyn BufRead + Send>> {
+  let head = reader.fill_buf()?;
+
+  let reader: Box<dyn BufRead + Send> = if head.starts_with(GZIP_MAGIC) {
+    Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+  } else if head.starts_with(ZSTD_MAGIC) {
+    Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+  } else {
+    Box::new(reader)
+  };
+
+  Ok(reader)
+}
+
+/// A file for writing, compressed according to its extension
+/// (`.gz`, `.zst` or `.zstd`). `finish` must be called to write the end
+/// of the stream, errors on drop are lost.
+pub enum CompressedWriter {
+  Plain(BufWriter<File>),
+  Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+  Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl CompressedWriter {
+  pub fn create(path: &Path) -> anyhow::Result<Self> {
+    let file = BufWriter::new(File::create(path).with_context(|| format!("in create {path:?}"))?);
+
+    let writer = match path.extension().and_then(|ext| ext.to_str()) {
+      Some("gz") => CompressedWriter::Gzip(flate2::write::GzEncoder::new(
+        file,
+        flate2::Compression::default(),
+      )),
+      Some("zst" | "zstd") => CompressedWriter::Zstd(zstd::Encoder::new(file, 0)?),
+      _ => CompressedWriter::Plain(file),
+    };
+
+    Ok(writer)
+  }
+
+  /// Write the end of the compressed stream and flush the file
+  pub fn finish(self) -> io::Result<()> {
+    let mut file = match self {
+      CompressedWriter::Plain(file) => file,
+      CompressedWriter::Gzip(encoder) => encoder.finish()?,
+      CompressedWriter::Zstd(encoder) => encoder.finish()?,
+    };
+    file.flush()
+  }
+}
+
+impl Write for CompressedWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      CompressedWriter::Plain(file) => file.write(buf),
+      CompressedWriter::Gzip(encoder) => encoder.write(buf),
+      CompressedWriter::Zstd(encoder) => encoder.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      CompressedWriter::Plain(file) => file.flush(),
+      CompressedWriter::Gzip(encoder) => encoder.flush(),
+      CompressedWriter::Zstd(encoder) => encoder.flush(),
+    }
+  }
+}
+
+/// Reader of a file which is still being written.
+///
+/// At end of file, it polls for new data instead of returning EOF,
+/// until no data arrives within `idle_timeout`.
+pub struct TailReader<R: Read> {
+  inner: R,
+  idle_timeout: Duration,
+}
+
+impl<R: Read> TailReader<R> {
+  const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+  pub fn new(inner: R, idle_timeout: Duration) -> Self {
+    TailReader { inner, idle_timeout }
+  }
+}
+
+impl<R: Read> Read for TailReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let idle_since = Instant::now();
+    loop {
+      let n = self.inner.read(buf)?;
+      if n > 0 || buf.is_empty() || idle_since.elapsed() >= self.idle_timeout {
+        return Ok(n);
+      }
+      sleep(Self::POLL_INTERVAL);
+    }
+  }
+}
+
+/// Reader of RTL event files, accepts json lines or binary events,
+/// either of which may be compressed by gzip or zstd
+pub enum EventReader {
+  Json(JsonReader<Box<dyn BufRead + Send>>),
+  Binary(BinaryReader<Box<dyn BufRead + Send>>),
+}
+
+impl EventReader {
+  /// `path` is '-' for stdin. With `follow`, the file is tailed while being written,
+  /// note that a FIFO needs no `follow` since it blocks until the writer closes it.
+  pub fn open(path: &Path, flavor: &str, follow: Option<Duration>) -> anyhow::Result<Self> {
+    let mut reader: Box<dyn BufRead + Send> = if path == Path::new("-") {
+      Box::new(BufReader::new(io::stdin()))
+    } else {
+      let file = File::open(path).with_context(|| format!("in open {path:?}"))?;
+      match follow {
+        Some(idle_timeout) => Box::new(BufReader::new(TailReader::new(file, idle_timeout))),
+        None => Box::new(BufReader::new(file)),
+      }
+    };
+
+    if follow.is_some() && is_compressed(reader.fill_buf()?) {
+      bail!("following a compressed event file is not supported");
+    }
+    let mut reader = decompress(reader)?;
+
+    if reader.fill_buf()?.starts_with(binary::MAGIC) {
+      Ok(EventReader::Binary(BinaryReader::new(reader, flavor)?))
+    } else {
+      Ok(EventReader::Json(JsonReader::new(reader, flavor)))
+    }
+  }
+
+  pub fn next_event<EventType>(&mut self) -> anyhow::Result<Option<EventType>>
+  where
+    EventType: for<'a> Deserialize<'a> + BinaryEvent,
+  {
+    match self {
+      EventReader::Json(reader) => reader.next_event(),
+      EventReader::Binary(reader) => reader.next_event(),
+    }
+  }
+}
+
+/// Decodes events on a separate thread, feeding the checking loop through a bounded channel
+pub struct EventPipeline<EventType> {
+  receiver: Receiver<anyhow::Result<EventType>>,
+  decoder: Option<JoinHandle<()>>,
+}
+
+impl<EventType> EventPipeline<EventType>
+where
+  EventType: for<'a> Deserialize<'a> + BinaryEvent + Send + 'static,
+{
+  pub fn spawn(mut reader: EventReader, capacity: usize) -> Self {
+    let (sender, receiver) = sync_channel(capacity);
+    let decoder = thread::spawn(move || {
+      loop {
+        let event = match reader.next_event::<EventType>() {
+          Ok(Some(event)) => Ok(event),
+          Ok(None) => return,
+          Err(e) => Err(e),
+        };
+        let is_err = event.is_err();
+        // the checker has stopped if the channel is disconnected
+        if sender.send(event).is_err() || is_err {
+          return;
+        }
+      }
+    });
+
+    EventPipeline { receiver, decoder: Some(decoder) }
+  }
+
+  /// The next event without blocking, None if no event is decoded yet
+  pub fn try_next_event(&mut self) -> Option<anyhow::Result<Option<EventType>>> {
+    match self.receiver.try_recv() {
+      Ok(event) => Some(event.map(Some)),
+      Err(TryRecvError::Empty) => None,
+      Err(TryRecvError::Disconnected) => {
+        self.finish();
+        Some(Ok(None))
+      }
+    }
+  }
+
+  /// The next event, blocks until it is decoded
+  pub fn next_event(&mut self) -> anyhow::Result<Option<EventType>> {
+    match self.receiver.recv() {
+      Ok(event) => event.map(Some),
+      Err(RecvError) => {
+        self.finish();
+        Ok(None)
+      }
+    }
+  }
+
+  // the decoder has quit, forward its panic if any
+  fn finish(&mut self) {
+    if let Some(decoder) = self.decoder.take()
+      && let Err(panic) = decoder.join()
+    {
+      panic::resume_unwind(panic);
+    }
+  }
+}
+
+/// Convert a json lines event file into binary events,
+/// the output is compressed according to its extension
+pub fn convert_to_binary<EventType>(
+  input: &Path,
+  output: &Path,
+  flavor: &str,
+) -> anyhow::Result<u64>
+where
+  EventType: for<'a> Deserialize<'a> + BinaryEvent,
+{
+  let mut reader = JsonReader::new(open_decompressed(input)?, flavor);
+  let mut writer = binary::BinaryWriter::new(CompressedWriter::create(output)?, flavor)?;
+
+  let mut event_count = 0;
+  while let Some(event) = reader.next_event::<EventType>()? {
+    event_count += 1;
+    writer.write_event(&event)?;
+  }
+  writer.into_inner().finish().with_context(|| format!("in write {output:?}"))?;
+
+  Ok(event_count)
+}
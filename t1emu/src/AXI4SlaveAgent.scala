@@ -6,20 +6,35 @@ package org.chipsalliance.t1.t1emu.dpi
 // TODO: upstream to AMBA as VIP
 import chisel3._
 import chisel3.util.circt.dpi.{RawClockedNonVoidFunctionCall, RawClockedVoidFunctionCall}
-import chisel3.util.{isPow2, log2Ceil}
+import chisel3.util.{isPow2, log2Ceil, Queue}
+import chisel3.experimental.dataview._
 import org.chipsalliance.amba.axi4.bundle.{
+  AR,
   ARChannel,
   ARFlowControl,
+  AW,
   AWChannel,
   AWFlowControl,
   AXI4BundleParameter,
+  AXI4ChiselBundle,
+  AXI4ROIrrevocable,
   AXI4ROIrrevocableVerilog,
+  AXI4RWIrrevocable,
   AXI4RWIrrevocableVerilog,
+  AXI4WOIrrevocable,
   AXI4WOIrrevocableVerilog,
+  B,
   BChannel,
   BFlowControl,
+  HasAR,
+  HasAW,
+  HasB,
+  HasR,
+  HasW,
+  R,
   RChannel,
   RFlowControl,
+  W,
   WChannel,
   WFlowControl
 }
@@ -32,13 +47,15 @@ case class AXI4SlaveAgentParameter(
   writePayloadSize: Int)
 
 class AXI4SlaveAgentInterface(parameter: AXI4SlaveAgentParameter) extends Bundle {
-  val clock:     Clock = Input(Clock())
-  val reset:     Reset = Input(Reset())
-  val channelId: UInt  = Input(Const(UInt(64.W)))
+  val clock:      Clock = Input(Clock())
+  val reset:      Reset = Input(Reset())
+  val channelId:  UInt  = Input(Const(UInt(64.W)))
+  // id of the testbench instance given by the DPI library
+  val instanceId: UInt  = Input(UInt(32.W))
   // don't issue read DPI
-  val gateRead:  Bool  = Input(Bool())
+  val gateRead:   Bool  = Input(Bool())
   // don't issue write DPI
-  val gateWrite: Bool  = Input(Bool())
+  val gateWrite:  Bool  = Input(Bool())
   val channel = Flipped(
     org.chipsalliance.amba.axi4.bundle.verilog.irrevocable(parameter.axiParameter)
   )
@@ -50,7 +67,6 @@ class WritePayload(length: Int, dataWidth: Int) extends Bundle {
   val strb = Vec(length, UInt(math.max(8, dataWidth / 8).W))
 }
 
-// TODO: consider adding the latency of the read transaction
 class ReadPayload(length: Int, dataWidth: Int) extends Bundle {
   val data = Vec(length, UInt(dataWidth.W))
 }
@@ -59,185 +75,164 @@ class ReadPayload(length: Int, dataWidth: Int) extends Bundle {
 class AXI4SlaveAgent(parameter: AXI4SlaveAgentParameter)
     extends FixedIORawModule[AXI4SlaveAgentInterface](new AXI4SlaveAgentInterface(parameter)) {
   dontTouch(io)
+  val invClock = (~io.clock.asBool).asClock;
+
   io.channel match {
-    case channel: AXI4RWIrrevocableVerilog =>
-      new WriteManager(channel)
-      new ReadManager(channel)
-    case channel: AXI4ROIrrevocableVerilog =>
-      new ReadManager(channel)
-    case channel: AXI4WOIrrevocableVerilog =>
-      new WriteManager(channel)
+    case channel: AXI4RWIrrevocableVerilog => {
+      val view = channel.viewAs[AXI4RWIrrevocable](
+        implicitly,
+        AXI4RWIrrevocableVerilog.viewChisel,
+        implicitly
+      )
+      new ReadManager(view)
+      new WriteManager(view)
+    }
+    case channel: AXI4ROIrrevocableVerilog => {
+      val view = channel.viewAs[AXI4ROIrrevocable](
+        implicitly,
+        AXI4ROIrrevocableVerilog.viewChisel,
+        implicitly
+      )
+      new ReadManager(view)
+    }
+    case channel: AXI4WOIrrevocableVerilog => {
+      val view = channel.viewAs[AXI4WOIrrevocable](
+        implicitly,
+        AXI4WOIrrevocableVerilog.viewChisel,
+        implicitly
+      )
+      new WriteManager(view)
+    }
+  }
+
+  RawClockedVoidFunctionCall(s"axi_tick")(
+    io.clock,
+    true.B,
+    io.instanceId,
+    io.reset.asTypeOf(UInt(8.W))
+  )
+
+  /// Widen a wire to make all DPI calls' types uniform
+  def widen(wire: UInt, target: Int): UInt = {
+    require(wire.isWidthKnown)
+    require(wire.getWidth <= target)
+    wire.asTypeOf(UInt(target.W))
   }
 
-  private class WriteManager(
-    channel: AWChannel with AWFlowControl with WChannel with WFlowControl with BChannel with BFlowControl) {
+  private class WriteManager(channel: HasAW with HasW with HasB) {
     withClockAndReset(io.clock, io.reset) {
+      val awqueue = Module(new Queue(new AW(parameter.axiParameter), 2))
+      val wqueue  = Module(new Queue(new W(parameter.axiParameter), 2))
+      val bqueue  = Module(new Queue(new B(parameter.axiParameter), 2))
+
+      awqueue.io.enq <> channel.aw
+      wqueue.io.enq <> channel.w
+      bqueue.io.deq <> channel.b
 
-      /** There is an aw in the register. */
-      val awIssued = RegInit(false.B)
-
-      /** There is a w in the register. */
-      val last = RegInit(false.B)
-
-      /** memory to store the write payload
-        * @todo
-        *   limit the payload size based on the RTL configuration.
-        */
-      val writePayload =
-        RegInit(0.U.asTypeOf(new WritePayload(parameter.writePayloadSize, parameter.axiParameter.dataWidth)))
-
-      /** AWID, latch at AW fire, used at B fire. */
-      val awid     = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWID)))
-      val awaddr   = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWADDR)))
-      val awlen    = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWLEN)))
-      val awsize   = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWSIZE)))
-      val awburst  = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWBURST)))
-      val awlock   = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWLOCK)))
-      val awcache  = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWCACHE)))
-      val awprot   = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWPROT)))
-      val awqos    = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWQOS)))
-      val awregion = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWREGION)))
-      val awuser   = RegInit(0.U.asTypeOf(chiselTypeOf(channel.AWUSER)))
-
-      /** index the payload, used to write [[writePayload]] */
-      val writeIdx  = RegInit(0.U.asTypeOf(UInt(8.W)))
-      val bFire     = channel.BREADY && channel.BVALID
-      val awFire    = channel.AWREADY && channel.AWVALID
-      val wLastFire = channel.WVALID && channel.WREADY && channel.WLAST
-      val awExist   = channel.AWVALID || awIssued
-      val wExist    = channel.WVALID && channel.WLAST || last
+      // Invoke DPI at negedge
+      // NOTICE: results from these AXI calls CANNOT directly write any outside reg. Only write wires (e.g. here, only writes queue IO)
 
       // AW
-      channel.AWREADY := !awIssued || (wExist && channel.BREADY)
-      when(channel.AWREADY && channel.AWVALID) {
-        awid     := channel.AWID
-        awaddr   := channel.AWADDR
-        awlen    := channel.AWLEN
-        awsize   := channel.AWSIZE
-        awburst  := channel.AWBURST
-        awlock   := channel.AWLOCK
-        awcache  := channel.AWCACHE
-        awprot   := channel.AWPROT
-        awqos    := channel.AWQOS
-        awregion := channel.AWREGION
-        awuser   := channel.AWUSER
-      }
-      when(awFire ^ bFire) {
-        awIssued := awFire
-      }
+      val awRet = RawClockedNonVoidFunctionCall(s"axi_push_AW", Bool())(
+        invClock,
+        awqueue.io.deq.valid,
+        io.instanceId,
+        io.reset.asTypeOf(UInt(8.W)),
+        io.channelId,
+        parameter.axiParameter.dataWidth.U(64.W),
+        awqueue.io.deq.bits.id.asTypeOf(UInt(64.W)),
+        awqueue.io.deq.bits.addr.asTypeOf(UInt(64.W)),
+        awqueue.io.deq.bits.size.asTypeOf(UInt(64.W)),
+        awqueue.io.deq.bits.len.asTypeOf(UInt(64.W)),
+        awqueue.io.deq.bits.user.asTypeOf(UInt(64.W))
+      )
+      awqueue.io.deq.ready := awRet
 
       // W
-      val writePayloadUpdate = WireDefault(writePayload)
-      channel.WREADY := !last || (awExist && channel.BREADY)
-      when(channel.WVALID && channel.WREADY) {
-        writePayload.data(writeIdx)       := channel.WDATA
-        writePayloadUpdate.data(writeIdx) := channel.WDATA
-        writePayload.strb(writeIdx)       := channel.WSTRB.pad(writePayload.strb.getWidth)
-        writePayloadUpdate.strb(writeIdx) := channel.WSTRB.pad(writePayload.strb.getWidth)
-        writeIdx                          := writeIdx + 1.U
-        when(channel.WLAST) {
-          writeIdx := 0.U
-        }
-      }
-      when(wLastFire ^ bFire) {
-        last := wLastFire
-      }
+      val wRet = RawClockedNonVoidFunctionCall(s"axi_push_W", Bool())(
+        invClock,
+        wqueue.io.deq.valid,
+        io.instanceId,
+        io.reset.asTypeOf(UInt(8.W)),
+        io.channelId,
+        parameter.axiParameter.dataWidth.U(64.W),
+        widen(wqueue.io.deq.bits.data, 1024),
+        widen(wqueue.io.deq.bits.strb, 128),
+        wqueue.io.deq.bits.last.asTypeOf(UInt(8.W))
+      )
+      wqueue.io.deq.ready := wRet
 
-      // B
-      channel.BVALID := awExist && wExist
-      channel.BID    := Mux(awIssued, awid, channel.AWID)
-      channel.BRESP  := 0.U(2.W) // OK
-      channel.BUSER  := DontCare
-      // TODO: add latency to the write transaction reply
-      when(channel.BVALID && channel.BREADY) {
-        RawClockedVoidFunctionCall(s"axi_write_${parameter.name}")(
-          io.clock,
-          when.cond && !io.gateWrite,
-          io.channelId,
-          // handle AW and W at same beat.
-          Mux(awIssued, awid.asTypeOf(UInt(64.W)), channel.AWID),
-          Mux(awIssued, awaddr.asTypeOf(UInt(64.W)), channel.AWADDR),
-          Mux(awIssued, awlen.asTypeOf(UInt(64.W)), channel.AWLEN),
-          Mux(awIssued, awsize.asTypeOf(UInt(64.W)), channel.AWSIZE),
-          Mux(awIssued, awburst.asTypeOf(UInt(64.W)), channel.AWBURST),
-          Mux(awIssued, awlock.asTypeOf(UInt(64.W)), channel.AWLOCK),
-          Mux(awIssued, awcache.asTypeOf(UInt(64.W)), channel.AWCACHE),
-          Mux(awIssued, awprot.asTypeOf(UInt(64.W)), channel.AWPROT),
-          Mux(awIssued, awqos.asTypeOf(UInt(64.W)), channel.AWQOS),
-          Mux(awIssued, awregion.asTypeOf(UInt(64.W)), channel.AWREGION),
-          writePayloadUpdate
-        )
+      class BBundle extends Bundle {
+        val user     = UInt(32.W)
+        val id       = UInt(16.W)
+        val _padding = UInt(8.W)
+        val valid    = UInt(8.W)
       }
+
+      val bRet = RawClockedNonVoidFunctionCall(s"axi_pop_B", new BBundle())(
+        invClock,
+        bqueue.io.enq.ready,
+        io.instanceId,
+        io.reset.asTypeOf(UInt(64.W)),
+        io.channelId,
+        parameter.axiParameter.dataWidth.U(64.W)
+      )
+      bqueue.io.enq.valid     := bRet.valid
+      bqueue.io.enq.bits.id   := bRet.id
+      bqueue.io.enq.bits.resp := 0.U(2.W)
+      bqueue.io.enq.bits.user := bRet.user
     }
   }
 
-  private class ReadManager(channel: ARChannel with ARFlowControl with RChannel with RFlowControl) {
+  private class ReadManager(channel: HasAR with HasR) {
     withClockAndReset(io.clock, io.reset) {
-      class CAMValue extends Bundle {
-        val arid             = UInt(16.W)
-        val arlen            = UInt(8.W)
-        val readPayload      = new ReadPayload(parameter.readPayloadSize, parameter.axiParameter.dataWidth)
-        val readPayloadIndex = UInt(8.W)
-        val valid            = Bool()
-      }
+      val arqueue = Module(new Queue(new AR(parameter.axiParameter), 2))
+      val rqueue  = Module(new Queue(new R(parameter.axiParameter), 2))
 
-      /** CAM to maintain order of read requests. This is maintained as FIFO. */
-      val cam: Vec[CAMValue] = RegInit(0.U.asTypeOf(Vec(parameter.outstanding, new CAMValue)))
-      require(isPow2(parameter.outstanding), "Need to handle pointers")
-      val arPtr = RegInit(0.U.asTypeOf(UInt(log2Ceil(parameter.outstanding).W)))
-      val rPtr  = RegInit(0.U.asTypeOf(UInt(log2Ceil(parameter.outstanding).W)))
+      arqueue.io.enq <> channel.ar
+      rqueue.io.deq <> channel.r
+
+      // Invoke DPI at negedge
+      // See the notice in WriteManager
 
       // AR
-      channel.ARREADY := !cam(arPtr).valid
-      val arPtrNext: UInt = RegNext(arPtr, 0.U)
-
-      val arFire       = channel.ARREADY && channel.ARVALID
-      val readPlayload = RawClockedNonVoidFunctionCall(
-        s"axi_read_${parameter.name}",
-        new ReadPayload(parameter.readPayloadSize, parameter.axiParameter.dataWidth)
-      )(
-        io.clock,
-        !io.gateRead && arFire,
+      val arRet = RawClockedNonVoidFunctionCall(s"axi_push_AR", Bool())(
+        invClock,
+        arqueue.io.deq.valid,
+        io.instanceId,
+        io.reset.asTypeOf(UInt(8.W)),
         io.channelId,
-        channel.ARID.asTypeOf(UInt(64.W)),
-        channel.ARADDR.asTypeOf(UInt(64.W)),
-        channel.ARLEN.asTypeOf(UInt(64.W)),
-        channel.ARSIZE.asTypeOf(UInt(64.W)),
-        channel.ARBURST.asTypeOf(UInt(64.W)),
-        channel.ARLOCK.asTypeOf(UInt(64.W)),
-        channel.ARCACHE.asTypeOf(UInt(64.W)),
-        channel.ARPROT.asTypeOf(UInt(64.W)),
-        channel.ARQOS.asTypeOf(UInt(64.W)),
-        channel.ARREGION.asTypeOf(UInt(64.W))
+        parameter.axiParameter.dataWidth.U(64.W),
+        arqueue.io.deq.bits.id.asTypeOf(UInt(64.W)),
+        arqueue.io.deq.bits.addr.asTypeOf(UInt(64.W)),
+        arqueue.io.deq.bits.size.asTypeOf(UInt(64.W)),
+        arqueue.io.deq.bits.len.asTypeOf(UInt(64.W)),
+        arqueue.io.deq.bits.user.asTypeOf(UInt(64.W))
       )
-
-      when(arFire) {
-        arPtr := arPtr + 1.U
-      }
-
-      when(RegNext(arFire, false.B)) {
-        cam(arPtrNext).arid             := RegNext(channel.ARID, 0.U)
-        cam(arPtrNext).arlen            := RegNext(channel.ARLEN, 0.U)
-        cam(arPtrNext).readPayload      := readPlayload
-        cam(arPtrNext).readPayloadIndex := 0.U
-        cam(arPtrNext).valid            := true.B
-      }
-
-      // R
-      channel.RVALID := cam(rPtr).valid
-      channel.RID    := cam(rPtr).arid
-      channel.RDATA  := cam(rPtr).readPayload.data(cam(rPtr).readPayloadIndex)
-      channel.RRESP  := 0.U // OK
-      channel.RLAST  := (cam(rPtr).arlen === cam(rPtr).readPayloadIndex) && cam(rPtr).valid
-      channel.RUSER  := DontCare
-      when(channel.RREADY && channel.RVALID) {
-        // increase index
-        cam(rPtr).readPayloadIndex := cam(rPtr).readPayloadIndex + 1.U
-        when(channel.RLAST) {
-          cam(rPtr).valid := false.B
-          rPtr            := rPtr + 1.U
-        }
+      arqueue.io.deq.ready := arRet
+
+      require(parameter.axiParameter.dataWidth <= 1024)
+      class RBundle extends Bundle {
+        val data  = UInt(1024.W)
+        val user  = UInt(32.W)
+        val id    = UInt(16.W)
+        val last  = UInt(8.W)
+        val valid = UInt(8.W)
       }
+      val rRet = RawClockedNonVoidFunctionCall(s"axi_pop_R", new RBundle())(
+        invClock,
+        rqueue.io.enq.ready,
+        io.instanceId,
+        io.reset.asTypeOf(UInt(8.W)),
+        io.channelId,
+        parameter.axiParameter.dataWidth.U(64.W)
+      )
+      rqueue.io.enq.valid     := rRet.valid
+      rqueue.io.enq.bits.id   := rRet.id
+      rqueue.io.enq.bits.last := rRet.last
+      rqueue.io.enq.bits.user := rRet.user
+      rqueue.io.enq.bits.data := rRet.data
+      rqueue.io.enq.bits.resp := 0.U
     }
   }
 }
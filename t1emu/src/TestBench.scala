@@ -13,7 +13,7 @@ import chisel3.util.circt.dpi.{
   RawClockedVoidFunctionCall,
   RawUnclockedNonVoidFunctionCall
 }
-import chisel3.util.{HasExtModuleInline, PopCount, UIntToOH, Valid}
+import chisel3.util.{Cat, HasExtModuleInline, PopCount, UIntToOH, Valid}
 import org.chipsalliance.amba.axi4.bundle._
 import org.chipsalliance.t1.rtl.{T1, T1Parameter}
 import org.chipsalliance.t1.t1emu.dpi._
@@ -51,10 +51,15 @@ class TestBench(val parameter: T1Parameter)
       override def desiredName = "VerbatimModule"
       val clock                = IO(Output(Bool()))
       val reset                = IO(Output(Bool()))
+      val instanceId           = IO(Output(UInt(32.W)))
+      val vrfSnapshot          = IO(Output(Bool()))
+      val finalState           = IO(Output(Bool()))
     }
   )
   def clock                  = verbatimModule.clock.asClock
   def reset                  = verbatimModule.reset
+  // id of this instance given by the DPI library, the first argument of all DPI calls
+  def instanceId             = verbatimModule.instanceId
   override def implicitClock = verbatimModule.clock.asClock
   override def implicitReset = verbatimModule.reset
   val dut: Instance[T1] = SerializableModuleGenerator(classOf[T1], parameter).instance()
@@ -107,7 +112,8 @@ class TestBench(val parameter: T1Parameter)
     doIssue,
     RawClockedNonVoidFunctionCall("issue_vector_instruction", new Issue)(
       clock,
-      doIssue
+      doIssue,
+      instanceId
     ),
     0.U.asTypeOf(new Issue)
   )
@@ -127,10 +133,10 @@ class TestBench(val parameter: T1Parameter)
   retire.vxsat   := dut.io.retire.csr.bits.vxsat
   // TODO:
   //  retire.fflag := dut.io.retire.csr.bits.fflag
-  RawClockedVoidFunctionCall("retire_vector_instruction")(clock, t1Probe.retireValid, retire)
+  RawClockedVoidFunctionCall("retire_vector_instruction")(clock, t1Probe.retireValid, instanceId, retire)
   val dummy = Wire(Bool())
   dummy := false.B
-  RawClockedVoidFunctionCall("retire_vector_mem")(clock, dut.io.retire.mem.valid, dummy)
+  RawClockedVoidFunctionCall("retire_vector_mem")(clock, dut.io.retire.mem.valid, instanceId, dummy)
 
   // Memory Drivers
   Seq(
@@ -157,10 +163,11 @@ class TestBench(val parameter: T1Parameter)
         case io: AXI4RWIrrevocableVerilog => io <> bundle
       }
       agent.io.clock := clock
-      agent.io.reset     := reset
-      agent.io.channelId := index.U
-      agent.io.gateRead  := false.B
-      agent.io.gateWrite := false.B
+      agent.io.reset      := reset
+      agent.io.channelId  := index.U
+      agent.io.instanceId := instanceId
+      agent.io.gateRead   := false.B
+      agent.io.gateWrite  := false.B
     }
 
   // Events for difftest and performance modeling
@@ -193,6 +200,44 @@ class TestBench(val parameter: T1Parameter)
       )
     )
   }
+
+  // shadow of the VRF kept from the VRF write probes, only for the checker.
+  // It holds what is written through the write port of each lane, not the content of the SRAM.
+  val vrfRowsPerReg  = parameter.vLen / parameter.dLen
+  val vrfRowsPerLane = 32 * vrfRowsPerReg
+  val laneBytes      = parameter.datapathWidth / 8
+  val shadowVrf: Seq[Seq[UInt]] = laneProbes.map { lane =>
+    val vrf     = lane.vrfProbe
+    val rows    = Mem(vrfRowsPerLane, Vec(laneBytes, UInt(8.W)))
+    // unwritten rows read as zero, like the VRF of spike
+    val written = RegInit(0.U(vrfRowsPerLane.W))
+    val row     = vrf.requestVd * vrfRowsPerReg.U + vrf.requestOffset
+    val bytes   = vrf.requestData.asTypeOf(Vec(laneBytes, UInt(8.W)))
+    val enables = vrf.requestMask.asBools
+    when(vrf.valid) {
+      // the first write of a row clears the unmasked bytes
+      rows.write(
+        row,
+        VecInit(bytes.zip(enables).map { case (byte, enable) => Mux(enable, byte, 0.U) }),
+        enables.map(_ || !written(row))
+      )
+      written := written | UIntToOH(row, vrfRowsPerLane)
+    }
+    Seq.tabulate(vrfRowsPerLane)(r => Mux(written(r), rows.read(r.U).asUInt, 0.U))
+  }
+  // vrf_idx of a byte is dLen / 8 * row + datapathWidth / 8 * lane, the first byte is the LSB
+  val shadowVrfData: UInt = Cat(Seq.tabulate(vrfRowsPerLane, shadowVrf.size)((r, l) => shadowVrf(l)(r)).flatten.reverse)
+
+  // vrf snapshot each time the vector unit becomes idle, e.g. at a fence.
+  // It is logged one cycle later, when the last writes have landed in the shadow.
+  val becomeIdle = RegNext(t1Probe.idle && !RegNext(t1Probe.idle, true.B), false.B)
+  when(verbatimModule.vrfSnapshot && becomeIdle && t1Probe.idle)(
+    log.printf(cf"""{"event":"VrfSnapshot","data":"${shadowVrfData}%x","cycle":${simulationTime}}\n""")
+  )
+  // final state at exit
+  when(verbatimModule.finalState)(
+    log.printf(cf"""{"event":"FinalState","vrf":"${shadowVrfData}%x","cycle":${simulationTime}}\n""")
+  )
   // memory write from store unit
   when(storeUnitProbe.valid)(
     log.printf(
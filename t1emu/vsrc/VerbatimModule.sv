@@ -48,7 +48,10 @@ module VerbatimModule #(
   parameter string T1_SPIKE_ISA
 )(
   output reg clock,
-  output reg reset
+  output reg reset,
+  output reg vrfSnapshot,
+  output reg [31:0] instanceId,
+  output reg finalState
 );
 
   // This module contains everything we can not represent in Chisel currently,
@@ -59,14 +62,21 @@ module VerbatimModule #(
   //   +t1_wave_path            (required T) path to wave dump file
   //   +t1_timeout              (optional)   max cycle between two V inst retire
   //
+  //   +t1_dev_vrf_snapshot     (optional)   set to 1 to log the VRF each time the vector unit becomes idle
+  //   +t1_dev_final_state      (optional)   set to 1 to log the VRF at exit
+  //
   //   +t1_debug_global_timeout (optional)   max cycle for whole simulation, for debug only
   //   +t1_debug_dump_start     (optional T) cycle when dump starts, by default it's simulation start, for debug only
   //   +t1_debug_dump_end       (optional T) cycle when dump ends, by default is's simulation end, for debug only
+  //
+  //   +help                                 print all plusargs and exit
 
   longint unsigned cycle = 0;
   longint unsigned debug_global_timeout = 0;
   longint unsigned dpi_timeout = 1000000;
   string elf_file;
+  int dev_vrf_snapshot = 0;
+  int dev_final_state = 0;
 `ifdef T1_ENABLE_TRACE
   longint unsigned debug_dump_start = 0;
   longint unsigned debug_dump_end = 0;
@@ -98,16 +108,25 @@ module VerbatimModule #(
   endfunction
 `endif
 
-  import "DPI-C" context function void t1_cosim_init(
+  // t1_cosim_init returns the id of this instance, passed as the first argument of all
+  // the other DPI calls, including the ones of the TestBench
+  import "DPI-C" context function int unsigned t1_cosim_init(
     string elf_file,
     int dlen,
     int lane_width,
     int vlen,
     string spike_isa
   );
-  import "DPI-C" context function void t1_cosim_set_timeout(longint unsigned timeout);
-  import "DPI-C" context function void t1_cosim_final();
-  import "DPI-C" context function byte unsigned t1_cosim_watchdog();
+  import "DPI-C" context function void t1_cosim_set_timeout(int unsigned instance_id, longint unsigned timeout);
+  import "DPI-C" context function void t1_cosim_final(int unsigned instance_id);
+  import "DPI-C" context function byte unsigned t1_cosim_watchdog(int unsigned instance_id);
+
+  // quit successfully, with +t1_dev_final_state the TestBench logs the final state
+  // at the next clock edge, then the simulation finishes
+  task automatic quit();
+    if (dev_final_state != 0) finalState = 1'b1;
+    else $finish;
+  endtask
 
   initial begin
     clock = 1'b0;
@@ -121,11 +140,18 @@ module VerbatimModule #(
     $value$plusargs("t1_elf_file=%s", elf_file);
     $value$plusargs("t1_timeout=%d", dpi_timeout);
     $value$plusargs("t1_debug_global_timeout=%d", debug_global_timeout);
+`ifdef T1_DEV
+    $value$plusargs("t1_dev_vrf_snapshot=%d", dev_vrf_snapshot);
+    $value$plusargs("t1_dev_final_state=%d", dev_final_state);
+`endif
+    vrfSnapshot = dev_vrf_snapshot != 0;
+    finalState = 1'b0;
 
-    if (elf_file.len() == 0) $fatal(1, "+t1_elf_file must be set");
+    // +help is handled in t1_cosim_init
+    if (elf_file.len() == 0 && !$test$plusargs("help")) $fatal(1, "+t1_elf_file must be set");
 
-    t1_cosim_init(elf_file, T1_DLEN, T1_LANE_WIDTH, T1_VLEN, T1_SPIKE_ISA);
-    t1_cosim_set_timeout(dpi_timeout);
+    instanceId = t1_cosim_init(elf_file, T1_DLEN, T1_LANE_WIDTH, T1_VLEN, T1_SPIKE_ISA);
+    t1_cosim_set_timeout(instanceId, dpi_timeout);
 
     __circt_lib_logging::log_open();
 
@@ -144,11 +170,16 @@ module VerbatimModule #(
 
       cycle += 1;
 
+      if (finalState) begin
+        // the final state is logged at this clock edge
+        $finish;
+      end
+
       begin
-        automatic byte unsigned st = t1_cosim_watchdog();
+        automatic byte unsigned st = t1_cosim_watchdog(instanceId);
         if (st == 255) begin
           // quit successfully, only if both DPI and TestBench finish
-          $finish;
+          quit();
         end else if (st == 0) begin
           // continue, do nothing here
         end else begin
@@ -174,7 +205,7 @@ module VerbatimModule #(
 
   final begin
     __circt_lib_logging::log_close();
-    t1_cosim_final();
+    t1_cosim_final(instanceId);
   end
 
   initial #(11) reset = 1'b0;
@@ -47,13 +47,15 @@ case class AXI4SlaveAgentParameter(
   writePayloadSize: Int)
 
 class AXI4SlaveAgentInterface(parameter: AXI4SlaveAgentParameter) extends Bundle {
-  val clock:     Clock = Input(Clock())
-  val reset:     Reset = Input(Reset())
-  val channelId: UInt  = Input(Const(UInt(64.W)))
+  val clock:      Clock = Input(Clock())
+  val reset:      Reset = Input(Reset())
+  val channelId:  UInt  = Input(Const(UInt(64.W)))
+  // id of the testbench instance given by the DPI library
+  val instanceId: UInt  = Input(UInt(32.W))
   // don't issue read DPI
-  val gateRead:  Bool  = Input(Bool())
+  val gateRead:   Bool  = Input(Bool())
   // don't issue write DPI
-  val gateWrite: Bool  = Input(Bool())
+  val gateWrite:  Bool  = Input(Bool())
   val channel = Flipped(
     org.chipsalliance.amba.axi4.bundle.verilog.irrevocable(parameter.axiParameter)
   )
@@ -106,6 +108,7 @@ class AXI4SlaveAgent(parameter: AXI4SlaveAgentParameter)
   RawClockedVoidFunctionCall(s"axi_tick")(
     io.clock,
     true.B,
+    io.instanceId,
     io.reset.asTypeOf(UInt(8.W))
   )
 
@@ -133,6 +136,7 @@ class AXI4SlaveAgent(parameter: AXI4SlaveAgentParameter)
       val awRet = RawClockedNonVoidFunctionCall(s"axi_push_AW", Bool())(
         invClock,
         awqueue.io.deq.valid,
+        io.instanceId,
         io.reset.asTypeOf(UInt(8.W)),
         io.channelId,
         parameter.axiParameter.dataWidth.U(64.W),
@@ -148,6 +152,7 @@ class AXI4SlaveAgent(parameter: AXI4SlaveAgentParameter)
       val wRet = RawClockedNonVoidFunctionCall(s"axi_push_W", Bool())(
         invClock,
         wqueue.io.deq.valid,
+        io.instanceId,
         io.reset.asTypeOf(UInt(8.W)),
         io.channelId,
         parameter.axiParameter.dataWidth.U(64.W),
@@ -167,6 +172,7 @@ class AXI4SlaveAgent(parameter: AXI4SlaveAgentParameter)
       val bRet = RawClockedNonVoidFunctionCall(s"axi_pop_B", new BBundle())(
         invClock,
         bqueue.io.enq.ready,
+        io.instanceId,
         io.reset.asTypeOf(UInt(64.W)),
         io.channelId,
         parameter.axiParameter.dataWidth.U(64.W)
@@ -193,6 +199,7 @@ class AXI4SlaveAgent(parameter: AXI4SlaveAgentParameter)
       val arRet = RawClockedNonVoidFunctionCall(s"axi_push_AR", Bool())(
         invClock,
         arqueue.io.deq.valid,
+        io.instanceId,
         io.reset.asTypeOf(UInt(8.W)),
         io.channelId,
         parameter.axiParameter.dataWidth.U(64.W),
@@ -215,6 +222,7 @@ class AXI4SlaveAgent(parameter: AXI4SlaveAgentParameter)
       val rRet = RawClockedNonVoidFunctionCall(s"axi_pop_R", new RBundle())(
         invClock,
         rqueue.io.enq.ready,
+        io.instanceId,
         io.reset.asTypeOf(UInt(8.W)),
         io.channelId,
         parameter.axiParameter.dataWidth.U(64.W)
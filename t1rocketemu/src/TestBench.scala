@@ -10,7 +10,7 @@ import chisel3.experimental.hierarchy.Instance
 import chisel3.experimental.{ExtModule, SerializableModule, SerializableModuleGenerator}
 import chisel3.properties.{AnyClassType, Class, ClassType, Property}
 import chisel3.util.circt.dpi.{RawClockedNonVoidFunctionCall, RawUnclockedNonVoidFunctionCall}
-import chisel3.util.{HasExtModuleInline, PopCount, UIntToOH, Valid}
+import chisel3.util.{Cat, HasExtModuleInline, PopCount, UIntToOH, Valid}
 import org.chipsalliance.amba.axi4.bundle._
 import org.chipsalliance.t1.t1rocketemu.dpi._
 import org.chipsalliance.t1.tile.{T1RocketTile, T1RocketTileParameter}
@@ -52,11 +52,16 @@ class TestBench(val parameter: T1RocketTileParameter)
       val clock                = IO(Output(Bool()))
       val reset                = IO(Output(Bool()))
       val initFlag             = IO(Output(Bool()))
+      val instanceId           = IO(Output(UInt(32.W)))
+      val vrfSnapshot          = IO(Output(Bool()))
+      val finalState           = IO(Output(Bool()))
       val idle                 = IO(Input(Bool()))
     }
   )
   def clock                  = verbatimModule.clock.asClock
   def reset                  = verbatimModule.reset
+  // id of this instance given by the DPI library, the first argument of all DPI calls
+  def instanceId             = verbatimModule.instanceId
   def initFlag               = verbatimModule.initFlag
   override def implicitClock = verbatimModule.clock.asClock
   override def implicitReset = verbatimModule.reset
@@ -71,12 +76,15 @@ class TestBench(val parameter: T1RocketTileParameter)
   simulationTime := simulationTime + 1.U
 
   // get resetVector from simulator
-  dut.io.resetVector := RawClockedNonVoidFunctionCall("get_resetvector", Const(UInt(64.W)))(clock, initFlag)
+  dut.io.resetVector := RawClockedNonVoidFunctionCall("get_resetvector", Const(UInt(64.W)))(clock, initFlag, instanceId)
+
+  // interrupts from the CLINT of the emulator, bit 0: msip, bit 1: mtip
+  val interrupts: UInt = RawClockedNonVoidFunctionCall("get_interrupts", UInt(8.W))(clock, !reset.asBool, instanceId)
 
   dut.io.hartid   := 0.U
   dut.io.debug    := 0.U
-  dut.io.mtip     := 0.U
-  dut.io.msip     := 0.U
+  dut.io.mtip     := interrupts(1)
+  dut.io.msip     := interrupts(0)
   dut.io.meip     := 0.U
   dut.io.buserror := 0.U
 
@@ -105,10 +113,11 @@ class TestBench(val parameter: T1RocketTileParameter)
         case io: AXI4RWIrrevocableVerilog => io <> bundle
       }
       agent.io.clock := clock
-      agent.io.reset     := reset
-      agent.io.channelId := index.U
-      agent.io.gateRead  := false.B
-      agent.io.gateWrite := false.B
+      agent.io.reset      := reset
+      agent.io.channelId  := index.U
+      agent.io.instanceId := instanceId
+      agent.io.gateRead   := false.B
+      agent.io.gateWrite  := false.B
     }
 
   val instFetchAXI   = dut.io.instructionFetchAXI.viewAs[AXI4ROIrrevocableVerilog]
@@ -127,10 +136,11 @@ class TestBench(val parameter: T1RocketTileParameter)
     case io: AXI4ROIrrevocableVerilog => io <> instFetchAXI
   }
   instFetchAgent.io.clock := clock
-  instFetchAgent.io.reset     := reset
-  instFetchAgent.io.channelId := 2.U
-  instFetchAgent.io.gateRead  := false.B
-  instFetchAgent.io.gateWrite := false.B
+  instFetchAgent.io.reset      := reset
+  instFetchAgent.io.channelId  := 2.U
+  instFetchAgent.io.instanceId := instanceId
+  instFetchAgent.io.gateRead   := false.B
+  instFetchAgent.io.gateWrite  := false.B
 
   val loadStoreAXI   = dut.io.loadStoreAXI.viewAs[AXI4RWIrrevocableVerilog]
   val loadStoreAgent = Module(
@@ -149,10 +159,11 @@ class TestBench(val parameter: T1RocketTileParameter)
     case io: AXI4RWIrrevocableVerilog => io <> loadStoreAXI
   }
   loadStoreAgent.io.clock := clock
-  loadStoreAgent.io.reset     := reset
-  loadStoreAgent.io.channelId := 3.U
-  loadStoreAgent.io.gateRead  := false.B
-  loadStoreAgent.io.gateWrite := false.B
+  loadStoreAgent.io.reset      := reset
+  loadStoreAgent.io.channelId  := 3.U
+  loadStoreAgent.io.instanceId := instanceId
+  loadStoreAgent.io.gateRead   := false.B
+  loadStoreAgent.io.gateWrite  := false.B
 
   // probes
   val t1RocketProbe  = probe.read(dut.io.t1RocketProbe)
@@ -188,19 +199,20 @@ class TestBench(val parameter: T1RocketTileParameter)
     )
   )
 
-  // [[option]] rocket fpu reg write
-  parameter.fpuParameter.zip(t1RocketProbe.fpuProbe).zip(rocketProbe.fpuScoreboard).map {
+  // shadow of the XRF for the final state
+  val shadowXrf = RegInit(VecInit(Seq.fill(32)(0.U(32.W))))
+  when(rocketProbe.rfWen && rocketProbe.rfWaddr =/= 0.U)(shadowXrf(rocketProbe.rfWaddr) := rocketProbe.rfWdata)
+
+  // [[option]] rocket fpu reg write, gives the shadow of the FRF for the final state
+  val shadowFrf = parameter.fpuParameter.zip(t1RocketProbe.fpuProbe).zip(rocketProbe.fpuScoreboard).map {
     case ((fpuParameter, fpu), fpuScoreboard) => {
-      val fpToIEEE           = Module(
-        new FPToIEEE(
-          FPToIEEEParameter(
-            fpuParameter.useAsyncReset,
-            fpuParameter.xLen,
-            fpuParameter.fLen,
-            fpuParameter.minFLen
-          )
-        )
+      val fpToIEEEParameter  = FPToIEEEParameter(
+        fpuParameter.useAsyncReset,
+        fpuParameter.xLen,
+        fpuParameter.fLen,
+        fpuParameter.minFLen
       )
+      val fpToIEEE           = Module(new FPToIEEE(fpToIEEEParameter))
       val isVectorForLLWrite = RegNext(rocketProbe.vectorWriteFD, false.B)
 
       fpToIEEE.io.clock           := clock
@@ -232,6 +244,17 @@ class TestBench(val parameter: T1RocketTileParameter)
           cf"""{"event":"FregWriteWait","idx":${fpuScoreboard.scoreBoardSetAddress},"cycle":${simulationTime}}\n"""
         )
       }
+
+      // the writes from the vector unit are not logged as FregWrite, but kept in the shadow
+      val frfToIEEE = Module(new FPToIEEE(fpToIEEEParameter))
+      frfToIEEE.io.clock    := clock
+      frfToIEEE.io.reset    := reset
+      frfToIEEE.io.in.valid := fpu.pipeWrite.rfWen || fpu.loadOrVectorWrite.rfWen
+      frfToIEEE.io.in.bits  := fpToIEEE.io.in.bits
+
+      val shadowFrf = RegInit(VecInit(Seq.fill(32)(0.U(32.W))))
+      when(frfToIEEE.io.out.valid)(shadowFrf(rfWaddr) := frfToIEEE.io.out.bits(31, 0))
+      shadowFrf
     }
   }
 
@@ -251,6 +274,48 @@ class TestBench(val parameter: T1RocketTileParameter)
     )
   }
 
+  // shadow of the VRF kept from the VRF write probes, only for the checker.
+  // It holds what is written through the write port of each lane, not the content of the SRAM.
+  val vrfRowsPerReg  = parameter.vLen / parameter.dLen
+  val vrfRowsPerLane = 32 * vrfRowsPerReg
+  val laneBytes      = parameter.t1Parameter.datapathWidth / 8
+  val shadowVrf: Seq[Seq[UInt]] = laneVrfProbes.map { lane =>
+    val rows    = Mem(vrfRowsPerLane, Vec(laneBytes, UInt(8.W)))
+    // unwritten rows read as zero, like the VRF of spike
+    val written = RegInit(0.U(vrfRowsPerLane.W))
+    val row     = lane.requestVd * vrfRowsPerReg.U + lane.requestOffset
+    val bytes   = lane.requestData.asTypeOf(Vec(laneBytes, UInt(8.W)))
+    val enables = lane.requestMask.asBools
+    when(lane.valid) {
+      // the first write of a row clears the unmasked bytes
+      rows.write(
+        row,
+        VecInit(bytes.zip(enables).map { case (byte, enable) => Mux(enable, byte, 0.U) }),
+        enables.map(_ || !written(row))
+      )
+      written := written | UIntToOH(row, vrfRowsPerLane)
+    }
+    Seq.tabulate(vrfRowsPerLane)(r => Mux(written(r), rows.read(r.U).asUInt, 0.U))
+  }
+  // vrf_idx of a byte is dLen / 8 * row + datapathWidth / 8 * lane, the first byte is the LSB
+  val shadowVrfData: UInt = Cat(Seq.tabulate(vrfRowsPerLane, shadowVrf.size)((r, l) => shadowVrf(l)(r)).flatten.reverse)
+
+  // t1 vrf snapshot each time the vector unit becomes idle, e.g. at a fence.
+  // It is logged one cycle later, when the last writes have landed in the shadow.
+  val t1BecomeIdle = RegNext(t1Probe.idle && !RegNext(t1Probe.idle, true.B), false.B)
+  when(verbatimModule.vrfSnapshot && t1BecomeIdle && t1Probe.idle)(
+    log.printf(cf"""{"event":"VrfSnapshot","data":"${shadowVrfData}%x","cycle":${simulationTime}}\n""")
+  )
+
+  // final state at exit, the FRF only if there is a fpu
+  def hexList(regs: Seq[UInt]): Printable = regs.map(reg => cf""""$reg%x"""").reduce(_ + cf"," + _)
+  when(verbatimModule.finalState) {
+    val frf = shadowFrf.map(frf => cf""","frf":[${hexList(frf)}]""").getOrElse(PString(""))
+    log.printf(
+      cf"""{"event":"FinalState","vrf":"${shadowVrfData}%x","xrf":[${hexList(shadowXrf)}]$frf,"cycle":${simulationTime}}\n"""
+    )
+  }
+
   // t1 memory write from store unit
   when(storeUnitProbe.valid)(
     log.printf(
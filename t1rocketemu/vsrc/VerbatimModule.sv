@@ -50,6 +50,9 @@ module VerbatimModule #(
   output reg clock,
   output reg reset,
   output reg initFlag,
+  output reg vrfSnapshot,
+  output reg [31:0] instanceId,
+  output reg finalState,
   input  wire idle
 );
 
@@ -64,9 +67,14 @@ module VerbatimModule #(
   //   +t1_dramsim3_cfg         (required)   path to the dramsim3 configuration.
   //   +t1_dramsim3_path        (optional)   path of the output of dramsim3. If absent, one path under temp is created
   //
+  //   +t1_dev_vrf_snapshot     (optional)   set to 1 to log the VRF each time the vector unit becomes idle
+  //   +t1_dev_final_state      (optional)   set to 1 to log the VRF, XRF and FRF at exit
+  //
   //   +t1_debug_global_timeout (optional)   max cycle for whole simulation, for debug only
   //   +t1_debug_dump_start     (optional T) cycle when dump starts, by default it's simulation start, for debug only
   //   +t1_debug_dump_end       (optional T) cycle when dump ends, by default is's simulation end, for debug only
+  //
+  //   +help                                 print all plusargs and exit
 
   longint unsigned cycle = 0;
   longint unsigned quit_cycle = 0;
@@ -76,6 +84,8 @@ module VerbatimModule #(
   string elf_file;
   string dramsim3_cfg;
   string dramsim3_path;
+  int dev_vrf_snapshot = 0;
+  int dev_final_state = 0;
 `ifdef T1_ENABLE_TRACE
   longint unsigned debug_dump_start = 0;
   longint unsigned debug_dump_end = 0;
@@ -107,7 +117,9 @@ module VerbatimModule #(
   endfunction
 `endif
 
-  import "DPI-C" context function void t1_cosim_init(
+  // t1_cosim_init returns the id of this instance, passed as the first argument of all
+  // the other DPI calls, including the ones of the TestBench
+  import "DPI-C" context function int unsigned t1_cosim_init(
     string elf_file,
     int dlen,
     int lane_width,
@@ -116,10 +128,17 @@ module VerbatimModule #(
     string ds3_cfg,
     string ds3_path
   );
-  import "DPI-C" context function void t1_cosim_set_timeout(longint unsigned timeout);
-  import "DPI-C" context function void t1_cosim_final();
-  import "DPI-C" context function byte unsigned t1_cosim_watchdog();
+  import "DPI-C" context function void t1_cosim_set_timeout(int unsigned instance_id, longint unsigned timeout);
+  import "DPI-C" context function void t1_cosim_final(int unsigned instance_id);
+  import "DPI-C" context function byte unsigned t1_cosim_watchdog(int unsigned instance_id);
   
+  // quit successfully, with +t1_dev_final_state the TestBench logs the final state
+  // at the next clock edge, then the simulation finishes
+  task automatic quit();
+    if (dev_final_state != 0) finalState = 1'b1;
+    else $finish;
+  endtask
+
   initial begin
     clock = 1'b0;
     reset = 1'b1;
@@ -136,11 +155,18 @@ module VerbatimModule #(
     $value$plusargs("t1_timeout_after_quit=%d", timeout_after_quit);
     $value$plusargs("t1_dramsim3_cfg=%s", dramsim3_cfg);
     $value$plusargs("t1_dramsim3_path=%s", dramsim3_path);
+`ifdef T1_DEV
+    $value$plusargs("t1_dev_vrf_snapshot=%d", dev_vrf_snapshot);
+    $value$plusargs("t1_dev_final_state=%d", dev_final_state);
+`endif
+    vrfSnapshot = dev_vrf_snapshot != 0;
+    finalState = 1'b0;
 
-    if (elf_file.len() == 0) $fatal(1, "+t1_elf_file must be set");
+    // +help is handled in t1_cosim_init
+    if (elf_file.len() == 0 && !$test$plusargs("help")) $fatal(1, "+t1_elf_file must be set");
 
-    t1_cosim_init(elf_file, T1_DLEN, T1_LANE_WIDTH, T1_VLEN, T1_SPIKE_ISA, dramsim3_cfg, dramsim3_path);
-    t1_cosim_set_timeout(dpi_timeout);
+    instanceId = t1_cosim_init(elf_file, T1_DLEN, T1_LANE_WIDTH, T1_VLEN, T1_SPIKE_ISA, dramsim3_cfg, dramsim3_path);
+    t1_cosim_set_timeout(instanceId, dpi_timeout);
 
     __circt_lib_logging::log_open();
 
@@ -159,11 +185,16 @@ module VerbatimModule #(
 
       cycle += 1;
 
+      if (finalState) begin
+        // the final state is logged at this clock edge
+        $finish;
+      end
+
       if (quit_cycle != 0) begin
         // cosim already quits
 
         if (idle) begin
-          $finish;
+          quit();
         end else if (cycle > quit_cycle + timeout_after_quit) begin
           // cosim already quits, but TestBench does not become idle
           $fatal(1, "TestBench idle timeout");
@@ -171,12 +202,12 @@ module VerbatimModule #(
       end else begin
         // do not call watchdog if cosim already quit
 
-        automatic byte unsigned st = t1_cosim_watchdog();
+        automatic byte unsigned st = t1_cosim_watchdog(instanceId);
         if (st == 255) begin
           quit_cycle = cycle;
           if (idle) begin
             // quit successfully, only if both DPI and TestBench finish
-            $finish;
+            quit();
           end
         end else if (st == 0) begin
           // continue, do nothing here
@@ -203,7 +234,7 @@ module VerbatimModule #(
 
   final begin
     __circt_lib_logging::log_close();
-    t1_cosim_final();
+    t1_cosim_final(instanceId);
   end
 
   initial #(100) reset = 1'b0;